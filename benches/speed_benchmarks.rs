@@ -1,3 +1,5 @@
+use brainstorm::evaluator::{compare_precision, Evaluator, Precision};
+use brainstorm::features::{FeatureSet, MAX_INPUT_SIZE};
 use brainstorm::search_algorithm::{ModelMode, SearchAlgorithm, SearchOptions, SearchRequest};
 use brainstorm::utils::{
     board_to_tensor, encode_board_features, order_captures, order_moves, HistoryTable,
@@ -9,7 +11,7 @@ use std::path::Path;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, OnceLock};
 use std::time::Duration;
-use tch::{CModule, Device};
+use tch::Device;
 
 const FAST_MODEL_PATH: &str = "models/brainstorm_fast_eval_jit.pt";
 const ACCURATE_MODEL_PATH: &str = "models/brainstorm_accurate_eval_jit.pt";
@@ -22,8 +24,8 @@ const TACTICAL_FEN: &str = "2r2rk1/pp1n1pp1/2p1pn1p/2Pp4/3P4/2N1PN2/PP3PPP/2RR2K
 const ENDGAME_FEN: &str = "8/5pk1/3p1np1/2pPp3/2P1P3/3N1P2/5K1P/8 w - - 0 40";
 
 struct BenchModels {
-    small: Arc<CModule>,
-    large: Arc<CModule>,
+    small: Arc<Evaluator>,
+    large: Arc<Evaluator>,
 }
 
 static MODELS: OnceLock<BenchModels> = OnceLock::new();
@@ -52,9 +54,9 @@ fn load_models() -> &'static BenchModels {
     })
 }
 
-fn load_benchmark_model(label: &str, primary_path: &str, legacy_path: &str) -> CModule {
+fn load_benchmark_model(label: &str, primary_path: &str, legacy_path: &str) -> Evaluator {
     if Path::new(primary_path).exists() {
-        return CModule::load_on_device(primary_path, Device::Cpu).unwrap_or_else(|_| {
+        return Evaluator::load(Path::new(primary_path), Device::Cpu).unwrap_or_else(|_| {
             panic!("failed to load {label} model for benchmarks from `{primary_path}`")
         });
     }
@@ -64,12 +66,12 @@ fn load_benchmark_model(label: &str, primary_path: &str, legacy_path: &str) -> C
             "[bench] deprecated model filename `{}` detected; rename to `{}`",
             legacy_path, primary_path
         );
-        return CModule::load_on_device(legacy_path, Device::Cpu).unwrap_or_else(|_| {
+        return Evaluator::load(Path::new(legacy_path), Device::Cpu).unwrap_or_else(|_| {
             panic!("failed to load {label} model for benchmarks from `{legacy_path}`")
         });
     }
 
-    CModule::load_on_device(primary_path, Device::Cpu).unwrap_or_else(|_| {
+    Evaluator::load(Path::new(primary_path), Device::Cpu).unwrap_or_else(|_| {
         panic!(
             "failed to load {label} model for benchmarks from `{primary_path}` (legacy `{legacy_path}` is also accepted)"
         )
@@ -85,6 +87,12 @@ fn benchmark_feature_encoding(c: &mut Criterion) {
             let mut features = [0_f32; 775];
             b.iter(|| encode_board_features(board, &mut features));
         });
+        group.bench_with_input(BenchmarkId::new("v2", name), board, |b, board| {
+            let mut features = [0_f32; MAX_INPUT_SIZE];
+            b.iter(|| {
+                FeatureSet::V2.encode(board, &mut features);
+            });
+        });
     }
 
     group.finish();
@@ -101,7 +109,15 @@ fn benchmark_move_ordering(c: &mut Criterion) {
             b.iter_batched(
                 || board.generate_moves().to_vec(),
                 |mut moves| {
-                    order_moves(board, &mut moves, None, killers, &history, board.turn());
+                    order_moves(
+                        board,
+                        &mut moves,
+                        None,
+                        killers,
+                        &history,
+                        board.turn(),
+                        None,
+                    );
                 },
                 BatchSize::SmallInput,
             );
@@ -136,6 +152,8 @@ fn benchmark_model_inference(c: &mut Criterion) {
                     let input = board_to_tensor(board).to_device(Device::Cpu);
                     let _ = models
                         .small
+                        .module()
+                        .expect("benchmark models are TorchScript")
                         .forward_ts(&[input])
                         .expect("small model forward should succeed");
                 });
@@ -152,6 +170,8 @@ fn benchmark_model_inference(c: &mut Criterion) {
                     let input = board_to_tensor(board).to_device(Device::Cpu);
                     let _ = models
                         .large
+                        .module()
+                        .expect("benchmark models are TorchScript")
                         .forward_ts(&[input])
                         .expect("large model forward should succeed");
                 });
@@ -164,6 +184,8 @@ fn benchmark_model_inference(c: &mut Criterion) {
         b.iter(|| {
             let _ = models
                 .small
+                .module()
+                .expect("benchmark models are TorchScript")
                 .forward_ts(&[reference_input.shallow_clone()])
                 .expect("small model forward should succeed");
         });
@@ -172,6 +194,8 @@ fn benchmark_model_inference(c: &mut Criterion) {
         b.iter(|| {
             let _ = models
                 .large
+                .module()
+                .expect("benchmark models are TorchScript")
                 .forward_ts(&[reference_input.shallow_clone()])
                 .expect("large model forward should succeed");
         });
@@ -216,14 +240,13 @@ fn benchmark_search(c: &mut Criterion) {
         let history = vec![board.zobrist()];
         let request = SearchRequest {
             max_depth: depth,
-            soft_time_ms: None,
-            hard_time_ms: None,
+            ..SearchRequest::default()
         };
         let options = SearchOptions {
             hash_mb: 64,
             threads: 1,
             model_mode,
-            debug_log: false,
+            ..SearchOptions::default()
         };
 
         group.bench_function(BenchmarkId::from_parameter(name), |b| {
@@ -236,6 +259,77 @@ fn benchmark_search(c: &mut Criterion) {
     group.finish();
 }
 
+/// Compares f32 and int8 inference on the fast model: an accuracy report over the bench
+/// positions and their children, then search speed in nodes per second.
+fn benchmark_precision(c: &mut Criterion) {
+    let reference = Arc::new(load_benchmark_model(
+        "fast",
+        FAST_MODEL_PATH,
+        LEGACY_SMALL_MODEL_PATH,
+    ));
+    let quantized = Arc::new(
+        load_benchmark_model("fast", FAST_MODEL_PATH, LEGACY_SMALL_MODEL_PATH)
+            .with_precision(Precision::Int8, Device::Cpu)
+            .expect("int8 conversion of the fast model should succeed"),
+    );
+
+    let mut boards = Vec::new();
+    for (_, board) in bench_positions() {
+        for mv in board.generate_moves() {
+            let mut child = board.shallow_clone();
+            child.apply_move(mv);
+            boards.push(child);
+        }
+        boards.push(board);
+    }
+    let report = compare_precision(&reference, &quantized, &boards, Device::Cpu);
+    eprintln!(
+        "[bench] int8 vs f32: positions={} mean_abs_error_cp={:.2} max_abs_error_cp={}",
+        report.positions, report.mean_abs_error_cp, report.max_abs_error_cp
+    );
+
+    let mut group = c.benchmark_group("precision_cpu");
+    group.sample_size(10);
+    group.warm_up_time(Duration::from_secs(1));
+    group.measurement_time(Duration::from_secs(10));
+
+    let board = board_from_fen(MIDDLEGAME_FEN);
+    let history = vec![board.zobrist()];
+    let request = SearchRequest {
+        max_depth: 3,
+        ..SearchRequest::default()
+    };
+    let options = SearchOptions {
+        hash_mb: 64,
+        threads: 1,
+        model_mode: ModelMode::Small,
+        ..SearchOptions::default()
+    };
+
+    for (name, evaluator) in [("f32", &reference), ("int8", &quantized)] {
+        let searcher = SearchAlgorithm::new(
+            Arc::clone(evaluator),
+            Arc::clone(evaluator),
+            Device::Cpu,
+            Arc::new(AtomicBool::new(false)),
+        );
+        let result = searcher.search_quiet(&board, request, &options, &history);
+        eprintln!(
+            "[bench] {name} fast_d3_middlegame: nodes={} nps={:.0}",
+            result.nodes,
+            result.nodes as f64 / result.elapsed.as_secs_f64().max(1e-9)
+        );
+
+        group.bench_function(BenchmarkId::new("fast_d3_middlegame", name), |b| {
+            b.iter(|| {
+                let _ = searcher.search_quiet(&board, request, &options, &history);
+            });
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     name = speed_benches;
     config = Criterion::default().warm_up_time(Duration::from_secs(2));
@@ -243,6 +337,7 @@ criterion_group!(
         benchmark_feature_encoding,
         benchmark_move_ordering,
         benchmark_model_inference,
-        benchmark_search
+        benchmark_search,
+        benchmark_precision
 );
 criterion_main!(speed_benches);
@@ -8,6 +8,7 @@ PROFILE="release"
 RUN_CHECK=1
 COPY_BINARY=1
 INSTALL_DEPS=0
+CARGO_FEATURES=()
 
 usage() {
   cat <<'EOF'
@@ -19,6 +20,7 @@ Options:
   --no-check       Skip `cargo check`
   --no-copy        Do not copy built binary to ./brainstorm
   --install-deps   Install Python deps with uv (torch==2.4.0, pygame, python-chess)
+  --embed-models   Embed models/*.pt in the binary (cargo feature `embedded-models`)
   -h, --help       Show this help text
 EOF
 }
@@ -34,6 +36,7 @@ for arg in "$@"; do
     --no-check) RUN_CHECK=0 ;;
     --no-copy) COPY_BINARY=0 ;;
     --install-deps) INSTALL_DEPS=1 ;;
+    --embed-models) CARGO_FEATURES=(--features embedded-models) ;;
     -h|--help)
       usage
       exit 0
@@ -114,16 +117,16 @@ log "Using torch lib dir: $TORCH_LIB"
 
 if [[ "$RUN_CHECK" -eq 1 ]]; then
   log "Running cargo check"
-  cargo check
+  cargo check ${CARGO_FEATURES[@]+"${CARGO_FEATURES[@]}"}
 fi
 
 if [[ "$PROFILE" == "release" ]]; then
   log "Building release binary"
-  cargo build --release
+  cargo build --release ${CARGO_FEATURES[@]+"${CARGO_FEATURES[@]}"}
   BUILT_BIN="${REPO_ROOT}/target/release/brainstorm"
 else
   log "Building debug binary"
-  cargo build
+  cargo build ${CARGO_FEATURES[@]+"${CARGO_FEATURES[@]}"}
   BUILT_BIN="${REPO_ROOT}/target/debug/brainstorm"
 fi
 
@@ -0,0 +1,139 @@
+use crate::search_algorithm::{SearchAlgorithm, SearchOptions, SearchRequest, SearchResult};
+use pleco::Board;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_BENCH_DEPTH: u32 = 6;
+pub const DEFAULT_BENCH_THREADS: usize = 1;
+pub const DEFAULT_BENCH_HASH_MB: usize = 16;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Positions searched by `bench`: the four from `benches/speed_benchmarks.rs` first, then
+/// a spread of openings, tactics and endgames. Changing this list changes the signature.
+pub const BENCH_FENS: [&str; 12] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r2q1rk1/pp1b1ppp/2n1pn2/2bp4/2P5/2NP1NP1/PP2PPBP/R1BQ1RK1 w - - 0 8",
+    "2r2rk1/pp1n1pp1/2p1pn1p/2Pp4/3P4/2N1PN2/PP3PPP/2RR2K1 w - - 0 15",
+    "8/5pk1/3p1np1/2pPp3/2P1P3/3N1P2/5K1P/8 w - - 0 40",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/8/4k3/8/2p5/8/B2K4/8 b - - 0 60",
+    "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - 0 7",
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BenchConfig {
+    pub depth: u32,
+    pub threads: usize,
+    pub hash_mb: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_BENCH_DEPTH,
+            threads: DEFAULT_BENCH_THREADS,
+            hash_mb: DEFAULT_BENCH_HASH_MB,
+        }
+    }
+}
+
+impl BenchConfig {
+    /// Parses the positional `[depth] [threads] [hash]` arguments; missing ones keep their
+    /// defaults.
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut config = Self::default();
+        if let Some(depth) = args.first() {
+            config.depth = parse_arg("depth", depth)?;
+        }
+        if let Some(threads) = args.get(1) {
+            config.threads = parse_arg("threads", threads)?;
+        }
+        if let Some(hash_mb) = args.get(2) {
+            config.hash_mb = parse_arg("hash", hash_mb)?;
+        }
+        if args.len() > 3 {
+            return Err(format!("unexpected bench argument `{}`", args[3]));
+        }
+        Ok(config)
+    }
+}
+
+fn parse_arg<T: std::str::FromStr + Default + PartialEq>(
+    name: &str,
+    value: &str,
+) -> Result<T, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|parsed| *parsed != T::default())
+        .ok_or_else(|| format!("invalid bench {name} `{value}`"))
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub results: Vec<SearchResult>,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// FNV-1a hash of every position's node count and best move. Any functional change to
+    /// the search or evaluation is expected to change it; speedups are not.
+    pub signature: u64,
+}
+
+impl BenchReport {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.elapsed.as_secs_f64().max(1e-9)) as u64
+    }
+}
+
+/// Searches every position (normally `BENCH_FENS`) to `config.depth` from a cleared
+/// searcher, so the node counts depend only on the code and the networks. With more than
+/// one thread the root split makes them vary from run to run.
+pub fn run_bench(
+    searcher: &SearchAlgorithm,
+    options: &SearchOptions,
+    config: BenchConfig,
+    fens: &[&str],
+    mut on_result: impl FnMut(usize, &str, &SearchResult),
+) -> BenchReport {
+    let options = SearchOptions {
+        hash_mb: config.hash_mb,
+        threads: config.threads,
+        ..options.clone()
+    };
+    let request = SearchRequest {
+        max_depth: config.depth,
+        ..SearchRequest::default()
+    };
+
+    let start = Instant::now();
+    let mut results = Vec::with_capacity(fens.len());
+    let mut signature = FNV_OFFSET_BASIS;
+    for (index, fen) in fens.iter().enumerate() {
+        let board = Board::from_fen(fen).expect("bench FENs must be valid");
+        searcher.clear();
+        let result = searcher.search_quiet(&board, request, &options, &[board.zobrist()]);
+        for byte in result
+            .nodes
+            .to_le_bytes()
+            .into_iter()
+            .chain(result.best_move.get_raw().to_le_bytes())
+        {
+            signature = (signature ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+        on_result(index, fen, &result);
+        results.push(result);
+    }
+
+    BenchReport {
+        nodes: results.iter().map(|result| result.nodes).sum(),
+        results,
+        elapsed: start.elapsed(),
+        signature,
+    }
+}
@@ -0,0 +1,330 @@
+use pleco::helper::prelude::{
+    bishop_moves, king_moves, knight_moves, passed_pawn_mask, pawn_attacks_from, queen_moves,
+    rook_moves,
+};
+use pleco::{BitBoard, Board, PieceType, Player, SQ};
+use std::fmt;
+
+pub(crate) const MAX_PHASE: i32 = 24;
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+const DOUBLED_PAWN_CP: i32 = -15;
+const ISOLATED_PAWN_CP: i32 = -12;
+/// Indexed by the rank counted from the pawn's own side.
+const PASSED_PAWN_CP: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+const PAWN_SHIELD_CP: i32 = 10;
+const KING_ZONE_ATTACK_CP: i32 = -8;
+
+// Piece-square tables in centipawns from White's point of view, a8 first so they read like
+// a diagram. Values follow the well-known "simplified evaluation function".
+#[rustfmt::skip]
+const PAWN_PST: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const KNIGHT_PST: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+const BISHOP_PST: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+const ROOK_PST: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+const QUEEN_PST: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+#[rustfmt::skip]
+const KING_MIDDLEGAME_PST: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+#[rustfmt::skip]
+const KING_ENDGAME_PST: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+/// The terms of the classical evaluation, each White minus Black in centipawns.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ClassicalEval {
+    pub material: i32,
+    pub piece_squares: i32,
+    pub mobility: i32,
+    pub king_safety: i32,
+    pub pawn_structure: i32,
+}
+
+impl ClassicalEval {
+    /// Scores `board` term by term.
+    pub fn of(board: &Board) -> Self {
+        let phase = game_phase(board);
+        let mut eval = Self::default();
+        for (player, sign) in [(Player::White, 1), (Player::Black, -1)] {
+            eval.material += sign * material(board, player);
+            eval.piece_squares += sign * piece_squares(board, player, phase);
+            eval.mobility += sign * mobility(board, player);
+            eval.king_safety += sign * king_safety(board, player) * phase / MAX_PHASE;
+            eval.pawn_structure += sign * pawn_structure(board, player);
+        }
+        eval
+    }
+
+    /// Sum of the terms from White's point of view.
+    pub fn total(&self) -> i32 {
+        self.material + self.piece_squares + self.mobility + self.king_safety + self.pawn_structure
+    }
+}
+
+impl fmt::Display for ClassicalEval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "material={} piece_squares={} mobility={} king_safety={} pawn_structure={}",
+            self.material, self.piece_squares, self.mobility, self.king_safety, self.pawn_structure
+        )
+    }
+}
+
+/// Handcrafted evaluation in centipawns from the side to move's point of view. Needs no
+/// network, so it also stands in when the models cannot be loaded.
+pub fn evaluate(board: &Board) -> i32 {
+    let score = ClassicalEval::of(board).total();
+    if board.turn() == Player::Black {
+        -score
+    } else {
+        score
+    }
+}
+
+/// Material in centipawns. The king's value never enters the material count; it only
+/// makes king captures sort last when ordering by the least valuable attacker.
+pub fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::P => 100,
+        PieceType::N => 320,
+        PieceType::B => 330,
+        PieceType::R => 500,
+        PieceType::Q => 900,
+        PieceType::K => 20_000,
+        PieceType::None | PieceType::All => 0,
+    }
+}
+
+/// 24 with all minor and major pieces on the board, 0 with only kings and pawns.
+pub(crate) fn game_phase(board: &Board) -> i32 {
+    let phase: i32 = [
+        (PieceType::N, 1),
+        (PieceType::B, 1),
+        (PieceType::R, 2),
+        (PieceType::Q, 4),
+    ]
+    .iter()
+    .map(|&(piece_type, weight)| {
+        weight * board.piece_bb_both_players(piece_type).count_bits() as i32
+    })
+    .sum();
+    phase.min(MAX_PHASE)
+}
+
+fn material(board: &Board, player: Player) -> i32 {
+    [
+        PieceType::P,
+        PieceType::N,
+        PieceType::B,
+        PieceType::R,
+        PieceType::Q,
+    ]
+    .iter()
+    .map(|&piece_type| piece_value(piece_type) * board.count_piece(player, piece_type) as i32)
+    .sum()
+}
+
+/// Index into the a8-first tables for `sq` as seen by `player`.
+fn pst_index(sq: SQ, player: Player) -> usize {
+    let index = sq.0 as usize;
+    match player {
+        Player::White => index ^ 56,
+        Player::Black => index,
+    }
+}
+
+fn piece_squares(board: &Board, player: Player, phase: i32) -> i32 {
+    let mut score = 0;
+    for (piece_type, table) in [
+        (PieceType::P, &PAWN_PST),
+        (PieceType::N, &KNIGHT_PST),
+        (PieceType::B, &BISHOP_PST),
+        (PieceType::R, &ROOK_PST),
+        (PieceType::Q, &QUEEN_PST),
+    ] {
+        let mut pieces = board.piece_bb(player, piece_type);
+        while let Some(sq) = pieces.pop_some_lsb() {
+            score += table[pst_index(sq, player)];
+        }
+    }
+
+    let king = pst_index(board.king_sq(player), player);
+    score += (KING_MIDDLEGAME_PST[king] * phase + KING_ENDGAME_PST[king] * (MAX_PHASE - phase))
+        / MAX_PHASE;
+    score
+}
+
+/// Pseudo-legal destination squares not occupied by own pieces, weighted per piece.
+fn mobility(board: &Board, player: Player) -> i32 {
+    let occupied = board.occupied();
+    let own = board.get_occupied_player(player);
+    let mut score = 0;
+    for (piece_type, weight) in [
+        (PieceType::N, 4),
+        (PieceType::B, 5),
+        (PieceType::R, 2),
+        (PieceType::Q, 1),
+    ] {
+        let mut pieces = board.piece_bb(player, piece_type);
+        while let Some(sq) = pieces.pop_some_lsb() {
+            let attacks = match piece_type {
+                PieceType::N => knight_moves(sq),
+                PieceType::B => bishop_moves(occupied, sq),
+                PieceType::R => rook_moves(occupied, sq),
+                _ => queen_moves(occupied, sq),
+            };
+            score += weight * (attacks & !own).count_bits() as i32;
+        }
+    }
+    score
+}
+
+/// Pawn shield in front of the king and enemy pressure on the squares around it. Scaled
+/// by the game phase by the caller, since it stops mattering once the queens are gone.
+fn king_safety(board: &Board, player: Player) -> i32 {
+    let king = board.king_sq(player);
+    let zone = king_moves(king) | BitBoard(1 << king.0);
+    let shield = shield_squares(king, player) & board.piece_bb(player, PieceType::P);
+    let mut score = PAWN_SHIELD_CP * shield.count_bits() as i32;
+
+    let enemy = player.other_player();
+    let occupied = board.occupied();
+    for piece_type in [
+        PieceType::P,
+        PieceType::N,
+        PieceType::B,
+        PieceType::R,
+        PieceType::Q,
+    ] {
+        let mut pieces = board.piece_bb(enemy, piece_type);
+        while let Some(sq) = pieces.pop_some_lsb() {
+            let attacks = match piece_type {
+                PieceType::P => pawn_attacks_from(sq, enemy),
+                PieceType::N => knight_moves(sq),
+                PieceType::B => bishop_moves(occupied, sq),
+                PieceType::R => rook_moves(occupied, sq),
+                _ => queen_moves(occupied, sq),
+            };
+            score += KING_ZONE_ATTACK_CP * (attacks & zone).count_bits() as i32;
+        }
+    }
+    score
+}
+
+/// The king's file and its neighbours, one and two ranks towards the enemy.
+fn shield_squares(king: SQ, player: Player) -> BitBoard {
+    let file = king.file_idx_of_sq() as i32;
+    let rank = king.rank_idx_of_sq() as i32;
+    let forward = if player == Player::White { 1 } else { -1 };
+    let mut squares = 0_u64;
+    for step in 1..=2 {
+        let shield_rank = rank + forward * step;
+        if !(0..8).contains(&shield_rank) {
+            continue;
+        }
+        for shield_file in (file - 1).max(0)..=(file + 1).min(7) {
+            squares |= 1 << (shield_rank * 8 + shield_file);
+        }
+    }
+    BitBoard(squares)
+}
+
+fn pawn_structure(board: &Board, player: Player) -> i32 {
+    let pawns = board.piece_bb(player, PieceType::P);
+    let enemy_pawns = board.piece_bb(player.other_player(), PieceType::P);
+    let mut score = 0;
+
+    for file in 0..8 {
+        let on_file = (pawns.0 & (FILE_A << file)).count_ones() as i32;
+        if on_file == 0 {
+            continue;
+        }
+        score += DOUBLED_PAWN_CP * (on_file - 1);
+
+        let left = if file > 0 { FILE_A << (file - 1) } else { 0 };
+        let right = if file < 7 { FILE_A << (file + 1) } else { 0 };
+        if pawns.0 & (left | right) == 0 {
+            score += ISOLATED_PAWN_CP * on_file;
+        }
+    }
+
+    let mut remaining = pawns;
+    while let Some(sq) = remaining.pop_some_lsb() {
+        if (passed_pawn_mask(player, sq) & enemy_pawns).is_empty() {
+            let rank = sq.rank_idx_of_sq() as usize;
+            let relative_rank = if player == Player::White {
+                rank
+            } else {
+                7 - rank
+            };
+            score += PASSED_PAWN_CP[relative_rank];
+        }
+    }
+    score
+}
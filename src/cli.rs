@@ -0,0 +1,314 @@
+use crate::bench::BenchConfig;
+use crate::epd::{ReportFormat, SuiteLimits};
+use crate::label::LabelConfig;
+use crate::match_runner::{EngineSpec, MatchConfig, SprtConfig, TimeControl};
+use crate::selfplay::SelfPlayConfig;
+use crate::training::DataFormat;
+use crate::utils::board_from_epd;
+
+pub const USAGE: &str = "\
+usage: brainstorm [--option NAME=VALUE]... [COMMAND]
+
+commands:
+  uci                                      run the UCI protocol on stdin (default)
+  bench [DEPTH] [THREADS] [HASH]           search the bench positions, print the signature
+  perft DEPTH [FEN]                        count leaf nodes per root move
+  analyse FEN|EPD [--depth N] [--movetime MS]
+                                           search one position and print the best move
+  eval FEN                                 print the evaluation breakdown of a position
+  epd FILE [--depth N] [--movetime MS] [--nodes N] [--format text|json]
+                                           run an EPD test suite (bm/am/id opcodes)
+  selfplay [--games N] [--depth N] [--movetime MS] [--nodes N] [--max-plies N]
+           [--book FILE] [--random-plies N] [--seed N] [--resign CP] [--draw CP]
+           [--output FILE] [--format text|binary]
+                                           play games against itself, optionally
+                                           writing the positions as training data
+  label INPUT --output FILE [--depth N] [--nodes N] [--threads N] [--hash MB]
+        [--format text|binary] [--features FILE.npy]
+                                           label EPD/FEN or PGN positions with searches
+  match --engine SPEC --engine SPEC [--openings FILE] [--games N] [--tc S+INC]
+        [--movetime MS] [--depth N] [--nodes N] [--max-plies N] [--resign CP]
+        [--draw CP] [--pgn FILE] [--sprt elo0=0,elo1=5,alpha=0.05,beta=0.05]
+                                           play two engines against each other;
+                                           SPEC is cmd=PATH or builtin, plus name=NAME
+                                           and OPTION=VALUE pairs
+  help                                     print this message
+
+--option (or -o) sets a UCI option before the command runs, e.g. -o Model=accurate.";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Uci,
+    Bench(BenchConfig),
+    Perft {
+        depth: u32,
+        fen: Option<String>,
+    },
+    Analyse {
+        fen: String,
+        depth: Option<u32>,
+        movetime_ms: Option<u64>,
+    },
+    Eval {
+        fen: String,
+    },
+    Epd {
+        path: String,
+        limits: SuiteLimits,
+        format: ReportFormat,
+    },
+    SelfPlay(SelfPlayConfig),
+    Label(LabelConfig),
+    Match(MatchConfig),
+    Help,
+}
+
+impl Command {
+    /// The `go` command that searches with the limits of `analyse`; without limits `go`
+    /// uses its default move time.
+    pub fn go_command(depth: Option<u32>, movetime_ms: Option<u64>) -> String {
+        let mut command = "go".to_string();
+        if let Some(depth) = depth {
+            command.push_str(&format!(" depth {depth}"));
+        }
+        if let Some(movetime_ms) = movetime_ms {
+            command.push_str(&format!(" movetime {movetime_ms}"));
+        }
+        command
+    }
+}
+
+/// Parsed command line: UCI options to set first, then the command to run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cli {
+    pub options: Vec<(String, String)>,
+    pub command: Command,
+}
+
+impl Cli {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Vec::new();
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == "--option" || arg == "-o" {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("`{arg}` needs NAME=VALUE"))?;
+                let (name, value) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("`{arg} {value}` is not NAME=VALUE"))?;
+                options.push((name.trim().to_string(), value.trim().to_string()));
+            } else if arg == "--help" {
+                return Ok(Self {
+                    options,
+                    command: Command::Help,
+                });
+            } else if let Some(flag) = arg.strip_prefix("--") {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("`--{flag}` needs a value"))?;
+                flags.push((flag.to_string(), value.as_str()));
+            } else {
+                positional.push(arg.as_str());
+            }
+        }
+
+        let (name, rest) = match positional.split_first() {
+            Some((name, rest)) => (*name, rest),
+            None => ("uci", &[][..]),
+        };
+        let command = match name {
+            "uci" => {
+                expect_no_arguments(name, rest, &flags)?;
+                Command::Uci
+            }
+            "help" | "-h" => Command::Help,
+            "bench" => {
+                expect_no_flags(name, &flags)?;
+                Command::Bench(BenchConfig::parse(rest)?)
+            }
+            "perft" => {
+                expect_no_flags(name, &flags)?;
+                let (depth, fen) = rest
+                    .split_first()
+                    .ok_or("perft needs a depth".to_string())?;
+                let depth = parse_value("depth", depth)?;
+                let fen = if fen.is_empty() {
+                    None
+                } else {
+                    Some(parse_position(fen)?)
+                };
+                Command::Perft { depth, fen }
+            }
+            "analyse" | "analyze" => {
+                let mut depth = None;
+                let mut movetime_ms = None;
+                for (flag, value) in &flags {
+                    match flag.as_str() {
+                        "depth" => depth = Some(parse_value("depth", value)?),
+                        "movetime" => movetime_ms = Some(parse_value("movetime", value)?),
+                        _ => return Err(format!("unknown analyse flag `--{flag}`")),
+                    }
+                }
+                Command::Analyse {
+                    fen: parse_position(rest)?,
+                    depth,
+                    movetime_ms,
+                }
+            }
+            "eval" => {
+                expect_no_flags(name, &flags)?;
+                Command::Eval {
+                    fen: parse_position(rest)?,
+                }
+            }
+            "epd" => {
+                let [path] = rest else {
+                    return Err("epd needs exactly one file".to_string());
+                };
+                let mut limits = SuiteLimits::default();
+                let mut format = ReportFormat::default();
+                for (flag, value) in &flags {
+                    match flag.as_str() {
+                        "depth" => limits.depth = Some(parse_value("depth", value)?),
+                        "movetime" => limits.movetime_ms = Some(parse_value("movetime", value)?),
+                        "nodes" => limits.nodes = Some(parse_value("nodes", value)?),
+                        "format" => {
+                            format = ReportFormat::from_str(value)
+                                .ok_or_else(|| format!("invalid format `{value}`"))?
+                        }
+                        _ => return Err(format!("unknown epd flag `--{flag}`")),
+                    }
+                }
+                Command::Epd {
+                    path: path.to_string(),
+                    limits,
+                    format,
+                }
+            }
+            "selfplay" => {
+                expect_no_arguments(name, rest, &[])?;
+                let mut config = SelfPlayConfig::default();
+                for (flag, value) in &flags {
+                    match flag.as_str() {
+                        "games" => config.games = parse_value("games", value)?,
+                        "depth" => config.depth = Some(parse_value("depth", value)?),
+                        "movetime" => config.movetime_ms = Some(parse_value("movetime", value)?),
+                        "nodes" => config.nodes = Some(parse_value("nodes", value)?),
+                        "max-plies" => config.max_plies = parse_value("max-plies", value)?,
+                        "book" => config.book = Some(value.to_string()),
+                        "random-plies" => config.random_plies = parse_value("random-plies", value)?,
+                        "seed" => config.seed = parse_value("seed", value)?,
+                        "resign" => config.resign_cp = Some(parse_value("resign", value)?),
+                        "draw" => config.draw_cp = Some(parse_value("draw", value)?),
+                        "output" => config.output = Some(value.to_string()),
+                        "format" => {
+                            config.format = DataFormat::from_str(value)
+                                .ok_or_else(|| format!("invalid format `{value}`"))?
+                        }
+                        _ => return Err(format!("unknown selfplay flag `--{flag}`")),
+                    }
+                }
+                Command::SelfPlay(config)
+            }
+            "label" => {
+                let [input] = rest else {
+                    return Err("label needs exactly one input file".to_string());
+                };
+                let output = flags
+                    .iter()
+                    .find(|(flag, _)| flag == "output")
+                    .map(|(_, value)| *value)
+                    .ok_or_else(|| "label needs --output FILE".to_string())?;
+                let mut config = LabelConfig::new(input, output);
+                for (flag, value) in &flags {
+                    match flag.as_str() {
+                        "output" => {}
+                        "depth" => config.depth = Some(parse_value("depth", value)?),
+                        "nodes" => config.nodes = Some(parse_value("nodes", value)?),
+                        "threads" => config.threads = parse_value("threads", value)?,
+                        "hash" => config.hash_mb = parse_value("hash", value)?,
+                        "format" => {
+                            config.format = DataFormat::from_str(value)
+                                .ok_or_else(|| format!("invalid format `{value}`"))?
+                        }
+                        "features" => config.features = Some(value.to_string()),
+                        _ => return Err(format!("unknown label flag `--{flag}`")),
+                    }
+                }
+                Command::Label(config)
+            }
+            "match" => {
+                expect_no_arguments(name, rest, &[])?;
+                let engines = flags
+                    .iter()
+                    .filter(|(flag, _)| flag == "engine")
+                    .map(|(_, spec)| EngineSpec::parse(spec))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let [first, second] = <[EngineSpec; 2]>::try_from(engines)
+                    .map_err(|_| "match needs exactly two --engine specs".to_string())?;
+                if first.name == second.name {
+                    return Err(format!("both engines are named `{}`", first.name));
+                }
+                let mut config = MatchConfig::new(first, second);
+                for (flag, value) in &flags {
+                    match flag.as_str() {
+                        "engine" => {}
+                        "openings" => config.openings = Some(value.to_string()),
+                        "games" => config.games = Some(parse_value("games", value)?),
+                        "tc" => {
+                            config.time_control = Some(
+                                TimeControl::from_str(value)
+                                    .ok_or_else(|| format!("invalid tc `{value}`"))?,
+                            )
+                        }
+                        "movetime" => config.movetime_ms = Some(parse_value("movetime", value)?),
+                        "depth" => config.depth = Some(parse_value("depth", value)?),
+                        "nodes" => config.nodes = Some(parse_value("nodes", value)?),
+                        "max-plies" => config.max_plies = parse_value("max-plies", value)?,
+                        "resign" => config.resign_cp = Some(parse_value("resign", value)?),
+                        "draw" => config.draw_cp = Some(parse_value("draw", value)?),
+                        "pgn" => config.pgn = Some(value.to_string()),
+                        "sprt" => config.sprt = Some(SprtConfig::from_str(value)?),
+                        _ => return Err(format!("unknown match flag `--{flag}`")),
+                    }
+                }
+                Command::Match(config)
+            }
+            _ => return Err(format!("unknown command `{name}`")),
+        };
+        Ok(Self { options, command })
+    }
+}
+
+fn expect_no_flags(name: &str, flags: &[(String, &str)]) -> Result<(), String> {
+    match flags.first() {
+        Some((flag, _)) => Err(format!("{name} does not take `--{flag}`")),
+        None => Ok(()),
+    }
+}
+
+fn expect_no_arguments(name: &str, rest: &[&str], flags: &[(String, &str)]) -> Result<(), String> {
+    expect_no_flags(name, flags)?;
+    match rest.first() {
+        Some(arg) => Err(format!("{name} does not take `{arg}`")),
+        None => Ok(()),
+    }
+}
+
+/// A FEN or EPD record, either quoted as one argument or split over several.
+fn parse_position(parts: &[&str]) -> Result<String, String> {
+    let record = parts.join(" ");
+    board_from_epd(&record)
+        .map(|board| board.fen())
+        .ok_or_else(|| format!("invalid FEN or EPD `{record}`"))
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {name} `{value}`"))
+}
@@ -1,19 +1,77 @@
-use crate::search_algorithm::{ModelMode, SearchAlgorithm, SearchOptions, SearchRequest};
-use pleco::{BitMove, Board, Player};
-use std::path::Path;
+use crate::bench::{run_bench, BenchConfig, BENCH_FENS};
+use crate::classical::{self, ClassicalEval};
+use crate::epd::{parse_epd_suite, run_suite, ReportFormat, SuiteLimits};
+use crate::evaluator::{Evaluator, Precision};
+use crate::features::{color_flipped, MAX_INPUT_SIZE};
+use crate::label::{label_positions, read_positions, LabelConfig};
+use crate::perft;
+use crate::search_algorithm::{
+    Contempt, ModelMode, SearchAlgorithm, SearchMode, SearchOptions, SearchRequest, SearchResult,
+    MAX_CONTEMPT_CP,
+};
+use crate::selfplay::{parse_openings, play_game, SelfPlayConfig};
+use crate::time_manager::{plan_search, GoOptions};
+use crate::training::{FeatureWriter, TrainingWriter};
+use crate::utils::board_from_epd;
+use pleco::{BitMove, Board};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
-use tch::{CModule, Device};
+use std::time::Instant;
+use tch::Device;
 
 const FAST_MODEL_PATH: &str = "models/brainstorm_fast_eval_jit.pt";
 const ACCURATE_MODEL_PATH: &str = "models/brainstorm_accurate_eval_jit.pt";
 const LEGACY_SMALL_MODEL_PATH: &str = "models/eval_params264k_norm_mse0.117666_jit.pt";
 const LEGACY_LARGE_MODEL_PATH: &str = "models/eval_660k_norm_mse_0.026550_jit.pt";
+#[cfg(feature = "embedded-models")]
+const EMBEDDED_FAST_MODEL: Option<EmbeddedModel> = Some(EmbeddedModel {
+    bytes: include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/models/brainstorm_fast_eval_jit.pt"
+    )),
+    metadata: include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/models/brainstorm_fast_eval_jit.json"
+    )),
+});
+#[cfg(not(feature = "embedded-models"))]
+const EMBEDDED_FAST_MODEL: Option<EmbeddedModel> = None;
+#[cfg(feature = "embedded-models")]
+const EMBEDDED_ACCURATE_MODEL: Option<EmbeddedModel> = Some(EmbeddedModel {
+    bytes: include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/models/brainstorm_accurate_eval_jit.pt"
+    )),
+    metadata: include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/models/brainstorm_accurate_eval_jit.json"
+    )),
+});
+#[cfg(not(feature = "embedded-models"))]
+const EMBEDDED_ACCURATE_MODEL: Option<EmbeddedModel> = None;
+const EMBEDDED_MODEL_LABEL: &str = "<embedded>";
+/// `label` reports its progress every this many positions.
+const LABEL_PROGRESS_INTERVAL: usize = 1_000;
+const FAST_MODEL_ENV: &str = "BRAINSTORM_FAST_MODEL";
+const ACCURATE_MODEL_ENV: &str = "BRAINSTORM_ACCURATE_MODEL";
+const DEFAULT_PATH_VALUE: &str = "<default>";
+// Covers binaries copied to the repo root as well as `target/<profile>/brainstorm`.
+const EXECUTABLE_SEARCH_DEPTH: usize = 3;
 const MAX_HASH_MB: usize = 4096;
-const DEFAULT_FALLBACK_MOVETIME_MS: u64 = 2_000;
+const DEFAULT_MOVE_OVERHEAD_MS: u64 = 30;
+const MAX_MOVE_OVERHEAD_MS: u64 = 5_000;
+const EVALCHECK_REPORT_LIMIT: usize = 5;
+
+/// A default network and its metadata sidecar baked in by the `embedded-models` feature.
+#[derive(Clone, Copy)]
+struct EmbeddedModel {
+    bytes: &'static [u8],
+    metadata: &'static str,
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum EvalDeviceChoice {
@@ -33,12 +91,65 @@ impl EvalDeviceChoice {
     }
 }
 
+/// Model files chosen through UCI options or environment variables. `None` means the
+/// bundled default under `models/`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct ModelPaths {
+    fast: Option<String>,
+    accurate: Option<String>,
+}
+
+impl ModelPaths {
+    fn from_env() -> Self {
+        let read = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        Self {
+            fast: read(FAST_MODEL_ENV),
+            accurate: read(ACCURATE_MODEL_ENV),
+        }
+    }
+}
+
+/// Evaluators loaded so far. Models are loaded on first `isready`/`go` and only when the
+/// active `Model` needs them, so `uci` never waits on libtorch. A model that failed to
+/// load is not retried until an option that affects it changes.
+#[derive(Default)]
+struct LoadedEvaluators {
+    device: Option<Device>,
+    fast: Option<Arc<Evaluator>>,
+    accurate: Option<Arc<Evaluator>>,
+    fast_failed: bool,
+    accurate_failed: bool,
+}
+
+impl LoadedEvaluators {
+    fn is_loaded(&self) -> bool {
+        self.fast.is_some() || self.accurate.is_some()
+    }
+
+    /// Whether loading would change nothing for `mode`.
+    fn covers(&self, mode: ModelMode) -> bool {
+        (!needs_fast_model(mode) || self.fast.is_some() || self.fast_failed)
+            && (!needs_accurate_model(mode) || self.accurate.is_some() || self.accurate_failed)
+    }
+}
+
 pub struct Engine {
     pub board: Board,
-    pub search_algorithm: SearchAlgorithm,
+    search_algorithm: Option<SearchAlgorithm>,
+    should_stop: Arc<AtomicBool>,
     options: SearchOptions,
+    move_overhead_ms: u64,
+    /// The `Contempt` value, kept while `ContemptAuto` overrides it.
+    contempt_cp: i32,
     device_choice: EvalDeviceChoice,
-    active_device: Device,
+    precision: Precision,
+    evaluators: LoadedEvaluators,
+    announce_device: bool,
+    model_paths: ModelPaths,
     position_history: Vec<u64>,
     search_handle: Option<JoinHandle<()>>,
 }
@@ -46,19 +157,14 @@ pub struct Engine {
 impl Engine {
     pub fn new() -> Self {
         let board = Board::default();
-        let should_stop = Arc::new(AtomicBool::new(false));
-        let device_choice = EvalDeviceChoice::Auto;
-        let (search_algorithm, active_device, startup_message) =
-            Self::build_search_algorithm_for_choice(device_choice, Arc::clone(&should_stop))
-                .expect("failed to initialize evaluators");
-        if let Some(message) = startup_message {
-            eprintln!("[engine] {message}");
-        }
 
         let options = SearchOptions {
             hash_mb: 64,
             threads: SearchAlgorithm::default_threads(),
             model_mode: ModelMode::Small,
+            search_mode: SearchMode::AlphaBeta,
+            contempt: Contempt::default(),
+            lazy_eval: false,
             debug_log: false,
         };
 
@@ -66,10 +172,16 @@ impl Engine {
 
         Self {
             board,
-            search_algorithm,
+            search_algorithm: None,
+            should_stop: Arc::new(AtomicBool::new(false)),
             options,
-            device_choice,
-            active_device,
+            move_overhead_ms: DEFAULT_MOVE_OVERHEAD_MS,
+            contempt_cp: 0,
+            device_choice: EvalDeviceChoice::Auto,
+            precision: Precision::default(),
+            evaluators: LoadedEvaluators::default(),
+            announce_device: false,
+            model_paths: ModelPaths::from_env(),
             position_history: vec![root_key],
             search_handle: None,
         }
@@ -86,13 +198,28 @@ impl Engine {
                 .map(|count| count.get())
                 .unwrap_or(1)
         );
-        println!("option name Model type combo default fast var fast var balanced var accurate");
+        println!("option name Model type combo default fast var fast var balanced var accurate var adaptive var ensemble var classical");
+        println!("option name SearchMode type combo default alphabeta var alphabeta var mcts");
         println!("option name Device type combo default auto var auto var cpu var cuda");
+        println!("option name Precision type combo default f32 var f32 var f16 var int8");
+        println!(
+            "option name Contempt type spin default 0 min {} max {}",
+            -MAX_CONTEMPT_CP, MAX_CONTEMPT_CP
+        );
+        println!("option name ContemptAuto type check default false");
+        println!(
+            "option name Move Overhead type spin default {} min 0 max {}",
+            DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS
+        );
+        println!("option name FastModelPath type string default {FAST_MODEL_PATH}");
+        println!("option name AccurateModelPath type string default {ACCURATE_MODEL_PATH}");
+        println!("option name LazyEval type check default false");
         println!("option name DebugLog type check default false");
         println!("uciok");
     }
 
-    pub fn isready(&self) {
+    pub fn isready(&mut self) {
+        self.ensure_evaluators(self.options.model_mode);
         println!("readyok");
     }
 
@@ -103,64 +230,113 @@ impl Engine {
         self.position_history.push(self.board.zobrist());
     }
 
+    /// UCI `setoption`; unknown options and invalid values are ignored, as the protocol
+    /// asks.
     pub fn setoption(&mut self, command: &str) {
         if let Some((name, value)) = parse_option_parts(command) {
-            match name.to_ascii_lowercase().as_str() {
-                "hash" => {
-                    if let Ok(hash_mb) = value.parse::<usize>() {
-                        self.options.hash_mb = hash_mb.clamp(1, MAX_HASH_MB);
-                        let info = SearchAlgorithm::hash_table_info(self.options.hash_mb);
-                        println!(
-                            "info string hash_config requested_mb={} effective_mb={} entries={}",
-                            self.options.hash_mb, info.effective_mb, info.entries
-                        );
-                    }
+            let _ = self.set_option(&name, &value);
+        }
+    }
+
+    /// Sets option `name` (case-insensitive, as listed by `uci`) to `value`.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("invalid value `{value}` for option `{name}`");
+        match name.to_ascii_lowercase().as_str() {
+            "hash" => {
+                let hash_mb = value.parse::<usize>().map_err(|_| invalid())?;
+                self.options.hash_mb = hash_mb.clamp(1, MAX_HASH_MB);
+                let info = SearchAlgorithm::hash_table_info(self.options.hash_mb);
+                println!(
+                    "info string hash_config requested_mb={} effective_mb={} entries={}",
+                    self.options.hash_mb, info.effective_mb, info.entries
+                );
+            }
+            "threads" => {
+                let threads = value.parse::<usize>().map_err(|_| invalid())?;
+                let max_threads = std::thread::available_parallelism()
+                    .map(|count| count.get())
+                    .unwrap_or(1);
+                self.options.threads = threads.clamp(1, max_threads);
+            }
+            "model" => {
+                let (mode, alias_message) =
+                    ModelMode::parse_with_alias_info(value).ok_or_else(invalid)?;
+                self.options.model_mode = mode;
+                if let Some(message) = alias_message {
+                    println!("info string {}", message);
                 }
-                "threads" => {
-                    if let Ok(threads) = value.parse::<usize>() {
-                        let max_threads = std::thread::available_parallelism()
-                            .map(|count| count.get())
-                            .unwrap_or(1);
-                        self.options.threads = threads.clamp(1, max_threads);
-                    }
+            }
+            "searchmode" => {
+                self.options.search_mode = SearchMode::from_str(value).ok_or_else(invalid)?;
+            }
+            "contempt" => {
+                let cp = value.parse::<i32>().map_err(|_| invalid())?;
+                self.contempt_cp = cp.clamp(-MAX_CONTEMPT_CP, MAX_CONTEMPT_CP);
+                let auto = self.options.contempt == Contempt::Auto;
+                self.options.contempt = Contempt::new(self.contempt_cp, auto);
+            }
+            "contemptauto" => {
+                let auto = parse_bool(value).ok_or_else(invalid)?;
+                self.options.contempt = Contempt::new(self.contempt_cp, auto);
+            }
+            "move overhead" => {
+                let overhead_ms = value.parse::<u64>().map_err(|_| invalid())?;
+                self.move_overhead_ms = overhead_ms.min(MAX_MOVE_OVERHEAD_MS);
+            }
+            "lazyeval" => {
+                self.options.lazy_eval = parse_bool(value).ok_or_else(invalid)?;
+            }
+            "debuglog" => {
+                self.options.debug_log = parse_bool(value).ok_or_else(invalid)?;
+            }
+            "precision" => {
+                let precision = Precision::from_str(value).ok_or_else(invalid)?;
+                if precision != self.precision {
+                    self.precision = precision;
+                    self.unload_evaluators(true, true);
                 }
-                "model" => {
-                    if let Some((mode, alias_message)) = ModelMode::parse_with_alias_info(&value) {
-                        self.options.model_mode = mode;
-                        if let Some(message) = alias_message {
-                            println!("info string {}", message);
-                        }
-                    }
+            }
+            "device" => {
+                let choice = EvalDeviceChoice::from_str(value).ok_or_else(invalid)?;
+                if choice != self.device_choice {
+                    self.device_choice = choice;
+                    self.unload_evaluators(true, true);
+                    self.announce_device = true;
                 }
-                "debuglog" => {
-                    self.options.debug_log = parse_bool(&value).unwrap_or(self.options.debug_log);
+            }
+            "fastmodelpath" | "evalfile" => {
+                let path = configured_model_path(value).or_else(|| ModelPaths::from_env().fast);
+                if path != self.model_paths.fast {
+                    self.model_paths.fast = path;
+                    self.unload_evaluators(true, false);
+                    println!(
+                        "info string fast_model={}",
+                        model_source_label(
+                            self.model_paths.fast.as_deref(),
+                            FAST_MODEL_PATH,
+                            EMBEDDED_FAST_MODEL
+                        )
+                    );
                 }
-                "device" => {
-                    if let Some(choice) = EvalDeviceChoice::from_str(&value) {
-                        self.stop_and_join_search();
-                        let should_stop = Arc::clone(&self.search_algorithm.should_stop);
-                        match Self::build_search_algorithm_for_choice(choice, should_stop) {
-                            Ok((search_algorithm, active_device, warning)) => {
-                                self.search_algorithm = search_algorithm;
-                                self.device_choice = choice;
-                                self.active_device = active_device;
-                                if let Some(message) = warning {
-                                    println!("info string {}", message);
-                                }
-                                println!(
-                                    "info string evaluator_device={}",
-                                    device_to_label(self.active_device)
-                                );
-                            }
-                            Err(err) => {
-                                println!("info string failed to set Device: {}", err);
-                            }
-                        }
-                    }
+            }
+            "accuratemodelpath" => {
+                let path = configured_model_path(value).or_else(|| ModelPaths::from_env().accurate);
+                if path != self.model_paths.accurate {
+                    self.model_paths.accurate = path;
+                    self.unload_evaluators(false, true);
+                    println!(
+                        "info string accurate_model={}",
+                        model_source_label(
+                            self.model_paths.accurate.as_deref(),
+                            ACCURATE_MODEL_PATH,
+                            EMBEDDED_ACCURATE_MODEL
+                        )
+                    );
                 }
-                _ => {}
             }
+            _ => return Err(format!("unknown option `{name}`")),
         }
+        Ok(())
     }
 
     pub fn position(&mut self, command: &str) {
@@ -215,25 +391,31 @@ impl Engine {
     pub fn go(&mut self, command: &str, tx: Sender<BitMove>) {
         self.stop_and_join_search();
 
-        let go_options = self.parse_go_options(command);
-        let request = self.build_search_request(&go_options);
-
+        let go_options = GoOptions::parse(command);
+        if let Some(depth) = go_options.perft {
+            self.perft(depth);
+            return;
+        }
+        let (search_algorithm, request, options) = self.prepare_go(&go_options);
         let board_clone = self.board.parallel_clone();
         let history = self.position_history.clone();
-        let search_algorithm = self.search_algorithm.clone();
-        let options = self.options.clone();
 
         self.search_handle = Some(thread::spawn(move || {
             let result = search_algorithm.search(&board_clone, request, &options, &history);
             if options.debug_log {
                 println!(
-                    "info string depth={} score_cp={} nodes={} elapsed_ms={} eval_calls={} eval_cache_hits={} tt_probes={} tt_hits={} q_nodes={} beta_cutoffs={}",
+                    "info string depth={} score_cp={} nodes={} elapsed_ms={} eval_calls={} eval_cache_hits={} fast_model_evals={} accurate_model_evals={} lazy_eval_cutoffs={} policy_orderings={} reused_playouts={} tt_probes={} tt_hits={} q_nodes={} beta_cutoffs={}",
                     result.depth,
                     result.score_cp,
                     result.nodes,
                     result.elapsed.as_millis(),
                     result.stats.eval_calls,
                     result.stats.eval_cache_hits,
+                    result.stats.fast_model_evals,
+                    result.stats.accurate_model_evals,
+                    result.stats.lazy_eval_cutoffs,
+                    result.stats.policy_orderings,
+                    result.stats.reused_playouts,
                     result.stats.tt_probes,
                     result.stats.tt_hits,
                     result.stats.q_nodes,
@@ -244,6 +426,283 @@ impl Engine {
         }));
     }
 
+    /// Searches the current position as `go` would, but waits for the result and prints
+    /// nothing.
+    pub fn search_quiet(&mut self, go_options: &GoOptions) -> SearchResult {
+        self.stop_and_join_search();
+        let (search_algorithm, request, options) = self.prepare_go(go_options);
+        search_algorithm.search_quiet(&self.board, request, &options, &self.position_history)
+    }
+
+    /// The searcher, limits and options `go_options` call for in the current position.
+    fn prepare_go(
+        &mut self,
+        go_options: &GoOptions,
+    ) -> (SearchAlgorithm, SearchRequest, SearchOptions) {
+        let plan = plan_search(go_options, self.board.turn(), self.move_overhead_ms);
+        let request = plan.request;
+
+        let mut options = self.options.clone();
+        // Loading the fast model just for an emergency move would cost more than it saves.
+        if plan.emergency
+            && options.model_mode != ModelMode::Classical
+            && self.evaluators.fast.is_some()
+        {
+            options.model_mode = ModelMode::Small;
+            if options.debug_log {
+                println!(
+                    "info string emergency_time_mode max_depth={} hard_ms={}",
+                    request.max_depth,
+                    request.hard_time_ms.unwrap_or(0)
+                );
+            }
+        }
+
+        (self.ensure_evaluators(options.model_mode), request, options)
+    }
+
+    /// `bench [depth] [threads] [hash]`: searches the fixed bench positions with the
+    /// current model settings and prints the totals and the node signature.
+    pub fn bench(&mut self, command: &str) {
+        let args: Vec<&str> = command.split_whitespace().skip(1).collect();
+        let config = match BenchConfig::parse(&args) {
+            Ok(config) => config,
+            Err(err) => {
+                println!("info string {}", err);
+                return;
+            }
+        };
+        self.bench_with(config);
+    }
+
+    pub fn bench_with(&mut self, config: BenchConfig) {
+        self.stop_and_join_search();
+        let search_algorithm = self.ensure_evaluators(self.options.model_mode);
+        let report = run_bench(
+            &search_algorithm,
+            &self.options,
+            config,
+            &BENCH_FENS,
+            print_bench_position,
+        );
+        // The bench leaves nothing useful in the tables for the game in progress.
+        search_algorithm.clear();
+
+        println!("===========================");
+        println!("Total time (ms) : {}", report.elapsed.as_millis());
+        println!("Nodes searched  : {}", report.nodes);
+        println!("Nodes/second    : {}", report.nps());
+        println!("Signature       : {:016x}", report.signature);
+        if config.threads > 1 {
+            println!("info string bench signature is only reproducible with threads=1");
+        }
+    }
+
+    /// Runs the EPD test suite in `path` with the current settings and prints which
+    /// positions were solved, as text with per-position progress or as one JSON document.
+    pub fn epd_suite(&mut self, path: &str, limits: SuiteLimits, format: ReportFormat) {
+        let records = match std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read `{path}`: {err}"))
+            .and_then(|contents| parse_epd_suite(&contents))
+        {
+            Ok(records) => records,
+            Err(err) => {
+                println!("info string {}", err);
+                return;
+            }
+        };
+        self.stop_and_join_search();
+        let search_algorithm = self.ensure_evaluators(self.options.model_mode);
+        let report = run_suite(
+            &search_algorithm,
+            &self.options,
+            &records,
+            limits,
+            |outcome| {
+                if format == ReportFormat::Text {
+                    println!(
+                        "info string epd id={} solved={} played={} depth={} nodes={} time_ms={}",
+                        outcome.id,
+                        outcome.solved,
+                        outcome.played,
+                        outcome.depth,
+                        outcome.nodes,
+                        outcome.time_ms
+                    );
+                }
+            },
+        );
+        search_algorithm.clear();
+
+        match format {
+            ReportFormat::Text => println!("{}", report.to_text()),
+            ReportFormat::Json => println!("{}", report.to_json()),
+        }
+    }
+
+    /// `go perft N`: prints the leaf count under each legal move and the total, in the
+    /// format other engines use so the output can be diffed against theirs.
+    pub fn perft(&mut self, depth: u32) {
+        let start = Instant::now();
+        let mut board = self.board.shallow_clone();
+        let mut total = u64::from(depth == 0);
+        for (mv, nodes) in perft::divide(&mut board, depth) {
+            println!("{mv}: {nodes}");
+            total += nodes;
+        }
+        let elapsed = start.elapsed();
+        println!();
+        println!("Nodes searched: {total}");
+        println!(
+            "info string perft depth={} nodes={} time_ms={} nps={}",
+            depth,
+            total,
+            elapsed.as_millis(),
+            (total as f64 / elapsed.as_secs_f64().max(1e-9)) as u64
+        );
+    }
+
+    /// Runs `command` like `go` but waits for the search and returns its best move, for
+    /// callers outside the UCI loop. `go perft` returns a null move.
+    pub fn go_and_wait(&mut self, command: &str) -> BitMove {
+        let (tx, rx) = channel();
+        self.go(command, tx);
+        let best_move = rx.recv().unwrap_or_else(|_| BitMove::null());
+        self.stop_and_join_search();
+        best_move
+    }
+
+    /// Plays `config.games` games with the current settings moving for both sides,
+    /// printing each game's moves and result and writing its positions to
+    /// `config.output` if set.
+    pub fn selfplay(&mut self, config: SelfPlayConfig) {
+        let book = match &config.book {
+            Some(path) => match std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read `{path}`: {err}"))
+                .and_then(|contents| parse_openings(&contents))
+            {
+                Ok(book) => book,
+                Err(err) => {
+                    println!("info string {}", err);
+                    return;
+                }
+            },
+            None => Vec::new(),
+        };
+        let mut writer = match &config.output {
+            Some(path) => match TrainingWriter::create(path, config.format) {
+                Ok(writer) => Some(writer),
+                Err(err) => {
+                    println!("info string {}", err);
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        self.stop_and_join_search();
+        let search_algorithm = self.ensure_evaluators(self.options.model_mode);
+        for game in 1..=config.games {
+            search_algorithm.clear();
+            let start = config.opening(&book, game - 1);
+            let record = play_game(&search_algorithm, &self.options, &config, &start);
+            let moves: Vec<String> = record.moves.iter().map(|mv| mv.to_string()).collect();
+            println!(
+                "info string selfplay game={}/{} result={} termination={} plies={}",
+                game,
+                config.games,
+                record.result.as_str(),
+                record.termination.as_str(),
+                record.moves.len()
+            );
+            println!(
+                "position fen {} moves {}",
+                record.start_fen,
+                moves.join(" ")
+            );
+            if let Some(writer) = writer.as_mut() {
+                let written = record
+                    .training_records()
+                    .iter()
+                    .try_for_each(|position| writer.write(position));
+                if let Err(err) = written {
+                    println!("info string {}", err);
+                    return;
+                }
+            }
+        }
+        if let (Some(writer), Some(path)) = (writer, &config.output) {
+            match writer.finish() {
+                Ok(written) => println!("info string selfplay wrote {written} positions to {path}"),
+                Err(err) => println!("info string {}", err),
+            }
+        }
+    }
+
+    /// Labels every position in `config.input` with a fixed-limit search and writes the
+    /// records (and optionally the encoded features) to disk.
+    pub fn label(&mut self, config: LabelConfig) {
+        let start = Instant::now();
+        let positions = match read_positions(&config.input) {
+            Ok(positions) => positions,
+            Err(err) => {
+                println!("info string {}", err);
+                return;
+            }
+        };
+        let mut writer = match TrainingWriter::create(&config.output, config.format) {
+            Ok(writer) => writer,
+            Err(err) => {
+                println!("info string {}", err);
+                return;
+            }
+        };
+        let mut features = match &config.features {
+            Some(path) => match FeatureWriter::create(path, positions.len()) {
+                Ok(features) => Some(features),
+                Err(err) => {
+                    println!("info string {}", err);
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        self.stop_and_join_search();
+        let search_algorithm = self.ensure_evaluators(self.options.model_mode);
+        let total = positions.len();
+        let labelled = label_positions(
+            &search_algorithm,
+            &self.options,
+            &positions,
+            &config,
+            |index, record| {
+                writer.write(&record)?;
+                if let Some(features) = features.as_mut() {
+                    features.write(&positions[index].board)?;
+                }
+                if (index + 1) % LABEL_PROGRESS_INTERVAL == 0 {
+                    println!("info string label positions={}/{}", index + 1, total);
+                }
+                Ok(())
+            },
+        )
+        .and_then(|_| writer.finish())
+        .and_then(|written| match features {
+            Some(features) => features.finish(),
+            None => Ok(written),
+        });
+        match labelled {
+            Ok(written) => println!(
+                "info string label wrote {} positions to {} in {} ms",
+                written,
+                config.output,
+                start.elapsed().as_millis()
+            ),
+            Err(err) => println!("info string {}", err),
+        }
+    }
+
     pub fn make_move(&mut self, best_move: BitMove) {
         if !best_move.is_null() {
             self.board.apply_move(best_move);
@@ -252,10 +711,134 @@ impl Engine {
         self.finish_search_if_done();
     }
 
+    /// `eval`: prints what both networks make of the current position.
+    pub fn eval(&mut self) {
+        let search_algorithm = self.ensure_evaluators(ModelMode::HybridRoot);
+        let device = self.evaluators.device.unwrap_or(Device::Cpu);
+        let mut buffer = [0_f32; MAX_INPUT_SIZE];
+
+        println!("info string eval fen {}", self.board.fen());
+        let classical_eval = ClassicalEval::of(&self.board);
+        println!(
+            "info string eval classical white_cp={} side_to_move_cp={} {classical_eval}",
+            classical_eval.total(),
+            classical::evaluate(&self.board)
+        );
+        for (label, evaluator) in [
+            ("fast", self.evaluators.fast.as_deref()),
+            ("accurate", self.evaluators.accurate.as_deref()),
+        ] {
+            let Some(evaluator) = evaluator else {
+                continue;
+            };
+            let feature_set = evaluator.feature_set();
+            let features = feature_set.encode(&self.board, &mut buffer);
+            let raw = evaluator.raw_output(features, device);
+            let nonzero = features.iter().filter(|&&value| value != 0.0).count();
+            let segments = feature_set
+                .segments()
+                .iter()
+                .map(|(name, range)| {
+                    let sum: f32 = features[range.clone()].iter().sum();
+                    format!("{name}={sum}")
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            println!(
+                "info string eval {label} raw={raw:.6} scaled_cp={} side_to_move_cp={}",
+                (raw * evaluator.metadata().output_scale_cp).round() as i32,
+                evaluator.evaluate(&self.board, &mut buffer, device)
+            );
+            println!(
+                "info string eval {label} features set={} size={} nonzero={nonzero} {segments}",
+                feature_set.version(),
+                feature_set.input_size()
+            );
+        }
+
+        let (cache, fast_cached, accurate_cached) = search_algorithm.eval_cache_state(&self.board);
+        let cached = |score: Option<i32>| score.map_or("none".to_string(), |cp| cp.to_string());
+        println!(
+            "info string eval cache entries={} filled={} fast={} accurate={}",
+            cache.entries,
+            cache.filled,
+            cached(fast_cached),
+            cached(accurate_cached)
+        );
+    }
+
+    /// `evalcheck <epd file>`: scores every position and its color-flipped twin with both
+    /// networks and reports the largest disagreements. A color-symmetric network gives both
+    /// the same side-to-move score.
+    pub fn evalcheck(&mut self, command: &str) {
+        let path = command
+            .split_whitespace()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .join(" ");
+        if path.is_empty() {
+            println!("info string evalcheck requires an EPD file");
+            return;
+        }
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                println!("info string evalcheck failed to read `{path}`: {err}");
+                return;
+            }
+        };
+        self.ensure_evaluators(ModelMode::HybridRoot);
+        let device = self.evaluators.device.unwrap_or(Device::Cpu);
+        let boards: Vec<Board> = contents.lines().filter_map(board_from_epd).collect();
+        let mut buffer = [0_f32; MAX_INPUT_SIZE];
+
+        for (label, evaluator) in [
+            ("fast", self.evaluators.fast.as_deref()),
+            ("accurate", self.evaluators.accurate.as_deref()),
+        ] {
+            let Some(evaluator) = evaluator else {
+                continue;
+            };
+            let mut discrepancies = Vec::with_capacity(boards.len());
+            for board in &boards {
+                let Some(flipped) = color_flipped(board) else {
+                    continue;
+                };
+                let score = evaluator.evaluate(board, &mut buffer, device);
+                let flipped_score = evaluator.evaluate(&flipped, &mut buffer, device);
+                discrepancies.push(((score - flipped_score).abs(), score, flipped_score, board));
+            }
+            discrepancies.sort_by_key(|&(diff, ..)| std::cmp::Reverse(diff));
+
+            let checked = discrepancies.len();
+            let total: i64 = discrepancies.iter().map(|&(diff, ..)| diff as i64).sum();
+            println!(
+                "info string evalcheck {label} positions={checked} max_diff_cp={} mean_diff_cp={:.1} asymmetric={}",
+                discrepancies.first().map_or(0, |&(diff, ..)| diff),
+                if checked == 0 { 0.0 } else { total as f64 / checked as f64 },
+                discrepancies.iter().filter(|&&(diff, ..)| diff > 0).count()
+            );
+            for (diff, score, flipped_score, board) in
+                discrepancies.iter().take(EVALCHECK_REPORT_LIMIT)
+            {
+                if *diff == 0 {
+                    break;
+                }
+                println!(
+                    "info string evalcheck {label} diff_cp={diff} score={score} flipped={flipped_score} fen {}",
+                    board.fen()
+                );
+            }
+        }
+    }
+
+    /// The flag that stops the current search, for stopping it from another thread.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.should_stop)
+    }
+
     pub fn stop(&mut self) {
-        self.search_algorithm
-            .should_stop
-            .store(true, Ordering::Relaxed);
+        self.should_stop.store(true, Ordering::Relaxed);
     }
 
     pub fn quit(&mut self) {
@@ -280,9 +863,7 @@ impl Engine {
         if let Some(handle) = self.search_handle.take() {
             let _ = handle.join();
         }
-        self.search_algorithm
-            .should_stop
-            .store(false, Ordering::Relaxed);
+        self.should_stop.store(false, Ordering::Relaxed);
     }
 
     fn apply_moves_and_track(&mut self, moves: &[&str]) {
@@ -296,183 +877,224 @@ impl Engine {
         }
     }
 
-    fn parse_go_options(&self, command: &str) -> GoOptions {
-        let mut options = GoOptions::default();
-        let mut iter = command.split_whitespace().skip(1);
-
-        while let Some(token) = iter.next() {
-            match token {
-                "depth" => options.depth = iter.next().and_then(|value| value.parse::<u32>().ok()),
-                "movetime" => {
-                    options.movetime_ms = iter.next().and_then(|value| value.parse::<u64>().ok())
-                }
-                "wtime" => {
-                    options.wtime_ms = iter.next().and_then(|value| value.parse::<u64>().ok())
-                }
-                "btime" => {
-                    options.btime_ms = iter.next().and_then(|value| value.parse::<u64>().ok())
-                }
-                "winc" => options.winc_ms = iter.next().and_then(|value| value.parse::<u64>().ok()),
-                "binc" => options.binc_ms = iter.next().and_then(|value| value.parse::<u64>().ok()),
-                "movestogo" => {
-                    options.movestogo = iter.next().and_then(|value| value.parse::<u32>().ok())
-                }
-                "infinite" => options.infinite = true,
-                _ => {}
+    /// Loads whatever `mode` still needs and returns a searcher sharing the engine's stop
+    /// flag. Load failures are reported through `info string` once; the network that did
+    /// load then stands in for the one that did not, and without either the searcher falls
+    /// back to the classical evaluation, so the engine can still play.
+    fn ensure_evaluators(&mut self, mode: ModelMode) -> SearchAlgorithm {
+        if self.evaluators.covers(mode) {
+            if let Some(search_algorithm) = &self.search_algorithm {
+                return search_algorithm.clone();
             }
         }
 
-        options
-    }
-
-    fn build_search_request(&self, options: &GoOptions) -> SearchRequest {
-        let max_depth = options.depth.unwrap_or(64).max(1);
-
-        if options.infinite {
-            return SearchRequest {
-                max_depth,
-                soft_time_ms: None,
-                hard_time_ms: None,
-            };
-        }
+        self.stop_and_join_search();
+        let device = match self.evaluators.device {
+            Some(device) => device,
+            None => {
+                let target = resolve_device_for_choice(self.device_choice);
+                if let Some(message) = device_choice_warning(self.device_choice, target) {
+                    println!("info string {}", message);
+                }
+                target
+            }
+        };
 
-        if let Some(movetime_ms) = options.movetime_ms {
-            let adjusted = movetime_ms
-                .saturating_sub(safety_margin(movetime_ms))
-                .max(1);
-            return SearchRequest {
-                max_depth,
-                soft_time_ms: Some(adjusted),
-                hard_time_ms: Some(adjusted),
-            };
+        let mut errors = self.load_missing_evaluators(device, mode);
+        // Nothing is on the device yet, so everything can still move to the cpu.
+        if !errors.is_empty() && device != Device::Cpu && !self.evaluators.is_loaded() {
+            println!(
+                "info string falling back to cpu because loading models on {} failed: {}",
+                device_to_label(device),
+                errors.join("; ")
+            );
+            self.evaluators.fast_failed = false;
+            self.evaluators.accurate_failed = false;
+            errors = self.load_missing_evaluators(Device::Cpu, mode);
         }
-
-        if let Some(allocated) = self.allocate_clock_time_ms(options) {
-            let hard = allocated.saturating_sub(safety_margin(allocated)).max(1);
-            let soft = hard.saturating_mul(9) / 10;
-            return SearchRequest {
-                max_depth,
-                soft_time_ms: Some(soft.max(1)),
-                hard_time_ms: Some(hard),
-            };
+        for err in &errors {
+            println!("info string {}", err);
         }
 
-        if options.depth.is_some() {
-            return SearchRequest {
-                max_depth,
-                soft_time_ms: None,
-                hard_time_ms: None,
-            };
+        let device = self.evaluators.device.unwrap_or(Device::Cpu);
+        if self.announce_device && self.evaluators.is_loaded() {
+            println!("info string evaluator_device={}", device_to_label(device));
+            self.announce_device = false;
         }
 
-        let adjusted = DEFAULT_FALLBACK_MOVETIME_MS
-            .saturating_sub(safety_margin(DEFAULT_FALLBACK_MOVETIME_MS))
-            .max(1);
-        SearchRequest {
-            max_depth,
-            soft_time_ms: Some(adjusted),
-            hard_time_ms: Some(adjusted),
-        }
+        // Each mode only calls the evaluators it selects, so a model that is not loaded
+        // (yet, or at all) can be stood in for by the other one.
+        let fast = self.evaluators.fast.clone();
+        let accurate = self.evaluators.accurate.clone();
+        let small = fast.clone().or_else(|| accurate.clone());
+        let large = accurate.or(fast);
+        let search_algorithm = match (small, large) {
+            (Some(small), Some(large)) => {
+                if !errors.is_empty() {
+                    let (loaded, failed) = if self.evaluators.fast.is_some() {
+                        ("fast", "accurate")
+                    } else {
+                        ("accurate", "fast")
+                    };
+                    println!("info string using the {loaded} model in place of the {failed} one");
+                }
+                SearchAlgorithm::new(small, large, device, Arc::clone(&self.should_stop))
+            }
+            _ => {
+                if !errors.is_empty() {
+                    println!("info string falling back to classical evaluation");
+                }
+                SearchAlgorithm::classical(Arc::clone(&self.should_stop))
+            }
+        };
+        self.search_algorithm = Some(search_algorithm.clone());
+        search_algorithm
     }
 
-    fn allocate_clock_time_ms(&self, options: &GoOptions) -> Option<u64> {
-        let (remaining_ms, increment_ms) = match self.board.turn() {
-            Player::White => (options.wtime_ms?, options.winc_ms.unwrap_or(0)),
-            Player::Black => (options.btime_ms?, options.binc_ms.unwrap_or(0)),
-        };
+    /// Loads the models `mode` needs that are neither loaded nor known to fail, and returns
+    /// the errors of those that failed now.
+    fn load_missing_evaluators(&mut self, device: Device, mode: ModelMode) -> Vec<String> {
+        let mut errors = Vec::new();
+        if needs_fast_model(mode) && self.evaluators.fast.is_none() && !self.evaluators.fast_failed
+        {
+            let evaluator = Self::load_model_module(
+                "fast",
+                self.model_paths.fast.as_deref(),
+                EMBEDDED_FAST_MODEL,
+                FAST_MODEL_PATH,
+                LEGACY_SMALL_MODEL_PATH,
+                device,
+            )
+            .and_then(|evaluator| self.prepare_evaluator(evaluator, device, "fast"));
+            match evaluator {
+                Ok(evaluator) => {
+                    self.evaluators.fast = Some(Arc::new(evaluator));
+                    self.evaluators.device = Some(device);
+                }
+                Err(err) => {
+                    self.evaluators.fast_failed = true;
+                    errors.push(err);
+                }
+            }
+        }
 
-        let moves_to_go = options.movestogo.unwrap_or(30).max(1) as u64;
-        let base = remaining_ms / moves_to_go;
-        let increment_bonus = increment_ms.saturating_mul(4) / 5;
-        let mut allocated = base.saturating_add(increment_bonus).max(20);
-
-        let cap = remaining_ms.saturating_mul(7) / 10;
-        allocated = allocated.min(cap.max(20));
-        Some(allocated)
-    }
-
-    fn build_search_algorithm_for_choice(
-        choice: EvalDeviceChoice,
-        should_stop: Arc<AtomicBool>,
-    ) -> Result<(SearchAlgorithm, Device, Option<String>), String> {
-        let target = resolve_device_for_choice(choice);
-        let mut warning = device_choice_warning(choice, target);
-
-        match Self::build_search_algorithm_on_device(target, Arc::clone(&should_stop)) {
-            Ok(search_algorithm) => Ok((search_algorithm, target, warning)),
-            Err(err) if target != Device::Cpu => {
-                warning = Some(match warning {
-                    Some(existing) => {
-                        format!("{existing}; fallback to cpu because load failed: {err}")
-                    }
-                    None => format!(
-                        "falling back to cpu because loading models on {} failed: {}",
-                        device_to_label(target),
-                        err
-                    ),
-                });
-                let search_algorithm =
-                    Self::build_search_algorithm_on_device(Device::Cpu, should_stop)?;
-                Ok((search_algorithm, Device::Cpu, warning))
+        if needs_accurate_model(mode)
+            && self.evaluators.accurate.is_none()
+            && !self.evaluators.accurate_failed
+        {
+            let evaluator = Self::load_model_module(
+                "accurate",
+                self.model_paths.accurate.as_deref(),
+                EMBEDDED_ACCURATE_MODEL,
+                ACCURATE_MODEL_PATH,
+                LEGACY_LARGE_MODEL_PATH,
+                device,
+            )
+            .and_then(|evaluator| self.prepare_evaluator(evaluator, device, "accurate"));
+            match evaluator {
+                Ok(evaluator) => {
+                    self.evaluators.accurate = Some(Arc::new(evaluator));
+                    self.evaluators.device = Some(device);
+                }
+                Err(err) => {
+                    self.evaluators.accurate_failed = true;
+                    errors.push(err);
+                }
             }
-            Err(err) => Err(err),
         }
+        errors
     }
 
-    fn build_search_algorithm_on_device(
+    fn prepare_evaluator(
+        &self,
+        evaluator: Evaluator,
         device: Device,
-        should_stop: Arc<AtomicBool>,
-    ) -> Result<SearchAlgorithm, String> {
-        let small_evaluator =
-            Self::load_model_module("fast", FAST_MODEL_PATH, LEGACY_SMALL_MODEL_PATH, device)?;
-        let large_evaluator = Self::load_model_module(
-            "accurate",
-            ACCURATE_MODEL_PATH,
-            LEGACY_LARGE_MODEL_PATH,
-            device,
-        )?;
-
-        Self::probe_model_forward(&small_evaluator, device, "fast")?;
-        Self::probe_model_forward(&large_evaluator, device, "accurate")?;
-
-        let small_evaluator = Arc::new(small_evaluator);
-        let large_evaluator = Arc::new(large_evaluator);
-        Ok(SearchAlgorithm::new(
-            small_evaluator,
-            large_evaluator,
-            device,
-            should_stop,
-        ))
+        label: &str,
+    ) -> Result<Evaluator, String> {
+        Self::probe_model_forward(&evaluator, device, label)?;
+        let evaluator = evaluator
+            .with_precision(self.precision, device)
+            .map_err(|err| {
+                format!(
+                    "{label} model {} conversion failed: {err}",
+                    self.precision.as_str()
+                )
+            })?;
+        println!(
+            "info string {label}_model_metadata {} precision={} source={}",
+            evaluator.metadata(),
+            evaluator.precision().as_str(),
+            evaluator.metadata_source()
+        );
+        Ok(evaluator)
+    }
+
+    fn unload_evaluators(&mut self, fast: bool, accurate: bool) {
+        self.stop_and_join_search();
+        if fast {
+            self.evaluators.fast = None;
+            self.evaluators.fast_failed = false;
+        }
+        if accurate {
+            self.evaluators.accurate = None;
+            self.evaluators.accurate_failed = false;
+        }
+        if self.evaluators.fast.is_none() && self.evaluators.accurate.is_none() {
+            self.evaluators.device = None;
+        }
+        self.search_algorithm = None;
     }
 
+    /// A configured path always wins; otherwise the network embedded at build time is
+    /// used when available, then the default files under `models/`.
     fn load_model_module(
         label: &str,
+        configured_path: Option<&str>,
+        embedded: Option<EmbeddedModel>,
         primary_path: &str,
         legacy_path: &str,
         device: Device,
-    ) -> Result<CModule, String> {
-        let load_from = |path: &str| {
-            CModule::load_on_device(path, device).map_err(|err| {
+    ) -> Result<Evaluator, String> {
+        let load_from = |path: &Path| {
+            Evaluator::load(path, device).map_err(|err| {
                 format!(
-                    "{label} model load failed on {} from `{path}`: {err}",
-                    device_to_label(device)
+                    "{label} model load failed on {} from `{}`: {err}",
+                    device_to_label(device),
+                    path.display()
                 )
             })
         };
 
-        if Path::new(primary_path).exists() {
-            return load_from(primary_path);
+        if let Some(configured) = configured_path {
+            return load_from(&resolve_model_path(configured));
         }
 
-        if Path::new(legacy_path).exists() {
+        if let Some(embedded) = embedded {
+            return Evaluator::load_data(embedded.bytes, Some(embedded.metadata), device).map_err(
+                |err| {
+                    format!(
+                        "{label} model load failed on {} from {EMBEDDED_MODEL_LABEL}: {err}",
+                        device_to_label(device)
+                    )
+                },
+            );
+        }
+
+        let primary = resolve_model_path(primary_path);
+        if primary.exists() {
+            return load_from(&primary);
+        }
+
+        let legacy = resolve_model_path(legacy_path);
+        if legacy.exists() {
             eprintln!(
                 "[engine] deprecated model filename `{}` detected; rename to `{}`",
                 legacy_path, primary_path
             );
-            return load_from(legacy_path);
+            return load_from(&legacy);
         }
 
-        load_from(primary_path).map_err(|err| {
+        load_from(&primary).map_err(|err| {
             format!(
                 "{err}; expected `{}` (legacy `{}` is also accepted)",
                 primary_path, legacy_path
@@ -480,32 +1102,39 @@ impl Engine {
         })
     }
 
-    fn probe_model_forward(model: &CModule, device: Device, label: &str) -> Result<(), String> {
-        let probe = tch::Tensor::from_slice(&[0_f32; 775])
-            .view([1, 775])
-            .to_device(device);
-        let _ = model.forward_ts(&[probe]).map_err(|err| {
+    fn probe_model_forward(
+        evaluator: &Evaluator,
+        device: Device,
+        label: &str,
+    ) -> Result<(), String> {
+        evaluator.probe(device).map_err(|err| {
             format!(
                 "{} model forward probe failed on {}: {}",
                 label,
                 device_to_label(device),
                 err
             )
-        })?;
-        Ok(())
+        })
     }
 }
 
-#[derive(Default)]
-struct GoOptions {
-    depth: Option<u32>,
-    movetime_ms: Option<u64>,
-    wtime_ms: Option<u64>,
-    btime_ms: Option<u64>,
-    winc_ms: Option<u64>,
-    binc_ms: Option<u64>,
-    movestogo: Option<u32>,
-    infinite: bool,
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn print_bench_position(index: usize, fen: &str, result: &SearchResult) {
+    println!(
+        "info string bench position={}/{} depth={} score_cp={} nodes={} bestmove={} fen={}",
+        index + 1,
+        BENCH_FENS.len(),
+        result.depth,
+        result.score_cp,
+        result.nodes,
+        result.best_move,
+        fen
+    );
 }
 
 fn parse_option_parts(command: &str) -> Option<(String, String)> {
@@ -544,6 +1173,57 @@ fn parse_option_parts(command: &str) -> Option<(String, String)> {
     }
 }
 
+fn needs_fast_model(mode: ModelMode) -> bool {
+    !matches!(mode, ModelMode::Large | ModelMode::Classical)
+}
+
+fn needs_accurate_model(mode: ModelMode) -> bool {
+    !matches!(mode, ModelMode::Small | ModelMode::Classical)
+}
+
+fn model_source_label<'a>(
+    configured_path: Option<&'a str>,
+    default_path: &'a str,
+    embedded: Option<EmbeddedModel>,
+) -> &'a str {
+    match (configured_path, embedded) {
+        (Some(path), _) => path,
+        (None, Some(_)) => EMBEDDED_MODEL_LABEL,
+        (None, None) => default_path,
+    }
+}
+
+fn configured_model_path(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() || value.eq_ignore_ascii_case(DEFAULT_PATH_VALUE) {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Relative model paths are tried against the working directory first, then against the
+/// executable's directory and its parents, so GUIs that launch the engine from elsewhere
+/// still find `models/`.
+fn resolve_model_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() || path.exists() {
+        return path.to_path_buf();
+    }
+
+    if let Ok(executable) = std::env::current_exe() {
+        let executable = executable.canonicalize().unwrap_or(executable);
+        for dir in executable.ancestors().skip(1).take(EXECUTABLE_SEARCH_DEPTH) {
+            let candidate = dir.join(path);
+            if candidate.exists() {
+                return candidate;
+            }
+        }
+    }
+
+    path.to_path_buf()
+}
+
 fn parse_bool(value: &str) -> Option<bool> {
     match value.trim().to_ascii_lowercase().as_str() {
         "true" | "1" | "on" | "yes" => Some(true),
@@ -552,10 +1232,6 @@ fn parse_bool(value: &str) -> Option<bool> {
     }
 }
 
-fn safety_margin(time_ms: u64) -> u64 {
-    (time_ms / 25).clamp(5, 50)
-}
-
 fn resolve_device_for_choice(choice: EvalDeviceChoice) -> Device {
     match choice {
         EvalDeviceChoice::Auto => {
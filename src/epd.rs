@@ -0,0 +1,279 @@
+use crate::search_algorithm::{SearchAlgorithm, SearchOptions, SearchRequest};
+use crate::time_manager::{plan_search, GoOptions};
+use crate::utils::{board_from_epd, move_to_san, parse_move};
+use pleco::{BitMove, Board};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+/// One test position: the `bm` moves solve it, the `am` moves fail it.
+#[derive(Clone, Debug)]
+pub struct EpdRecord {
+    pub id: String,
+    pub board: Board,
+    pub best_moves: Vec<BitMove>,
+    pub avoid_moves: Vec<BitMove>,
+}
+
+impl EpdRecord {
+    /// Parses one EPD line. Blank lines and `#` comments yield `Ok(None)`; positions
+    /// without an `id` are named `line<N>`, and positions with neither `bm` nor `am`
+    /// cannot be scored and are an error. Opcodes other than `bm`, `am` and `id` are
+    /// ignored.
+    pub fn parse(line: &str, line_number: usize) -> Result<Option<Self>, String> {
+        let Some(board) = board_from_epd(line) else {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                return Ok(None);
+            }
+            return Err(format!("line {line_number}: invalid EPD position"));
+        };
+
+        let mut record = Self {
+            id: format!("line{line_number}"),
+            board,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+        };
+        for operation in split_operations(operations(line)) {
+            let operation = operation.trim();
+            let (opcode, operands) = operation
+                .split_once(char::is_whitespace)
+                .unwrap_or((operation, ""));
+            match opcode {
+                "id" => record.id = operands.trim().trim_matches('"').to_string(),
+                "bm" | "am" => {
+                    for text in operands.split_whitespace() {
+                        let mv = parse_move(&record.board, text).ok_or_else(|| {
+                            format!("line {line_number}: `{text}` is not a legal move")
+                        })?;
+                        if opcode == "bm" {
+                            record.best_moves.push(mv);
+                        } else {
+                            record.avoid_moves.push(mv);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if record.best_moves.is_empty() && record.avoid_moves.is_empty() {
+            return Err(format!(
+                "line {line_number}: neither `bm` nor `am` says what solves the position"
+            ));
+        }
+        Ok(Some(record))
+    }
+
+    pub fn is_solved_by(&self, mv: BitMove) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv))
+            && !self.avoid_moves.contains(&mv)
+    }
+}
+
+/// Everything after the four position fields and any move counters.
+fn operations(line: &str) -> &str {
+    let mut rest = line.trim_start();
+    for field in 0..6 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if field >= 4 && rest[..end].parse::<u32>().is_err() {
+            break;
+        }
+        rest = rest[end..].trim_start();
+    }
+    rest
+}
+
+/// Splits the operations at the `;` that end them, leaving those inside quoted strings.
+fn split_operations(operations: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (index, c) in operations.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&operations[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&operations[start..]);
+    parts
+}
+
+pub fn parse_epd_suite(contents: &str) -> Result<Vec<EpdRecord>, String> {
+    let mut records = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if let Some(record) = EpdRecord::parse(line, index + 1)? {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl ReportFormat {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Per-position search limits; the ones given all apply, and with none `go`'s default
+/// move time is used.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SuiteLimits {
+    pub depth: Option<u32>,
+    pub movetime_ms: Option<u64>,
+    pub nodes: Option<u64>,
+}
+
+impl SuiteLimits {
+    pub fn request(&self, board: &Board) -> SearchRequest {
+        let go_options = GoOptions {
+            depth: self.depth,
+            movetime_ms: self.movetime_ms,
+            nodes: self.nodes,
+            ..GoOptions::default()
+        };
+        plan_search(&go_options, board.turn(), 0).request
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PositionOutcome {
+    pub id: String,
+    pub fen: String,
+    /// Moves in SAN.
+    pub played: String,
+    pub best_moves: Vec<String>,
+    pub avoid_moves: Vec<String>,
+    pub solved: bool,
+    pub depth: u32,
+    pub nodes: u64,
+    pub score_cp: i32,
+    pub time_ms: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SuiteReport {
+    pub outcomes: Vec<PositionOutcome>,
+    pub elapsed: Duration,
+}
+
+#[derive(Serialize)]
+struct JsonSummary {
+    solved: usize,
+    total: usize,
+    solve_rate: f64,
+    time_ms: u64,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    positions: &'a [PositionOutcome],
+    summary: JsonSummary,
+}
+
+impl SuiteReport {
+    pub fn solved(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.solved)
+            .count()
+    }
+
+    fn summary(&self) -> JsonSummary {
+        let total = self.outcomes.len();
+        JsonSummary {
+            solved: self.solved(),
+            total,
+            solve_rate: (self.solved() as f64 * 1000.0 / total.max(1) as f64).round() / 10.0,
+            time_ms: self.elapsed.as_millis() as u64,
+        }
+    }
+
+    /// One line per failed position, then the solve count.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for outcome in self.outcomes.iter().filter(|outcome| !outcome.solved) {
+            let _ = write!(text, "failed {} played {}", outcome.id, outcome.played);
+            if !outcome.best_moves.is_empty() {
+                let _ = write!(text, " bm {}", outcome.best_moves.join(" "));
+            }
+            if !outcome.avoid_moves.is_empty() {
+                let _ = write!(text, " am {}", outcome.avoid_moves.join(" "));
+            }
+            let _ = writeln!(text, " fen {}", outcome.fen);
+        }
+        let summary = self.summary();
+        let _ = write!(
+            text,
+            "solved {}/{} ({:.1}%) in {} ms",
+            summary.solved, summary.total, summary.solve_rate, summary.time_ms
+        );
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&JsonReport {
+            positions: &self.outcomes,
+            summary: self.summary(),
+        })
+        .expect("suite reports always serialize")
+    }
+}
+
+/// Searches every record with `limits` from a cleared searcher and checks the moves
+/// played against its `bm`/`am` opcodes.
+pub fn run_suite(
+    searcher: &SearchAlgorithm,
+    options: &SearchOptions,
+    records: &[EpdRecord],
+    limits: SuiteLimits,
+    mut on_outcome: impl FnMut(&PositionOutcome),
+) -> SuiteReport {
+    let start = Instant::now();
+    let mut outcomes = Vec::with_capacity(records.len());
+    for record in records {
+        let board = &record.board;
+        searcher.clear();
+        let result =
+            searcher.search_quiet(board, limits.request(board), options, &[board.zobrist()]);
+        let san = |moves: &[BitMove]| moves.iter().map(|&mv| move_to_san(board, mv)).collect();
+        let outcome = PositionOutcome {
+            id: record.id.clone(),
+            fen: board.fen(),
+            played: if result.best_move.is_null() {
+                "none".to_string()
+            } else {
+                move_to_san(board, result.best_move)
+            },
+            best_moves: san(&record.best_moves),
+            avoid_moves: san(&record.avoid_moves),
+            solved: !result.best_move.is_null() && record.is_solved_by(result.best_move),
+            depth: result.depth,
+            nodes: result.nodes,
+            score_cp: result.score_cp,
+            time_ms: result.elapsed.as_millis() as u64,
+        };
+        on_outcome(&outcome);
+        outcomes.push(outcome);
+    }
+    SuiteReport {
+        outcomes,
+        elapsed: start.elapsed(),
+    }
+}
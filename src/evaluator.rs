@@ -0,0 +1,572 @@
+use crate::features::FeatureSet;
+#[cfg(feature = "onnx")]
+use crate::onnx::OnnxModel;
+use crate::quantized::{OutputActivation, QuantizedMlp};
+use pleco::{Board, Player};
+use serde::Deserialize;
+use std::fmt;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use tch::{CModule, Device, IValue, Kind, Tensor};
+
+/// Newest sidecar layout this build understands.
+pub const METADATA_FORMAT_VERSION: u32 = 1;
+/// Scale every network shipped before metadata existed was trained with.
+pub const LEGACY_OUTPUT_SCALE_CP: f32 = 2_500.0;
+const METADATA_METHOD: &str = "metadata";
+/// Largest score gap tolerated between a reduced-precision network and its f32 original
+/// on any probe position. Rounding costs a few centipawns; a misread architecture (a
+/// missing `output_activation`, a hidden activation other than ReLU, layers in the wrong
+/// order) costs far more on at least one of these positions.
+const MAX_PRECISION_PROBE_ERROR_CP: f32 = 30.0;
+/// Name of the TorchScript method that returns policy logits for `ModelMetadata::policy`.
+const POLICY_METHOD: &str = "policy";
+/// Openings, middlegames and endings with either side to move and either side ahead.
+const PRECISION_PROBE_FENS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+    "r2q1rk1/pp1b1ppp/2n1pn2/2bp4/2P5/2NP1NP1/PP2PPBP/R1BQ1RK1 w - - 0 8",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "2r3k1/5ppp/p3p3/1p1n4/3P4/P3BP2/1P3KPP/2R5 b - - 0 28",
+    "8/2k5/3p4/p2P1p2/P2P1P2/8/6K1/8 w - - 0 50",
+    "6k1/5p2/6p1/8/7p/8/6PP/3Q2K1 b - - 0 45",
+    "3qr1k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 30",
+];
+
+/// Describes what a network expects as input and how to read its output. Stored next to
+/// the TorchScript file as `<model>.json`, or returned as a JSON string by a `metadata()`
+/// method compiled into the module.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ModelMetadata {
+    #[serde(default = "default_format_version")]
+    pub format_version: u32,
+    pub feature_set: u32,
+    pub input_size: usize,
+    /// Centipawns per unit of raw network output, from White's point of view (the side to
+    /// move's for side-relative feature sets).
+    pub output_scale_cp: f32,
+    /// Activation after the last linear layer; only the int8 path needs to know it.
+    #[serde(default)]
+    pub output_activation: OutputActivation,
+    #[serde(default)]
+    pub training_data: Option<String>,
+    /// Layout of the optional policy head, served by a `policy()` method taking the same
+    /// input as `forward()`.
+    #[serde(default)]
+    pub policy: Option<PolicyFormat>,
+}
+
+/// Move encodings a policy head can use.
+///
+/// * `FromTo`: 64 x 64 logits indexed by `from * 64 + to` (a1 = 0), promotions sharing
+///   the logit of their squares. Side-relative feature sets index the mirrored board.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyFormat {
+    FromTo,
+}
+
+impl PolicyFormat {
+    pub fn size(self) -> usize {
+        match self {
+            Self::FromTo => 64 * 64,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::FromTo => "from_to",
+        }
+    }
+}
+
+fn default_format_version() -> u32 {
+    METADATA_FORMAT_VERSION
+}
+
+impl ModelMetadata {
+    pub fn legacy() -> Self {
+        Self {
+            format_version: METADATA_FORMAT_VERSION,
+            feature_set: FeatureSet::V1.version(),
+            input_size: FeatureSet::V1.input_size(),
+            output_scale_cp: LEGACY_OUTPUT_SCALE_CP,
+            output_activation: OutputActivation::None,
+            training_data: None,
+            policy: None,
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|err| format!("invalid model metadata: {err}"))
+    }
+
+    pub fn validate(&self) -> Result<FeatureSet, String> {
+        if self.format_version > METADATA_FORMAT_VERSION {
+            return Err(format!(
+                "model metadata format_version {} is newer than supported {}",
+                self.format_version, METADATA_FORMAT_VERSION
+            ));
+        }
+        let feature_set = FeatureSet::from_version(self.feature_set)
+            .ok_or_else(|| format!("unsupported feature_set {}", self.feature_set))?;
+        if self.input_size != feature_set.input_size() {
+            return Err(format!(
+                "input_size {} does not match feature_set {} ({} inputs)",
+                self.input_size,
+                self.feature_set,
+                feature_set.input_size()
+            ));
+        }
+        if !self.output_scale_cp.is_finite() || self.output_scale_cp <= 0.0 {
+            return Err(format!(
+                "output_scale_cp must be positive, got {}",
+                self.output_scale_cp
+            ));
+        }
+        Ok(feature_set)
+    }
+}
+
+impl fmt::Display for ModelMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "feature_set={} input_size={} output_scale_cp={}",
+            self.feature_set, self.input_size, self.output_scale_cp
+        )?;
+        if self.output_activation == OutputActivation::Tanh {
+            write!(f, " output_activation=tanh")?;
+        }
+        if let Some(policy) = self.policy {
+            write!(f, " policy={}", policy.as_str())?;
+        }
+        if let Some(training_data) = &self.training_data {
+            write!(f, " training_data=\"{training_data}\"")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataSource {
+    Sidecar(PathBuf),
+    Embedded,
+    TorchScript,
+    Legacy,
+}
+
+impl fmt::Display for MetadataSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sidecar(path) => write!(f, "{}", path.display()),
+            Self::Embedded => write!(f, "embedded"),
+            Self::TorchScript => write!(f, "torchscript"),
+            Self::Legacy => write!(f, "legacy-defaults"),
+        }
+    }
+}
+
+/// Numeric precision used for inference.
+///
+/// * `F32`: the TorchScript module as exported.
+/// * `F16`: the module converted to half precision; mainly useful on CUDA.
+/// * `Int8`: weights quantized to int8 with one scale per layer and evaluated by a
+///   native CPU implementation that skips libtorch and zero inputs. The arithmetic is
+///   f32, so int8 only compresses the weights; it is not integer inference.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Precision {
+    #[default]
+    F32,
+    F16,
+    Int8,
+}
+
+impl Precision {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "f32" => Some(Self::F32),
+            "f16" => Some(Self::F16),
+            "int8" => Some(Self::Int8),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::F16 => "f16",
+            Self::Int8 => "int8",
+        }
+    }
+}
+
+/// Runtime that executes the network.
+enum Backend {
+    TorchScript(CModule),
+    #[cfg(feature = "onnx")]
+    Onnx(OnnxModel),
+}
+
+impl Backend {
+    fn torchscript_mut(&mut self) -> Option<&mut CModule> {
+        match self {
+            Backend::TorchScript(module) => Some(module),
+            #[cfg(feature = "onnx")]
+            Backend::Onnx(_) => None,
+        }
+    }
+}
+
+/// A loaded network together with the metadata it was validated against.
+pub struct Evaluator {
+    backend: Backend,
+    metadata: ModelMetadata,
+    metadata_source: MetadataSource,
+    feature_set: FeatureSet,
+    precision: Precision,
+    quantized: Option<QuantizedMlp>,
+}
+
+impl Evaluator {
+    /// Loads a TorchScript file, or an ONNX file when the path ends in `.onnx`, reading
+    /// `<model>.json` next to it when present.
+    pub fn load(path: &Path, device: Device) -> Result<Self, String> {
+        let sidecar = read_sidecar(path)?;
+        if is_onnx_path(path) {
+            return Self::load_onnx(path, sidecar);
+        }
+
+        let module = CModule::load_on_device(path, device).map_err(|err| err.to_string())?;
+        match sidecar {
+            Some((metadata, source)) => {
+                Self::with_metadata(Backend::TorchScript(module), metadata, source)
+            }
+            None => Self::with_module_metadata(module),
+        }
+    }
+
+    /// ONNX networks always run on the CPU through tract.
+    #[cfg(feature = "onnx")]
+    fn load_onnx(
+        path: &Path,
+        sidecar: Option<(ModelMetadata, MetadataSource)>,
+    ) -> Result<Self, String> {
+        let (metadata, source) =
+            sidecar.unwrap_or_else(|| (ModelMetadata::legacy(), MetadataSource::Legacy));
+        metadata.validate()?;
+        if metadata.policy.is_some() {
+            return Err("policy heads are only supported for TorchScript models".to_string());
+        }
+        let model = OnnxModel::load(path, metadata.input_size)?;
+        Self::with_metadata(Backend::Onnx(model), metadata, source)
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    fn load_onnx(
+        path: &Path,
+        _sidecar: Option<(ModelMetadata, MetadataSource)>,
+    ) -> Result<Self, String> {
+        Err(format!(
+            "`{}` is an ONNX model; rebuild with `--features onnx` to load it",
+            path.display()
+        ))
+    }
+
+    /// Loads a network baked into the binary along with its optional sidecar text.
+    pub fn load_data(bytes: &[u8], metadata: Option<&str>, device: Device) -> Result<Self, String> {
+        let module = CModule::load_data_on_device(&mut Cursor::new(bytes), device)
+            .map_err(|err| err.to_string())?;
+        match metadata {
+            Some(text) => {
+                let metadata = ModelMetadata::from_json(text)?;
+                Self::with_metadata(
+                    Backend::TorchScript(module),
+                    metadata,
+                    MetadataSource::Embedded,
+                )
+            }
+            None => Self::with_module_metadata(module),
+        }
+    }
+
+    fn with_module_metadata(module: CModule) -> Result<Self, String> {
+        match module.method_is::<IValue>(METADATA_METHOD, &[]) {
+            Ok(IValue::String(text)) => {
+                let metadata = ModelMetadata::from_json(&text)?;
+                Self::with_metadata(
+                    Backend::TorchScript(module),
+                    metadata,
+                    MetadataSource::TorchScript,
+                )
+            }
+            _ => Self::with_metadata(
+                Backend::TorchScript(module),
+                ModelMetadata::legacy(),
+                MetadataSource::Legacy,
+            ),
+        }
+    }
+
+    fn with_metadata(
+        backend: Backend,
+        metadata: ModelMetadata,
+        metadata_source: MetadataSource,
+    ) -> Result<Self, String> {
+        let feature_set = metadata.validate()?;
+        Ok(Self {
+            backend,
+            metadata,
+            metadata_source,
+            feature_set,
+            precision: Precision::F32,
+            quantized: None,
+        })
+    }
+
+    /// Switches inference to `precision`, checking the converted network still agrees
+    /// with the f32 one on a set of reference positions.
+    pub fn with_precision(mut self, precision: Precision, device: Device) -> Result<Self, String> {
+        if precision == self.precision {
+            return Ok(self);
+        }
+        let mut buffer = vec![0_f32; self.metadata.input_size];
+        let mut probes = Vec::with_capacity(PRECISION_PROBE_FENS.len());
+        for fen in PRECISION_PROBE_FENS {
+            let board = Board::from_fen(fen).expect("valid probe FEN");
+            let features = self.feature_set.encode(&board, &mut buffer).to_vec();
+            let reference = self.try_raw_output(&features, device)?;
+            probes.push((fen, features, reference));
+        }
+
+        let Some(module) = self.backend.torchscript_mut() else {
+            return Err(format!(
+                "{} is only supported for TorchScript models",
+                precision.as_str()
+            ));
+        };
+        match precision {
+            Precision::F32 => module.to(device, Kind::Float, false),
+            Precision::F16 => module.to(device, Kind::Half, false),
+            Precision::Int8 => {
+                self.quantized = Some(QuantizedMlp::from_module(
+                    module,
+                    self.metadata.input_size,
+                    self.metadata.output_activation,
+                )?);
+            }
+        }
+        if precision != Precision::Int8 {
+            self.quantized = None;
+        }
+        self.precision = precision;
+
+        let scale_cp = self.metadata.output_scale_cp;
+        for (fen, features, reference) in probes {
+            let converted = self.try_raw_output(&features, device)?;
+            let error_cp = (converted - reference).abs() * scale_cp;
+            if error_cp > MAX_PRECISION_PROBE_ERROR_CP {
+                return Err(format!(
+                    "{} network disagrees with f32 by {error_cp:.0} cp on `{fen}` ({:.0} vs {:.0} cp)",
+                    precision.as_str(),
+                    converted * scale_cp,
+                    reference * scale_cp
+                ));
+            }
+        }
+        Ok(self)
+    }
+
+    /// The TorchScript module, or `None` for networks served by another runtime.
+    pub fn module(&self) -> Option<&CModule> {
+        match &self.backend {
+            Backend::TorchScript(module) => Some(module),
+            #[cfg(feature = "onnx")]
+            Backend::Onnx(_) => None,
+        }
+    }
+
+    pub fn metadata(&self) -> &ModelMetadata {
+        &self.metadata
+    }
+
+    pub fn metadata_source(&self) -> &MetadataSource {
+        &self.metadata_source
+    }
+
+    pub fn feature_set(&self) -> FeatureSet {
+        self.feature_set
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Runs an all-zero input through the network and checks it yields a single value
+    /// (and a full set of policy logits when the metadata declares a policy head), which
+    /// catches networks whose input width disagrees with their metadata.
+    pub fn probe(&self, device: Device) -> Result<(), String> {
+        let zeros = vec![0_f32; self.metadata.input_size];
+        let Some(module) = self.module() else {
+            // Other runtimes reject non-scalar outputs themselves.
+            return self.try_raw_output(&zeros, device).map(|_| ());
+        };
+        let output = module
+            .forward_ts(&[self.input_tensor(&zeros, device)])
+            .map_err(|err| err.to_string())?;
+        if output.numel() != 1 {
+            return Err(format!(
+                "expected a scalar output, got shape {:?}",
+                output.size()
+            ));
+        }
+        if let Some(policy) = self.metadata.policy {
+            let logits = self.try_policy_logits(&zeros, device)?;
+            if logits.len() != policy.size() {
+                return Err(format!(
+                    "expected {} policy logits, got {}",
+                    policy.size(),
+                    logits.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_policy(&self) -> bool {
+        self.metadata.policy.is_some()
+    }
+
+    /// Policy logits for `board` indexed by `from * 64 + to` on the real board, or `None`
+    /// for value-only networks. Encodes into `buffer` like `evaluate`.
+    pub fn policy(&self, board: &Board, buffer: &mut [f32], device: Device) -> Option<Vec<f32>> {
+        let policy = self.metadata.policy?;
+        let features = self.feature_set.encode(board, buffer);
+        let logits = self.try_policy_logits(features, device).ok()?;
+        if logits.len() != policy.size() {
+            return None;
+        }
+        if !(self.feature_set.is_side_relative() && board.turn() == Player::Black) {
+            return Some(logits);
+        }
+        // The network saw the board flipped vertically; map its squares back.
+        let mut unmirrored = vec![0.0; logits.len()];
+        for (index, logit) in logits.into_iter().enumerate() {
+            let (from, to) = (index / 64, index % 64);
+            unmirrored[(from ^ 56) * 64 + (to ^ 56)] = logit;
+        }
+        Some(unmirrored)
+    }
+
+    fn try_policy_logits(&self, features: &[f32], device: Device) -> Result<Vec<f32>, String> {
+        let module = self
+            .module()
+            .ok_or_else(|| "policy heads are only supported for TorchScript models".to_string())?;
+        let logits = module
+            .method_ts(POLICY_METHOD, &[self.input_tensor(features, device)])
+            .map_err(|err| format!("policy head failed: {err}"))?;
+        Vec::<f32>::try_from(
+            &logits
+                .to_device(Device::Cpu)
+                .to_kind(Kind::Float)
+                .view([-1]),
+        )
+        .map_err(|err| format!("failed to read policy logits: {err}"))
+    }
+
+    /// Unscaled network output for features encoded with `feature_set()`.
+    pub fn raw_output(&self, features: &[f32], device: Device) -> f32 {
+        self.try_raw_output(features, device).unwrap_or(0.0)
+    }
+
+    fn try_raw_output(&self, features: &[f32], device: Device) -> Result<f32, String> {
+        if let Some(quantized) = &self.quantized {
+            return Ok(quantized.forward(features));
+        }
+        match &self.backend {
+            Backend::TorchScript(module) => module
+                .forward_ts(&[self.input_tensor(features, device)])
+                .map(|tensor| tensor.double_value(&[]) as f32)
+                .map_err(|err| err.to_string()),
+            #[cfg(feature = "onnx")]
+            Backend::Onnx(model) => model.forward(features),
+        }
+    }
+
+    /// Scores `board` in centipawns from the side to move's point of view, encoding into
+    /// `buffer` (at least `MAX_INPUT_SIZE` long).
+    pub fn evaluate(&self, board: &Board, buffer: &mut [f32], device: Device) -> i32 {
+        let features = self.feature_set.encode(board, buffer);
+        let cp = (self.raw_output(features, device) * self.metadata.output_scale_cp).round() as i32;
+        if board.turn() == Player::Black && !self.feature_set.is_side_relative() {
+            -cp
+        } else {
+            cp
+        }
+    }
+
+    fn input_tensor(&self, features: &[f32], device: Device) -> Tensor {
+        let input = Tensor::from_slice(features)
+            .view([1, self.metadata.input_size as i64])
+            .to_device(device);
+        if self.precision == Precision::F16 {
+            input.to_kind(Kind::Half)
+        } else {
+            input
+        }
+    }
+}
+
+/// How far a reduced-precision evaluator strays from its f32 reference.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PrecisionReport {
+    pub positions: usize,
+    pub mean_abs_error_cp: f64,
+    pub max_abs_error_cp: i32,
+}
+
+/// Scores every board with both evaluators and summarizes the side-to-move differences.
+pub fn compare_precision(
+    reference: &Evaluator,
+    candidate: &Evaluator,
+    boards: &[Board],
+    device: Device,
+) -> PrecisionReport {
+    let mut buffer = [0_f32; crate::features::MAX_INPUT_SIZE];
+    let mut report = PrecisionReport::default();
+    let mut total_error = 0_i64;
+    for board in boards {
+        let error = (reference.evaluate(board, &mut buffer, device)
+            - candidate.evaluate(board, &mut buffer, device))
+        .abs();
+        total_error += error as i64;
+        report.max_abs_error_cp = report.max_abs_error_cp.max(error);
+        report.positions += 1;
+    }
+    if report.positions > 0 {
+        report.mean_abs_error_cp = total_error as f64 / report.positions as f64;
+    }
+    report
+}
+
+pub fn sidecar_path(model_path: &Path) -> PathBuf {
+    model_path.with_extension("json")
+}
+
+fn read_sidecar(model_path: &Path) -> Result<Option<(ModelMetadata, MetadataSource)>, String> {
+    let sidecar = sidecar_path(model_path);
+    if !sidecar.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&sidecar)
+        .map_err(|err| format!("failed to read `{}`: {err}", sidecar.display()))?;
+    let metadata = ModelMetadata::from_json(&text)
+        .map_err(|err| format!("{err} in `{}`", sidecar.display()))?;
+    Ok(Some((metadata, MetadataSource::Sidecar(sidecar))))
+}
+
+fn is_onnx_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("onnx"))
+}
@@ -0,0 +1,230 @@
+use crate::utils::{encode_board_features, piece_to_offset};
+use pleco::core::CastleType;
+use pleco::helper::prelude::{
+    bishop_moves, king_moves, knight_moves, pawn_attacks_from, queen_moves, rook_moves,
+};
+use pleco::{BitBoard, Board, Piece, PieceType, Player, SQ};
+use std::ops::Range;
+
+pub const V1_INPUT_SIZE: usize = 775;
+pub const V2_INPUT_SIZE: usize = V1_INPUT_SIZE + 64 + 64 + 8 + 1;
+/// Large enough for any feature set, so search buffers can be allocated once.
+pub const MAX_INPUT_SIZE: usize = V2_INPUT_SIZE;
+
+const V2_WHITE_ATTACKS: usize = V1_INPUT_SIZE;
+const V2_BLACK_ATTACKS: usize = V2_WHITE_ATTACKS + 64;
+const V2_EN_PASSANT_FILE: usize = V2_BLACK_ATTACKS + 64;
+const V2_RULE_50: usize = V2_EN_PASSANT_FILE + 8;
+
+/// Network input layouts. A network's metadata names the one it was trained on.
+///
+/// * `V1`: 768 piece-squares, side to move, check flags and castling rights.
+/// * `V2`: `V1` followed by the squares each side attacks (64 + 64), the en passant file
+///   (one-hot, 8) and the half-move clock scaled to `[0, 1]`.
+/// * `V1Mirrored`: the `V1` layout seen from the side to move. With Black to move the
+///   board is flipped vertically and colors are swapped, so "white" always means the side
+///   to move and the network output is already side-to-move relative.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeatureSet {
+    V1,
+    V2,
+    V1Mirrored,
+}
+
+impl FeatureSet {
+    pub fn from_version(version: u32) -> Option<Self> {
+        match version {
+            1 => Some(Self::V1),
+            2 => Some(Self::V2),
+            3 => Some(Self::V1Mirrored),
+            _ => None,
+        }
+    }
+
+    pub fn version(self) -> u32 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+            Self::V1Mirrored => 3,
+        }
+    }
+
+    pub fn input_size(self) -> usize {
+        match self {
+            Self::V1 | Self::V1Mirrored => V1_INPUT_SIZE,
+            Self::V2 => V2_INPUT_SIZE,
+        }
+    }
+
+    /// Whether the network scores from the side to move's point of view instead of White's.
+    pub fn is_side_relative(self) -> bool {
+        self == Self::V1Mirrored
+    }
+
+    /// Named index ranges of the layout, used for diagnostics.
+    pub fn segments(self) -> &'static [(&'static str, Range<usize>)] {
+        const V1_SEGMENTS: [(&str, Range<usize>); 4] = [
+            ("pieces", 0..768),
+            ("turn", 768..769),
+            ("check", 769..771),
+            ("castling", 771..775),
+        ];
+        const V2_SEGMENTS: [(&str, Range<usize>); 8] = [
+            ("pieces", 0..768),
+            ("turn", 768..769),
+            ("check", 769..771),
+            ("castling", 771..775),
+            ("white_attacks", V2_WHITE_ATTACKS..V2_BLACK_ATTACKS),
+            ("black_attacks", V2_BLACK_ATTACKS..V2_EN_PASSANT_FILE),
+            ("en_passant", V2_EN_PASSANT_FILE..V2_RULE_50),
+            ("rule_50", V2_RULE_50..V2_INPUT_SIZE),
+        ];
+        match self {
+            Self::V1 | Self::V1Mirrored => &V1_SEGMENTS,
+            Self::V2 => &V2_SEGMENTS,
+        }
+    }
+
+    /// Writes the features into the front of `features` and returns the filled prefix.
+    pub fn encode<'a>(self, board: &Board, features: &'a mut [f32]) -> &'a [f32] {
+        let size = self.input_size();
+        let features = &mut features[..size];
+        if self == Self::V1Mirrored {
+            encode_mirrored(board, features);
+            return features;
+        }
+
+        let (v1, extra) = features.split_at_mut(V1_INPUT_SIZE);
+        encode_board_features(
+            board,
+            v1.try_into().expect("V1 prefix is exactly V1_INPUT_SIZE"),
+        );
+
+        if self == Self::V2 {
+            extra.fill(0.0);
+            write_attacks(board, Player::White, features, V2_WHITE_ATTACKS);
+            write_attacks(board, Player::Black, features, V2_BLACK_ATTACKS);
+
+            let ep_square = board.ep_square();
+            if ep_square != SQ::NONE {
+                features[V2_EN_PASSANT_FILE + ep_square.file_idx_of_sq() as usize] = 1.0;
+            }
+            features[V2_RULE_50] = (board.rule_50() as f32 / 100.0).min(1.0);
+        }
+
+        features
+    }
+}
+
+fn encode_mirrored(board: &Board, features: &mut [f32]) {
+    features.fill(0.0);
+    let us = board.turn();
+    let flip = us == Player::Black;
+
+    for (sq, piece) in board.get_piece_locations() {
+        let rank = sq.rank_idx_of_sq() as usize;
+        let (rank, piece) = if flip {
+            (7 - rank, swap_color(piece))
+        } else {
+            (rank, piece)
+        };
+        features[piece_to_offset(piece) + sq.file_idx_of_sq() as usize + rank * 8] = 1.0;
+    }
+
+    features[768] = 1.0;
+    if board.in_check() {
+        features[769] = 1.0;
+    }
+    let them = us.other_player();
+    features[771] = board.can_castle(us, CastleType::KingSide) as i32 as f32;
+    features[772] = board.can_castle(us, CastleType::QueenSide) as i32 as f32;
+    features[773] = board.can_castle(them, CastleType::KingSide) as i32 as f32;
+    features[774] = board.can_castle(them, CastleType::QueenSide) as i32 as f32;
+}
+
+fn swap_color(piece: Piece) -> Piece {
+    Piece::make_lossy(piece.player_lossy().other_player(), piece.type_of())
+}
+
+/// The same position with the board flipped vertically, colors swapped and the other side
+/// to move. A color-symmetric evaluation scores it identically from the side to move.
+pub fn color_flipped(board: &Board) -> Option<Board> {
+    let fen = board.fen();
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+
+    let placement = fields[0]
+        .split('/')
+        .rev()
+        .map(swap_case)
+        .collect::<Vec<_>>()
+        .join("/");
+    let turn = if fields[1] == "w" { "b" } else { "w" };
+    let castling = if fields[2] == "-" {
+        "-".to_string()
+    } else {
+        let swapped = swap_case(fields[2]);
+        let mut castling: Vec<char> = swapped.chars().collect();
+        castling.sort_by_key(|&right| "KQkq".find(right));
+        castling.into_iter().collect()
+    };
+    let en_passant = match fields[3] {
+        "-" => "-".to_string(),
+        square => {
+            let (file, rank) = square.split_at(1);
+            format!("{file}{}", if rank == "3" { "6" } else { "3" })
+        }
+    };
+
+    let flipped = format!(
+        "{placement} {turn} {castling} {en_passant} {} {}",
+        fields[4], fields[5]
+    );
+    Board::from_fen(&flipped).ok()
+}
+
+fn swap_case(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_uppercase() {
+                c.to_ascii_lowercase()
+            } else {
+                c.to_ascii_uppercase()
+            }
+        })
+        .collect()
+}
+
+fn write_attacks(board: &Board, player: Player, features: &mut [f32], offset: usize) {
+    let mut attacks = attacked_squares(board, player);
+    while let Some(sq) = attacks.pop_some_lsb() {
+        let sq_idx = (sq.file_idx_of_sq() + sq.rank_idx_of_sq() * 8) as usize;
+        features[offset + sq_idx] = 1.0;
+    }
+}
+
+/// Squares `player` attacks with the current occupancy, ignoring pins.
+pub fn attacked_squares(board: &Board, player: Player) -> BitBoard {
+    let occupied = board.occupied();
+    let mut attacks = BitBoard(0);
+    for piece_type in [
+        PieceType::P,
+        PieceType::N,
+        PieceType::B,
+        PieceType::R,
+        PieceType::Q,
+        PieceType::K,
+    ] {
+        let mut pieces = board.piece_bb(player, piece_type);
+        while let Some(sq) = pieces.pop_some_lsb() {
+            attacks |= match piece_type {
+                PieceType::P => pawn_attacks_from(sq, player),
+                PieceType::N => knight_moves(sq),
+                PieceType::B => bishop_moves(occupied, sq),
+                PieceType::R => rook_moves(occupied, sq),
+                PieceType::Q => queen_moves(occupied, sq),
+                _ => king_moves(sq),
+            };
+        }
+    }
+    attacks
+}
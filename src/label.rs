@@ -0,0 +1,315 @@
+use crate::search_algorithm::{SearchAlgorithm, SearchOptions, SearchRequest};
+use crate::time_manager::{plan_search, GoOptions};
+use crate::training::{DataFormat, TrainingRecord};
+use crate::utils::{board_from_epd, parse_move};
+use pleco::{Board, Player};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+
+pub const DEFAULT_LABEL_DEPTH: u32 = 8;
+pub const DEFAULT_LABEL_HASH_MB: usize = 16;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LabelConfig {
+    /// EPD/FEN lines, or PGN games if the name ends in `.pgn`. Positions from games
+    /// with a known result take that result as their `wdl`; the others get
+    /// `wdl_from_score` of the search score.
+    pub input: String,
+    pub output: String,
+    pub format: DataFormat,
+    /// Where to write the positions' `encode_board_features` rows as a `.npy` file.
+    pub features: Option<String>,
+    /// Search limits per position. Without either, positions are searched to
+    /// `DEFAULT_LABEL_DEPTH`.
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    /// Positions searched at once, each by a single-threaded searcher.
+    pub threads: usize,
+    /// Hash per searcher.
+    pub hash_mb: usize,
+}
+
+impl LabelConfig {
+    pub fn new(input: &str, output: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            output: output.to_string(),
+            format: DataFormat::default(),
+            features: None,
+            depth: None,
+            nodes: None,
+            threads: SearchAlgorithm::default_threads(),
+            hash_mb: DEFAULT_LABEL_HASH_MB,
+        }
+    }
+
+    pub fn request(&self) -> SearchRequest {
+        let go_options = GoOptions {
+            depth: match (self.depth, self.nodes) {
+                (None, None) => Some(DEFAULT_LABEL_DEPTH),
+                (depth, _) => depth,
+            },
+            nodes: self.nodes,
+            ..GoOptions::default()
+        };
+        plan_search(&go_options, Player::White, 0).request
+    }
+}
+
+/// Expected score for the side to move on the 400-point logistic scale Elo uses, to
+/// three decimals.
+pub fn wdl_from_score(score_cp: i32) -> f32 {
+    let expected = 1.0 / (1.0 + 10f64.powf(-f64::from(score_cp) / 400.0));
+    ((expected * 1000.0).round() / 1000.0) as f32
+}
+
+/// A position to label, with the result of the game it was played in when known.
+#[derive(Clone, Debug)]
+pub struct LabelPosition {
+    pub board: Board,
+    /// The game's score for the side to move: 1, 0.5 or 0.
+    pub result: Option<f32>,
+}
+
+impl From<Board> for LabelPosition {
+    fn from(board: Board) -> Self {
+        Self {
+            board,
+            result: None,
+        }
+    }
+}
+
+/// White's score for a PGN result token or `Result` tag; `None` for `*` or anything else.
+fn white_score(result: &str) -> Option<f32> {
+    match result {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => None,
+    }
+}
+
+/// Reads the positions in `path`: PGN games if it ends in `.pgn`, EPD or FEN lines
+/// otherwise. Positions without a legal move are dropped.
+pub fn read_positions(path: &str) -> Result<Vec<LabelPosition>, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|err| format!("cannot read `{path}`: {err}"))?;
+    if path.to_ascii_lowercase().ends_with(".pgn") {
+        return pgn_positions(&contents);
+    }
+    let mut positions = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        match board_from_epd(line) {
+            Some(board) => positions.push(LabelPosition::from(board)),
+            None if line.trim().is_empty() || line.trim().starts_with('#') => {}
+            None => return Err(format!("line {}: invalid EPD position", index + 1)),
+        }
+    }
+    positions.retain(|position| !position.board.generate_moves().is_empty());
+    Ok(positions)
+}
+
+/// Every position of every game in `contents`, from the start (or the `FEN` tag) to the
+/// final position, skipping those without a legal move. Each position carries the
+/// game's result from the closing result token, or the `Result` tag without one.
+/// Comments, variations, NAGs and move numbers are ignored.
+pub fn pgn_positions(contents: &str) -> Result<Vec<LabelPosition>, String> {
+    let mut positions = Vec::new();
+    let mut board = Board::start_pos();
+    let mut games = 0;
+    // Where the current game's positions start, and its `Result` tag.
+    let mut game_start = 0;
+    let mut tag_result = None;
+    // Tags or moves of the current game have been read.
+    let mut in_game = false;
+    let mut in_moves = false;
+    let mut in_comment = false;
+    let mut variation_depth = 0;
+
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if !in_comment && variation_depth == 0 && trimmed.starts_with('[') {
+            if in_moves {
+                finish_game(
+                    &mut positions,
+                    &mut game_start,
+                    &mut board,
+                    &mut in_game,
+                    tag_result,
+                );
+                in_moves = false;
+            }
+            if !in_game {
+                games += 1;
+                in_game = true;
+                tag_result = None;
+            }
+            if let Some(result) = trimmed
+                .strip_prefix("[Result \"")
+                .and_then(|rest| rest.strip_suffix("\"]"))
+            {
+                tag_result = white_score(result);
+            }
+            if let Some(fen) = trimmed
+                .strip_prefix("[FEN \"")
+                .and_then(|rest| rest.strip_suffix("\"]"))
+            {
+                board = Board::from_fen(fen)
+                    .map_err(|_| format!("game {games}: invalid FEN tag `{fen}`"))?;
+            }
+            continue;
+        }
+
+        let mut text = String::with_capacity(line.len());
+        for ch in line.chars() {
+            match ch {
+                _ if in_comment => in_comment = ch != '}',
+                '{' => in_comment = true,
+                '(' => variation_depth += 1,
+                ')' => variation_depth = (variation_depth - 1).max(0),
+                ';' if variation_depth == 0 => break,
+                _ if variation_depth > 0 => {}
+                _ => text.push(ch),
+            }
+        }
+
+        for token in text.split_whitespace() {
+            if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+                let result = white_score(token).or(tag_result);
+                finish_game(
+                    &mut positions,
+                    &mut game_start,
+                    &mut board,
+                    &mut in_game,
+                    result,
+                );
+                in_moves = false;
+                continue;
+            }
+            // Drop move numbers such as `12.` or `12...`, also when glued to the move.
+            let token = match token.rfind('.') {
+                Some(dot)
+                    if token[..dot]
+                        .chars()
+                        .all(|ch| ch.is_ascii_digit() || ch == '.') =>
+                {
+                    &token[dot + 1..]
+                }
+                _ => token,
+            };
+            if token.is_empty() || token.starts_with('$') {
+                continue;
+            }
+            if !in_game {
+                games += 1;
+                in_game = true;
+                tag_result = None;
+            }
+            let mv = parse_move(&board, token)
+                .ok_or_else(|| format!("game {games}: `{token}` is not a legal move"))?;
+            positions.push(LabelPosition::from(board.shallow_clone()));
+            board.apply_move(mv);
+            in_moves = true;
+        }
+    }
+    finish_game(
+        &mut positions,
+        &mut game_start,
+        &mut board,
+        &mut in_game,
+        tag_result,
+    );
+    Ok(positions)
+}
+
+/// Keeps the final position of a game that has started, gives the game's positions
+/// White's score `result` from their side to move's view, and sets up for the next game.
+fn finish_game(
+    positions: &mut Vec<LabelPosition>,
+    game_start: &mut usize,
+    board: &mut Board,
+    in_game: &mut bool,
+    result: Option<f32>,
+) {
+    if *in_game && !board.generate_moves().is_empty() {
+        positions.push(LabelPosition::from(board.shallow_clone()));
+    }
+    for position in &mut positions[*game_start..] {
+        position.result = result.map(|white| match position.board.turn() {
+            Player::White => white,
+            Player::Black => 1.0 - white,
+        });
+    }
+    *game_start = positions.len();
+    *board = Board::start_pos();
+    *in_game = false;
+}
+
+/// Searches every position from cleared tables on `config.threads` independent
+/// searchers, so the labels do not depend on the thread count, and hands the records to
+/// `on_record` in input order. `wdl` is the position's game result when it has one and
+/// `wdl_from_score` otherwise. Stops at the first error `on_record` returns.
+pub fn label_positions(
+    searcher: &SearchAlgorithm,
+    options: &SearchOptions,
+    positions: &[LabelPosition],
+    config: &LabelConfig,
+    mut on_record: impl FnMut(usize, TrainingRecord) -> Result<(), String>,
+) -> Result<(), String> {
+    let options = SearchOptions {
+        threads: 1,
+        hash_mb: config.hash_mb,
+        ..options.clone()
+    };
+    let request = config.request();
+    let next = AtomicUsize::new(0);
+    let abort = AtomicBool::new(false);
+    let (tx, rx) = channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            let tx = tx.clone();
+            let searcher = searcher.independent();
+            let (options, next, abort) = (&options, &next, &abort);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= positions.len() || abort.load(Ordering::Relaxed) {
+                    break;
+                }
+                let LabelPosition {
+                    board,
+                    result: game,
+                } = &positions[index];
+                searcher.clear();
+                let result = searcher.search_quiet(board, request, options, &[board.zobrist()]);
+                let record = TrainingRecord {
+                    fen: board.fen(),
+                    score_cp: result.score_cp,
+                    wdl: game.unwrap_or_else(|| wdl_from_score(result.score_cp)),
+                    best_move: result.best_move,
+                };
+                if tx.send((index, record)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // Workers finish out of order; hold records back until their turn comes.
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (index, record) in rx {
+            pending.insert(index, record);
+            while let Some(record) = pending.remove(&expected) {
+                if let Err(err) = on_record(expected, record) {
+                    abort.store(true, Ordering::Relaxed);
+                    return Err(err);
+                }
+                expected += 1;
+            }
+        }
+        Ok(())
+    })
+}
@@ -1,3 +1,19 @@
+pub mod bench;
+pub mod classical;
+pub mod cli;
 pub mod engine;
+pub mod epd;
+pub mod evaluator;
+pub mod features;
+pub mod label;
+pub mod match_runner;
+pub mod mcts;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod perft;
+pub mod quantized;
 pub mod search_algorithm;
+pub mod selfplay;
+pub mod time_manager;
+pub mod training;
 pub mod utils;
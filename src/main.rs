@@ -8,10 +8,95 @@ use std::sync::{
 use std::thread;
 use std::time::Duration;
 
+use brainstorm::cli::{Cli, Command, USAGE};
 use brainstorm::engine::Engine;
+use brainstorm::match_runner::{print_match, EngineKind, SprtDecision};
+
+/// `match` exits with this status when the match cannot be played.
+const MATCH_FAILED_STATUS: i32 = 1;
+/// `match` exits with this status when the SPRT accepts H0, so scripts can gate on it.
+const SPRT_H0_STATUS: i32 = 3;
 
 fn main() {
-    let engine = Arc::new(Mutex::new(Engine::new()));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cli = match Cli::parse(&args) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    if let Command::Match(mut config) = cli.command {
+        // Options given with `-o` apply to the in-process engines, before their own.
+        for spec in &mut config.engines {
+            if spec.kind == EngineKind::Builtin {
+                spec.options.splice(0..0, cli.options.iter().cloned());
+            }
+        }
+        match print_match(&config) {
+            Ok(report) if report.decision == Some(SprtDecision::AcceptH0) => {
+                std::process::exit(SPRT_H0_STATUS)
+            }
+            Ok(_) => return,
+            Err(err) => {
+                eprintln!("error: {err}");
+                std::process::exit(MATCH_FAILED_STATUS);
+            }
+        }
+    }
+
+    let mut engine = Engine::new();
+    for (name, value) in &cli.options {
+        if let Err(err) = engine.set_option(name, value) {
+            eprintln!("error: {err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    }
+    match cli.command {
+        Command::Uci => run_uci(engine),
+        Command::Bench(config) => engine.bench_with(config),
+        Command::Perft { depth, fen } => {
+            if let Some(fen) = fen {
+                engine.position(&format!("position fen {fen}"));
+            }
+            engine.perft(depth);
+        }
+        Command::Analyse {
+            fen,
+            depth,
+            movetime_ms,
+        } => {
+            engine.position(&format!("position fen {fen}"));
+            let best_move = engine.go_and_wait(&Command::go_command(depth, movetime_ms));
+            print_bestmove(best_move);
+        }
+        Command::Eval { fen } => {
+            engine.position(&format!("position fen {fen}"));
+            engine.eval();
+        }
+        Command::Epd {
+            path,
+            limits,
+            format,
+        } => engine.epd_suite(&path, limits, format),
+        Command::SelfPlay(config) => engine.selfplay(config),
+        Command::Label(config) => engine.label(config),
+        Command::Match(_) => unreachable!("matches are run above"),
+        Command::Help => println!("{USAGE}"),
+    }
+}
+
+fn print_bestmove(best_move: BitMove) {
+    if best_move.is_null() {
+        println!("bestmove 0000");
+    } else {
+        println!("bestmove {}", best_move);
+    }
+}
+
+fn run_uci(engine: Engine) {
+    let engine = Arc::new(Mutex::new(engine));
     let (tx, rx) = channel::<BitMove>();
     let running = Arc::new(AtomicBool::new(true));
 
@@ -41,6 +126,9 @@ fn main() {
                     break;
                 }
                 Some("fen") => println!("{}", engine.board.fen()),
+                Some("eval") => engine.eval(),
+                Some("evalcheck") => engine.evalcheck(&command),
+                Some("bench") => engine.bench(&command),
                 _ => {}
             }
         }
@@ -50,13 +138,11 @@ fn main() {
     while running.load(Ordering::Relaxed) {
         match rx.try_recv() {
             Ok(best_move) => {
-                if best_move.is_null() {
-                    println!("bestmove 0000");
-                } else {
-                    println!("bestmove {}", best_move);
-                }
+                // Apply the move before announcing it, so a `position` sent in reply
+                // cannot reach the engine first.
                 let mut engine = engine.lock().unwrap();
                 engine.make_move(best_move);
+                print_bestmove(best_move);
             }
             Err(TryRecvError::Empty) => {
                 let mut engine = engine.lock().unwrap();
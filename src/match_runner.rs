@@ -0,0 +1,956 @@
+use crate::engine::Engine;
+use crate::selfplay::{
+    adjudicate, game_over, parse_openings, GameResult, Termination, ADJUDICATION_PLIES,
+    DEFAULT_MAX_PLIES,
+};
+use crate::time_manager::GoOptions;
+use crate::utils::{move_to_san, parse_move};
+use pleco::{BitMove, Board, Player};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_TIME_CONTROL: TimeControl = TimeControl {
+    base_ms: 10_000,
+    inc_ms: 100,
+};
+/// A move may overrun the clock by this much before the flag falls, to absorb pipe and
+/// scheduling delays.
+pub const CLOCK_MARGIN_MS: u64 = 50;
+/// Time an engine gets to answer `uci`, `isready` or, after its time is up, `stop`.
+const ENGINE_REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Mate scores reported by UCI engines map to this minus the plies to mate.
+const MATE_REPLY_CP: i32 = 30_000;
+const PGN_LINE_WIDTH: usize = 80;
+/// How often a built-in engine past its deadline is told again to stop.
+const STOP_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Base time and increment per move, written `SECONDS[+INCREMENT]` as in cutechess-cli
+/// and the PGN `TimeControl` tag, e.g. `10+0.1`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimeControl {
+    pub base_ms: u64,
+    pub inc_ms: u64,
+}
+
+impl TimeControl {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(value: &str) -> Option<Self> {
+        let seconds_to_ms = |seconds: &str| {
+            let seconds: f64 = seconds.trim().parse().ok()?;
+            (seconds.is_finite() && seconds >= 0.0).then(|| (seconds * 1000.0).round() as u64)
+        };
+        let (base, inc) = value.split_once('+').unwrap_or((value, "0"));
+        let time_control = Self {
+            base_ms: seconds_to_ms(base)?,
+            inc_ms: seconds_to_ms(inc)?,
+        };
+        (time_control.base_ms > 0).then_some(time_control)
+    }
+
+    pub fn pgn_tag(&self) -> String {
+        let seconds = |ms: u64| format!("{}", ms as f64 / 1000.0);
+        format!("{}+{}", seconds(self.base_ms), seconds(self.inc_ms))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EngineKind {
+    /// An external UCI engine started with this command.
+    Uci { command: String },
+    /// A Brainstorm engine running inside this process.
+    Builtin,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EngineSpec {
+    pub name: String,
+    pub kind: EngineKind,
+    /// UCI options set before the first game.
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineSpec {
+    /// Parses a comma-separated spec: `cmd=PATH` for a UCI engine or `builtin` for an
+    /// in-process one, an optional `name=NAME`, and any other `OPTION=VALUE` pairs as
+    /// UCI options, e.g. `builtin,name=accurate,Model=accurate`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut name = None;
+        let mut kind = None;
+        let mut options = Vec::new();
+        for part in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let duplicate = || format!("engine `{spec}` names more than one engine");
+            match part.split_once('=') {
+                None if part == "builtin" => {
+                    if kind.replace(EngineKind::Builtin).is_some() {
+                        return Err(duplicate());
+                    }
+                }
+                Some(("cmd", command)) => {
+                    let command = command.trim().to_string();
+                    if kind.replace(EngineKind::Uci { command }).is_some() {
+                        return Err(duplicate());
+                    }
+                }
+                Some(("name", value)) => name = Some(value.trim().to_string()),
+                Some((option, value)) => {
+                    options.push((option.trim().to_string(), value.trim().to_string()))
+                }
+                None => return Err(format!("invalid engine setting `{part}`")),
+            }
+        }
+        let kind = kind.ok_or_else(|| format!("engine `{spec}` needs `cmd=PATH` or `builtin`"))?;
+        let name = name.unwrap_or_else(|| match &kind {
+            EngineKind::Builtin => "brainstorm".to_string(),
+            EngineKind::Uci { command } => std::path::Path::new(command)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| command.clone()),
+        });
+        Ok(Self {
+            name,
+            kind,
+            options,
+        })
+    }
+}
+
+/// Sequential probability ratio test of H0 "the first engine is `elo0` stronger" against
+/// H1 "it is `elo1` stronger", with error rates `alpha` and `beta`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SprtConfig {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for SprtConfig {
+    fn default() -> Self {
+        Self {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SprtDecision {
+    AcceptH0,
+    AcceptH1,
+}
+
+impl SprtConfig {
+    /// Parses `elo0=0,elo1=5,alpha=0.05,beta=0.05`; missing settings keep their defaults.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(value: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for part in value
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let (key, number) = part
+                .split_once('=')
+                .ok_or_else(|| format!("invalid sprt setting `{part}`"))?;
+            let number: f64 = number
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid sprt setting `{part}`"))?;
+            match key.trim() {
+                "elo0" => config.elo0 = number,
+                "elo1" => config.elo1 = number,
+                "alpha" => config.alpha = number,
+                "beta" => config.beta = number,
+                _ => return Err(format!("invalid sprt setting `{part}`")),
+            }
+        }
+        if config.elo1 <= config.elo0
+            || !(0.0..0.5).contains(&config.alpha)
+            || !(0.0..0.5).contains(&config.beta)
+            || config.alpha == 0.0
+            || config.beta == 0.0
+        {
+            return Err(format!("invalid sprt bounds `{value}`"));
+        }
+        Ok(config)
+    }
+
+    /// Log-likelihood ratio bounds: H0 is accepted below the first, H1 above the second.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn decision(&self, llr: f64) -> Option<SprtDecision> {
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(SprtDecision::AcceptH1)
+        } else if llr <= lower {
+            Some(SprtDecision::AcceptH0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Wins, draws and losses of the first engine.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// Expected score of the stronger side at an Elo difference of `elo`.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(0.01, 0.99);
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, between 0 and 1.
+    pub fn score(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games().max(1))
+    }
+
+    /// Variance of a single game's score around the mean.
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        let deviation = |points: f64, count: u32| f64::from(count) * (points - score).powi(2);
+        (deviation(1.0, self.wins) + deviation(0.5, self.draws) + deviation(0.0, self.losses))
+            / f64::from(self.games().max(1))
+    }
+
+    /// The Elo difference implied by the score and the half-width of its 95% confidence
+    /// interval, with scores clipped to 1%..99%.
+    pub fn elo(&self) -> (f64, f64) {
+        if self.games() == 0 {
+            return (0.0, 0.0);
+        }
+        let score = self.score();
+        let margin = 1.96 * (self.variance() / f64::from(self.games())).sqrt();
+        let low = elo_from_score(score - margin);
+        let high = elo_from_score(score + margin);
+        (elo_from_score(score), (high - low) / 2.0)
+    }
+
+    /// Log-likelihood ratio of H1 against H0 under the normal approximation to the score
+    /// distribution; zero until the results vary.
+    pub fn llr(&self, sprt: &SprtConfig) -> f64 {
+        let variance = self.variance();
+        if self.games() == 0 || variance == 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (expected_score(sprt.elo0), expected_score(sprt.elo1));
+        f64::from(self.games()) * (score1 - score0) * (2.0 * self.score() - score0 - score1)
+            / (2.0 * variance)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchConfig {
+    pub engines: [EngineSpec; 2],
+    /// Opening positions (see `parse_openings`), each played twice with colours reversed.
+    pub openings: Option<String>,
+    /// Games to play; without a value, two per opening (or two from the initial position).
+    pub games: Option<usize>,
+    /// Move limits. With none of them, `DEFAULT_TIME_CONTROL` applies.
+    pub time_control: Option<TimeControl>,
+    pub movetime_ms: Option<u64>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub max_plies: usize,
+    /// Adjudication thresholds as for `SelfPlayConfig`; they need both engines to report
+    /// scores.
+    pub resign_cp: Option<i32>,
+    pub draw_cp: Option<i32>,
+    pub pgn: Option<String>,
+    /// Stops the match early once the test decides.
+    pub sprt: Option<SprtConfig>,
+}
+
+impl MatchConfig {
+    pub fn new(first: EngineSpec, second: EngineSpec) -> Self {
+        Self {
+            engines: [first, second],
+            openings: None,
+            games: None,
+            time_control: None,
+            movetime_ms: None,
+            depth: None,
+            nodes: None,
+            max_plies: DEFAULT_MAX_PLIES,
+            resign_cp: None,
+            draw_cp: None,
+            pgn: None,
+            sprt: None,
+        }
+    }
+
+    fn clock(&self) -> Option<TimeControl> {
+        match (self.time_control, self.movetime_ms, self.depth, self.nodes) {
+            (None, None, None, None) => Some(DEFAULT_TIME_CONTROL),
+            (time_control, ..) => time_control,
+        }
+    }
+}
+
+/// A finished game; `white` is the index of the engine that had White.
+#[derive(Clone, Debug)]
+pub struct MatchGame {
+    pub round: usize,
+    pub white: usize,
+    pub start_fen: String,
+    pub moves: Vec<BitMove>,
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl MatchGame {
+    /// Points the first engine scored.
+    pub fn first_engine_points(&self) -> f64 {
+        let white_points = match self.result {
+            GameResult::WhiteWins => 1.0,
+            GameResult::Draw => 0.5,
+            GameResult::BlackWins => 0.0,
+        };
+        if self.white == 0 {
+            white_points
+        } else {
+            1.0 - white_points
+        }
+    }
+
+    pub fn to_pgn(&self, names: [&str; 2], time_control: Option<TimeControl>) -> String {
+        let mut board = Board::from_fen(&self.start_fen).expect("games start from valid FENs");
+        let mut tags = vec![
+            ("Event", "Brainstorm match".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", pgn_date()),
+            ("Round", self.round.to_string()),
+            ("White", names[self.white].to_string()),
+            ("Black", names[1 - self.white].to_string()),
+            ("Result", self.result.as_str().to_string()),
+        ];
+        if self.start_fen != Board::start_pos().fen() {
+            tags.push(("FEN", self.start_fen.clone()));
+            tags.push(("SetUp", "1".to_string()));
+        }
+        if let Some(time_control) = time_control {
+            tags.push(("TimeControl", time_control.pgn_tag()));
+        }
+        let termination = match self.termination {
+            Termination::MaxPlies | Termination::AdjudicatedWin | Termination::AdjudicatedDraw => {
+                "adjudication"
+            }
+            Termination::TimeForfeit => "time forfeit",
+            Termination::IllegalMove => "rules infraction",
+            Termination::EngineFailure => "abandoned",
+            _ => "normal",
+        };
+        tags.push(("Termination", termination.to_string()));
+        tags.push(("PlyCount", self.moves.len().to_string()));
+
+        let mut pgn: String = tags
+            .iter()
+            .map(|(tag, value)| format!("[{tag} \"{value}\"]\n"))
+            .collect();
+        pgn.push('\n');
+
+        let mut tokens = Vec::with_capacity(self.moves.len() * 3 / 2 + 2);
+        for (ply, &mv) in self.moves.iter().enumerate() {
+            let number = board.moves_played() / 2 + 1;
+            if board.turn() == Player::White {
+                tokens.push(format!("{number}."));
+            } else if ply == 0 {
+                tokens.push(format!("{number}..."));
+            }
+            tokens.push(move_to_san(&board, mv));
+            board.apply_move(mv);
+        }
+        tokens.push(format!("{{{}}}", self.termination.as_str()));
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > PGN_LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push_str("\n\n");
+        pgn
+    }
+}
+
+/// Today's date in the PGN `YYYY.MM.DD` form.
+fn pgn_date() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+    // Howard Hinnant's days-to-civil conversion.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+/// An engine's answer to `go`: its move in UCI notation and, if it reported one, its
+/// score from its own point of view.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reply {
+    pub uci_move: String,
+    pub score_cp: Option<i32>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PlayerError {
+    /// No move before the deadline.
+    Timeout,
+    Failed(String),
+}
+
+pub trait MatchPlayer {
+    fn new_game(&mut self) -> Result<(), PlayerError>;
+
+    /// Chooses a move in the position reached from `start_fen` by `moves` (in UCI
+    /// notation) under `go`, answering within `deadline` if one is given.
+    fn play(
+        &mut self,
+        start_fen: &str,
+        moves: &[String],
+        go: &GoOptions,
+        deadline: Option<Duration>,
+    ) -> Result<Reply, PlayerError>;
+}
+
+fn position_command(start_fen: &str, moves: &[String]) -> String {
+    if moves.is_empty() {
+        format!("position fen {start_fen}")
+    } else {
+        format!("position fen {start_fen} moves {}", moves.join(" "))
+    }
+}
+
+/// A Brainstorm `Engine` in this process, searching without printing.
+pub struct BuiltinPlayer {
+    engine: Engine,
+}
+
+impl BuiltinPlayer {
+    pub fn new(options: &[(String, String)]) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        for (name, value) in options {
+            engine
+                .set_option(name, value)
+                .map_err(|err| format!("builtin engine: {err}"))?;
+        }
+        Ok(Self { engine })
+    }
+}
+
+impl MatchPlayer for BuiltinPlayer {
+    fn new_game(&mut self) -> Result<(), PlayerError> {
+        self.engine.ucinewgame();
+        Ok(())
+    }
+
+    fn play(
+        &mut self,
+        start_fen: &str,
+        moves: &[String],
+        go: &GoOptions,
+        deadline: Option<Duration>,
+    ) -> Result<Reply, PlayerError> {
+        self.engine.position(&position_command(start_fen, moves));
+        let started = Instant::now();
+        let (done, finished) = channel::<()>();
+        let timer = deadline.map(|deadline| {
+            let stop = self.engine.stop_flag();
+            // The search clears the flag when it starts, so keep raising it until the
+            // search returns.
+            std::thread::spawn(move || {
+                let mut wait = deadline;
+                while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(wait) {
+                    stop.store(true, Ordering::Relaxed);
+                    wait = STOP_RETRY_INTERVAL;
+                }
+            })
+        });
+        let result = self.engine.search_quiet(go);
+        drop(done);
+        if let Some(timer) = timer {
+            let _ = timer.join();
+        }
+        if deadline.is_some_and(|deadline| started.elapsed() > deadline) {
+            return Err(PlayerError::Timeout);
+        }
+        if result.best_move.is_null() {
+            return Err(PlayerError::Failed("no move found".to_string()));
+        }
+        Ok(Reply {
+            uci_move: result.best_move.to_string(),
+            score_cp: Some(result.score_cp),
+        })
+    }
+}
+
+/// An external engine speaking UCI over its standard input and output.
+pub struct UciPlayer {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciPlayer {
+    pub fn spawn(command: &str, options: &[(String, String)]) -> Result<Self, String> {
+        let mut child = Command::new(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("cannot start `{command}`: {err}"))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (tx, lines) = channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut player = Self {
+            child,
+            stdin,
+            lines,
+        };
+        let failed = |err: PlayerError| match err {
+            PlayerError::Timeout => format!("`{command}` did not answer `uci`"),
+            PlayerError::Failed(err) => format!("`{command}`: {err}"),
+        };
+        player.send("uci").map_err(failed)?;
+        player.wait_for("uciok").map_err(failed)?;
+        for (name, value) in options {
+            player
+                .send(&format!("setoption name {name} value {value}"))
+                .map_err(failed)?;
+        }
+        Ok(player)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), PlayerError> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(|err| PlayerError::Failed(format!("cannot write to engine: {err}")))
+    }
+
+    fn next_line(&self, deadline: Option<Instant>) -> Result<String, PlayerError> {
+        let received = match deadline {
+            Some(deadline) => self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self
+                .lines
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        received.map_err(|err| match err {
+            RecvTimeoutError::Timeout => PlayerError::Timeout,
+            RecvTimeoutError::Disconnected => PlayerError::Failed("engine exited".to_string()),
+        })
+    }
+
+    fn wait_for(&self, token: &str) -> Result<(), PlayerError> {
+        let deadline = Instant::now() + ENGINE_REPLY_TIMEOUT;
+        while self.next_line(Some(deadline))?.trim() != token {}
+        Ok(())
+    }
+}
+
+/// The score in an `info` line, from the engine's point of view.
+fn info_score(line: &str) -> Option<i32> {
+    let mut tokens = line.split_whitespace();
+    tokens.find(|&token| token == "score")?;
+    let kind = tokens.next()?;
+    let value: i32 = tokens.next()?.parse().ok()?;
+    match kind {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE_REPLY_CP - (2 * value - 1)),
+        "mate" => Some(-MATE_REPLY_CP - 2 * value),
+        _ => None,
+    }
+}
+
+impl MatchPlayer for UciPlayer {
+    fn new_game(&mut self) -> Result<(), PlayerError> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok")
+    }
+
+    fn play(
+        &mut self,
+        start_fen: &str,
+        moves: &[String],
+        go: &GoOptions,
+        deadline: Option<Duration>,
+    ) -> Result<Reply, PlayerError> {
+        self.send(&position_command(start_fen, moves))?;
+        self.send(&go_command(go))?;
+        let deadline = deadline.map(|deadline| Instant::now() + deadline);
+        let mut score_cp = None;
+        loop {
+            let line = match self.next_line(deadline) {
+                Err(PlayerError::Timeout) => {
+                    // Collect the late move so it is not taken as the answer to the next
+                    // `go`.
+                    self.send("stop")?;
+                    let _ = self.wait_for_bestmove();
+                    return Err(PlayerError::Timeout);
+                }
+                line => line?,
+            };
+            if line.starts_with("info") {
+                score_cp = info_score(&line).or(score_cp);
+            } else if let Some(rest) = line.strip_prefix("bestmove") {
+                let uci_move = rest.split_whitespace().next().unwrap_or("0000").to_string();
+                return Ok(Reply { uci_move, score_cp });
+            }
+        }
+    }
+}
+
+impl UciPlayer {
+    fn wait_for_bestmove(&self) -> Result<(), PlayerError> {
+        let deadline = Instant::now() + ENGINE_REPLY_TIMEOUT;
+        while !self.next_line(Some(deadline))?.starts_with("bestmove") {}
+        Ok(())
+    }
+}
+
+impl Drop for UciPlayer {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The UCI `go` command for `go`'s limits.
+pub fn go_command(go: &GoOptions) -> String {
+    let mut command = "go".to_string();
+    let fields = [
+        ("wtime", go.wtime_ms),
+        ("btime", go.btime_ms),
+        ("winc", go.winc_ms),
+        ("binc", go.binc_ms),
+        ("movetime", go.movetime_ms),
+        ("depth", go.depth.map(u64::from)),
+        ("nodes", go.nodes),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            command.push_str(&format!(" {name} {value}"));
+        }
+    }
+    command
+}
+
+pub fn create_player(spec: &EngineSpec) -> Result<Box<dyn MatchPlayer>, String> {
+    Ok(match &spec.kind {
+        EngineKind::Builtin => Box::new(BuiltinPlayer::new(&spec.options)?),
+        EngineKind::Uci { command } => Box::new(UciPlayer::spawn(command, &spec.options)?),
+    })
+}
+
+/// Plays one game from `start` with engine `white` as White, keeping both clocks.
+pub fn play_match_game(
+    players: &mut [Box<dyn MatchPlayer>; 2],
+    config: &MatchConfig,
+    round: usize,
+    white: usize,
+    start: &Board,
+) -> MatchGame {
+    let start_fen = start.fen();
+    let mut board = start.shallow_clone();
+    let mut history = vec![board.zobrist()];
+    let mut moves = Vec::new();
+    let mut uci_moves = Vec::new();
+    let mut scores: Vec<Option<i32>> = Vec::new();
+    let time_control = config.clock();
+    let mut clocks_ms = [time_control.map_or(0, |tc| tc.base_ms as i64); 2];
+
+    let finish = |moves, (result, termination)| MatchGame {
+        round,
+        white,
+        start_fen: start_fen.clone(),
+        moves,
+        result,
+        termination,
+    };
+    let loss_for = |side: Player, termination| {
+        let result = match side {
+            Player::White => GameResult::BlackWins,
+            Player::Black => GameResult::WhiteWins,
+        };
+        (result, termination)
+    };
+
+    for (index, player) in players.iter_mut().enumerate() {
+        if player.new_game().is_err() {
+            let side = if index == white {
+                Player::White
+            } else {
+                Player::Black
+            };
+            return finish(moves, loss_for(side, Termination::EngineFailure));
+        }
+    }
+
+    loop {
+        if let Some(outcome) = game_over(&board, &history) {
+            return finish(moves, outcome);
+        }
+        if moves.len() >= config.max_plies {
+            return finish(moves, (GameResult::Draw, Termination::MaxPlies));
+        }
+
+        let side = board.turn();
+        let mover = if side == Player::White {
+            white
+        } else {
+            1 - white
+        };
+        let clock = side as usize;
+        let mut go = GoOptions {
+            movetime_ms: config.movetime_ms,
+            depth: config.depth,
+            nodes: config.nodes,
+            ..GoOptions::default()
+        };
+        let mut deadline = config
+            .movetime_ms
+            .map(|movetime| Duration::from_millis(movetime * 2 + 1_000));
+        if let Some(tc) = time_control {
+            go.wtime_ms = Some(clocks_ms[0].max(1) as u64);
+            go.btime_ms = Some(clocks_ms[1].max(1) as u64);
+            go.winc_ms = Some(tc.inc_ms);
+            go.binc_ms = Some(tc.inc_ms);
+            deadline = Some(Duration::from_millis(
+                clocks_ms[clock].max(0) as u64 + CLOCK_MARGIN_MS,
+            ));
+        }
+
+        let started = Instant::now();
+        let reply = players[mover].play(&start_fen, &uci_moves, &go, deadline);
+        let elapsed_ms = started.elapsed().as_millis() as i64;
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(PlayerError::Timeout) => {
+                return finish(moves, loss_for(side, Termination::TimeForfeit))
+            }
+            Err(PlayerError::Failed(_)) => {
+                return finish(moves, loss_for(side, Termination::EngineFailure))
+            }
+        };
+        if let Some(tc) = time_control {
+            clocks_ms[clock] -= elapsed_ms;
+            if clocks_ms[clock] < -(CLOCK_MARGIN_MS as i64) {
+                return finish(moves, loss_for(side, Termination::TimeForfeit));
+            }
+            clocks_ms[clock] = clocks_ms[clock].max(0) + tc.inc_ms as i64;
+        }
+        let Some(mv) = parse_move(&board, &reply.uci_move) else {
+            return finish(moves, loss_for(side, Termination::IllegalMove));
+        };
+
+        board.apply_move(mv);
+        history.push(board.zobrist());
+        moves.push(mv);
+        uci_moves.push(mv.to_string());
+        scores.push(reply.score_cp);
+        let recent: Option<Vec<i32>> = scores
+            .iter()
+            .rev()
+            .take(ADJUDICATION_PLIES)
+            .rev()
+            .copied()
+            .collect();
+        if let Some(outcome) =
+            recent.and_then(|recent| adjudicate(config.resign_cp, config.draw_cp, &board, &recent))
+        {
+            return finish(moves, outcome);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchReport {
+    pub score: MatchScore,
+    pub games: Vec<MatchGame>,
+    /// Set when the SPRT ended the match.
+    pub decision: Option<SprtDecision>,
+}
+
+/// Plays the match, appending each game to `config.pgn` and calling `on_game` with the
+/// running score after it.
+pub fn run_match(
+    config: &MatchConfig,
+    mut on_game: impl FnMut(&MatchGame, &MatchScore),
+) -> Result<MatchReport, String> {
+    let openings = match &config.openings {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read `{path}`: {err}"))?;
+            parse_openings(&contents)?
+        }
+        None => vec![Board::start_pos()],
+    };
+    if openings.is_empty() {
+        return Err("the openings file holds no positions".to_string());
+    }
+    let mut pgn = match &config.pgn {
+        Some(path) => Some(BufWriter::new(
+            File::create(path).map_err(|err| format!("cannot create `{path}`: {err}"))?,
+        )),
+        None => None,
+    };
+    let mut players = [
+        create_player(&config.engines[0])?,
+        create_player(&config.engines[1])?,
+    ];
+    let names = [
+        config.engines[0].name.as_str(),
+        config.engines[1].name.as_str(),
+    ];
+
+    let total = config.games.unwrap_or(2 * openings.len());
+    let mut report = MatchReport {
+        score: MatchScore::default(),
+        games: Vec::with_capacity(total),
+        decision: None,
+    };
+    for index in 0..total {
+        let opening = &openings[(index / 2) % openings.len()];
+        let game = play_match_game(&mut players, config, index + 1, index % 2, opening);
+        let points = game.first_engine_points();
+        if points == 1.0 {
+            report.score.wins += 1;
+        } else if points == 0.0 {
+            report.score.losses += 1;
+        } else {
+            report.score.draws += 1;
+        }
+        if let Some(pgn) = pgn.as_mut() {
+            pgn.write_all(game.to_pgn(names, config.clock()).as_bytes())
+                .and_then(|_| pgn.flush())
+                .map_err(|err| format!("cannot write PGN: {err}"))?;
+        }
+        on_game(&game, &report.score);
+        report.games.push(game);
+
+        if let Some(sprt) = &config.sprt {
+            report.decision = sprt.decision(report.score.llr(sprt));
+            if report.decision.is_some() {
+                break;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// `match`: plays the match and prints each result, then the score, the Elo estimate
+/// and, with an SPRT, its state, in the layout cutechess-cli uses.
+/// Runs the match, printing each game and the final estimate. Errors that stop the
+/// match are returned rather than printed.
+pub fn print_match(config: &MatchConfig) -> Result<MatchReport, String> {
+    let names = [&config.engines[0].name, &config.engines[1].name];
+    let score_line = |score: &MatchScore| {
+        format!(
+            "Score of {} vs {}: {} - {} - {}  [{:.3}] {}",
+            names[0],
+            names[1],
+            score.wins,
+            score.losses,
+            score.draws,
+            score.score(),
+            score.games()
+        )
+    };
+    let result = run_match(config, |game, score| {
+        println!(
+            "info string match game={} white={} black={} result={} termination={} plies={}",
+            game.round,
+            names[game.white],
+            names[1 - game.white],
+            game.result.as_str(),
+            game.termination.as_str(),
+            game.moves.len()
+        );
+        println!("{}", score_line(score));
+    });
+    let report = result?;
+
+    let (elo, margin) = report.score.elo();
+    println!("Elo difference: {elo:.1} +/- {margin:.1}");
+    if let Some(sprt) = &config.sprt {
+        let llr = report.score.llr(sprt);
+        let (lower, upper) = sprt.bounds();
+        let state = match report.decision {
+            Some(SprtDecision::AcceptH1) => " - H1 was accepted",
+            Some(SprtDecision::AcceptH0) => " - H0 was accepted",
+            None => "",
+        };
+        println!("SPRT: llr {llr:.2}, lbound {lower:.2}, ubound {upper:.2}{state}");
+    }
+    println!("Finished match");
+    Ok(report)
+}
@@ -0,0 +1,476 @@
+use crate::classical;
+use crate::evaluator::Evaluator;
+use crate::features::MAX_INPUT_SIZE;
+use crate::search_algorithm::{
+    insufficient_material, mate_in, policy_evaluator, score_to_uci, ModelMode, SearchOptions,
+    SearchRequest, SearchResult, SearchStats,
+};
+use crate::utils::policy_index;
+use pleco::{BitMove, Board};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tch::Device;
+
+/// Exploration constant of the PUCT formula.
+const C_PUCT: f32 = 1.5;
+/// Centipawns that map to a value of `tanh(1)`, about 0.76.
+const VALUE_SCALE_CP: f32 = 400.0;
+/// `go depth N` allows `PLAYOUTS_PER_DEPTH << N` playouts, as if every ply doubled the tree.
+const PLAYOUTS_PER_DEPTH: u64 = 16;
+const MAX_DEPTH_SHIFT: u32 = 20;
+/// Info lines are printed at every power of two from here on.
+const FIRST_INFO_PLAYOUTS: u64 = 256;
+const MAX_VALUE: f32 = 0.999;
+
+#[derive(Clone)]
+struct Node {
+    /// Move leading here from the parent; null for the root.
+    mv: BitMove,
+    /// Zobrist key of the position, known once the node has been evaluated.
+    key: u64,
+    prior: f32,
+    visits: u32,
+    virtual_loss: u32,
+    /// Sum of the values backed up through this node, from the point of view of the side
+    /// that played `mv`.
+    value_sum: f32,
+    first_child: u32,
+    child_count: u16,
+    expanded: bool,
+    /// Exact value for the side to move when the game is over here.
+    terminal: Option<f32>,
+}
+
+impl Node {
+    fn new(mv: BitMove, prior: f32) -> Self {
+        Self {
+            mv,
+            key: 0,
+            prior,
+            visits: 0,
+            virtual_loss: 0,
+            value_sum: 0.0,
+            first_child: 0,
+            child_count: 0,
+            expanded: false,
+            terminal: None,
+        }
+    }
+
+    fn children(&self) -> std::ops::Range<usize> {
+        let first = self.first_child as usize;
+        first..first + self.child_count as usize
+    }
+}
+
+/// A PUCT search tree stored as an arena; the children of a node are contiguous.
+#[derive(Clone, Default)]
+pub struct MctsTree {
+    nodes: Vec<Node>,
+}
+
+impl MctsTree {
+    fn fresh(root_key: u64) -> Self {
+        let mut root = Node::new(BitMove::null(), 1.0);
+        root.key = root_key;
+        Self { nodes: vec![root] }
+    }
+
+    /// Re-roots the tree at `root_key` if that position is the root or lies up to two
+    /// plies below it (our last move and the opponent's reply).
+    fn reuse(&self, root_key: u64) -> Option<Self> {
+        self.nodes.first()?;
+        let mut frontier = vec![0];
+        for _ in 0..=2 {
+            let mut next = Vec::new();
+            for index in frontier {
+                let node = &self.nodes[index];
+                if node.expanded && node.terminal.is_none() && node.key == root_key {
+                    return Some(self.subtree(index));
+                }
+                next.extend(node.children());
+            }
+            frontier = next;
+        }
+        None
+    }
+
+    /// Copies the subtree under `new_root`. Terminal nodes start over unexpanded: a
+    /// repetition draw depends on the path from the old root, so their status is worked
+    /// out again on the next visit.
+    fn subtree(&self, new_root: usize) -> Self {
+        let mut root = self.nodes[new_root].clone();
+        root.mv = BitMove::null();
+        let mut nodes = vec![root];
+        let mut queue = VecDeque::from([(new_root, 0)]);
+        while let Some((old, new)) = queue.pop_front() {
+            let first = nodes.len();
+            for child in self.nodes[old].children() {
+                let node = &self.nodes[child];
+                if node.terminal.is_some() {
+                    nodes.push(Node::new(node.mv, node.prior));
+                } else {
+                    queue.push_back((child, nodes.len()));
+                    nodes.push(node.clone());
+                }
+            }
+            nodes[new].first_child = first as u32;
+        }
+        Self { nodes }
+    }
+
+    fn root(&self) -> &Node {
+        &self.nodes[0]
+    }
+
+    /// Walks from the root to a leaf by PUCT, adding a virtual loss to every node on the
+    /// way so concurrent workers spread over different lines.
+    fn select(&mut self) -> Vec<usize> {
+        let mut path = vec![0];
+        let mut index = 0;
+        self.nodes[0].virtual_loss += 1;
+        while self.nodes[index].expanded && self.nodes[index].terminal.is_none() {
+            let parent = &self.nodes[index];
+            let parent_visits = (parent.visits + parent.virtual_loss) as f32;
+            let exploration = C_PUCT * parent_visits.sqrt();
+            let Some(best) = parent.children().max_by(|&a, &b| {
+                let score_a = self.puct_score(a, exploration);
+                let score_b = self.puct_score(b, exploration);
+                score_a.total_cmp(&score_b)
+            }) else {
+                break;
+            };
+            self.nodes[best].virtual_loss += 1;
+            path.push(best);
+            index = best;
+        }
+        path
+    }
+
+    fn puct_score(&self, index: usize, exploration: f32) -> f32 {
+        let node = &self.nodes[index];
+        let visits = node.visits + node.virtual_loss;
+        // Virtual visits count as losses for the side choosing this child.
+        let q = if visits == 0 {
+            0.0
+        } else {
+            (node.value_sum - node.virtual_loss as f32) / visits as f32
+        };
+        q + exploration * node.prior / (1 + visits) as f32
+    }
+
+    /// Adds the leaf's children and backs its value (for the side to move at the leaf) up
+    /// the path, removing the virtual losses.
+    fn backup(&mut self, path: &[usize], evaluation: &LeafEvaluation) {
+        let leaf = *path.last().expect("paths start at the root");
+        if !self.nodes[leaf].expanded {
+            let first = self.nodes.len();
+            self.nodes.extend(
+                evaluation
+                    .children
+                    .iter()
+                    .map(|&(mv, prior)| Node::new(mv, prior)),
+            );
+            let node = &mut self.nodes[leaf];
+            node.key = evaluation.key;
+            node.terminal = evaluation.terminal;
+            node.first_child = first as u32;
+            node.child_count = evaluation.children.len() as u16;
+            node.expanded = true;
+        }
+
+        let mut value = -evaluation.value;
+        for &index in path.iter().rev() {
+            let node = &mut self.nodes[index];
+            node.virtual_loss -= 1;
+            node.visits += 1;
+            node.value_sum += value;
+            value = -value;
+        }
+    }
+
+    fn best_child(&self) -> Option<&Node> {
+        self.root()
+            .children()
+            .map(|index| &self.nodes[index])
+            .filter(|node| node.visits > 0)
+            .max_by_key(|node| node.visits)
+    }
+
+    /// Length of the line that follows the most visited child at every node.
+    fn principal_depth(&self) -> u32 {
+        let mut depth = 0;
+        let mut index = 0;
+        while let Some(best) = self.nodes[index]
+            .children()
+            .filter(|&child| self.nodes[child].visits > 0)
+            .max_by_key(|&child| self.nodes[child].visits)
+        {
+            depth += 1;
+            index = best;
+        }
+        depth
+    }
+
+    /// Best move and its score in centipawns for the side to move at the root.
+    fn outcome(&self) -> Option<(BitMove, i32)> {
+        let best = self.best_child()?;
+        if best.terminal == Some(-1.0) {
+            return Some((best.mv, mate_in(1)));
+        }
+        let q = (best.value_sum / best.visits as f32).clamp(-MAX_VALUE, MAX_VALUE);
+        Some((best.mv, (q.atanh() * VALUE_SCALE_CP).round() as i32))
+    }
+}
+
+struct LeafEvaluation {
+    key: u64,
+    value: f32,
+    terminal: Option<f32>,
+    children: Vec<(BitMove, f32)>,
+}
+
+/// Shared, read-only state of one MCTS search.
+pub(crate) struct Mcts<'a> {
+    pub small_eval: Option<&'a Evaluator>,
+    pub large_eval: Option<&'a Evaluator>,
+    pub eval_device: Device,
+    pub should_stop: &'a AtomicBool,
+    pub tree: &'a Mutex<Option<MctsTree>>,
+}
+
+impl Mcts<'_> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(
+        &self,
+        board: &Board,
+        request: &SearchRequest,
+        options: &SearchOptions,
+        game_history: &[u64],
+        threads: usize,
+        fallback_move: BitMove,
+        emit_info: bool,
+        start: Instant,
+    ) -> SearchResult {
+        let root_key = board.zobrist();
+        let mut stored = self.tree.lock().unwrap();
+        let tree = stored
+            .take()
+            .and_then(|tree| tree.reuse(root_key))
+            .unwrap_or_else(|| MctsTree::fresh(root_key));
+        let reused_playouts = tree.root().visits as u64;
+
+        let max_nodes = options.hash_mb.max(1) * 1024 * 1024 / std::mem::size_of::<Node>();
+        let budget = (PLAYOUTS_PER_DEPTH << request.max_depth.min(MAX_DEPTH_SHIFT))
+            .min(request.max_nodes.unwrap_or(u64::MAX));
+        let soft_deadline = request
+            .soft_time_ms
+            .map(|ms| start + Duration::from_millis(ms));
+        let hard_deadline = request
+            .hard_time_ms
+            .map(|ms| start + Duration::from_millis(ms));
+        let policy_eval = policy_evaluator(options.model_mode, self.small_eval, self.large_eval);
+
+        let shared = Mutex::new((tree, 0_u64));
+        let worker = || {
+            let mut stats = SearchStats::default();
+            let mut buffer = [0_f32; MAX_INPUT_SIZE];
+            let mut path_keys = Vec::new();
+            loop {
+                let path = {
+                    let (tree, playouts) = &mut *shared.lock().unwrap();
+                    let out_of_time = [soft_deadline, hard_deadline]
+                        .iter()
+                        .flatten()
+                        .any(|deadline| Instant::now() >= *deadline);
+                    if self.should_stop.load(Ordering::Relaxed)
+                        || *playouts >= budget
+                        || tree.nodes.len() >= max_nodes
+                        || (*playouts > 0 && out_of_time)
+                    {
+                        break;
+                    }
+                    *playouts += 1;
+                    tree.select()
+                };
+
+                let mut leaf = board.shallow_clone();
+                path_keys.clear();
+                path_keys.push(root_key);
+                {
+                    let (tree, _) = &*shared.lock().unwrap();
+                    for &index in &path[1..] {
+                        leaf.apply_move(tree.nodes[index].mv);
+                        path_keys.push(leaf.zobrist());
+                    }
+                }
+                path_keys.pop();
+                let evaluation = self.evaluate_leaf(
+                    &leaf,
+                    path.len() == 1,
+                    options.model_mode,
+                    policy_eval,
+                    game_history,
+                    &path_keys,
+                    &mut buffer,
+                    &mut stats,
+                );
+
+                let (tree, playouts) = &mut *shared.lock().unwrap();
+                tree.backup(&path, &evaluation);
+                if emit_info && *playouts >= FIRST_INFO_PLAYOUTS && playouts.is_power_of_two() {
+                    print_info(tree, *playouts, start);
+                }
+            }
+            stats
+        };
+
+        let mut stats = SearchStats::default();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (1..threads.max(1)).map(|_| scope.spawn(worker)).collect();
+            stats.saturating_add_assign(worker());
+            for handle in handles {
+                stats.saturating_add_assign(handle.join().expect("MCTS worker panicked"));
+            }
+        });
+
+        let (tree, playouts) = shared.into_inner().unwrap();
+        stats.reused_playouts = reused_playouts;
+        if emit_info {
+            print_info(&tree, playouts, start);
+        }
+        let (best_move, score_cp) = tree.outcome().unwrap_or((fallback_move, 0));
+        let depth = tree.principal_depth();
+        *stored = Some(tree);
+
+        SearchResult {
+            best_move,
+            score_cp,
+            depth,
+            nodes: playouts,
+            elapsed: start.elapsed(),
+            stats,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate_leaf(
+        &self,
+        board: &Board,
+        is_root: bool,
+        mode: ModelMode,
+        policy_eval: Option<&Evaluator>,
+        game_history: &[u64],
+        path_keys: &[u64],
+        buffer: &mut [f32; MAX_INPUT_SIZE],
+        stats: &mut SearchStats,
+    ) -> LeafEvaluation {
+        let key = board.zobrist();
+        let moves = board.generate_moves();
+        let game_over = if moves.is_empty() {
+            Some(if board.in_check() { -1.0 } else { 0.0 })
+        } else if is_root {
+            // The game goes on from the root whatever the draw rules say; let the
+            // children decide.
+            None
+        } else if board.rule_50() >= 100
+            || insufficient_material(board)
+            || path_keys.contains(&key)
+            || game_history.iter().filter(|&&seen| seen == key).count() >= 2
+        {
+            Some(0.0)
+        } else {
+            None
+        };
+        if let Some(value) = game_over {
+            return LeafEvaluation {
+                key,
+                value,
+                terminal: Some(value),
+                children: Vec::new(),
+            };
+        }
+
+        stats.eval_calls = stats.eval_calls.saturating_add(1);
+        let cp = self.leaf_cp(board, mode, buffer, stats);
+        let value = (cp as f32 / VALUE_SCALE_CP).tanh();
+
+        let logits = policy_eval
+            .and_then(|evaluator| evaluator.policy(board, buffer.as_mut_slice(), self.eval_device));
+        let priors: Vec<f32> = match &logits {
+            Some(logits) => {
+                stats.policy_orderings = stats.policy_orderings.saturating_add(1);
+                let move_logits: Vec<f32> =
+                    moves.iter().map(|&mv| logits[policy_index(mv)]).collect();
+                let max = move_logits.iter().copied().fold(f32::MIN, f32::max);
+                let exp: Vec<f32> = move_logits
+                    .iter()
+                    .map(|logit| (logit - max).exp())
+                    .collect();
+                let sum: f32 = exp.iter().sum();
+                exp.into_iter().map(|value| value / sum).collect()
+            }
+            None => vec![1.0 / moves.len() as f32; moves.len()],
+        };
+
+        LeafEvaluation {
+            key,
+            value,
+            terminal: None,
+            children: moves.iter().copied().zip(priors).collect(),
+        }
+    }
+
+    /// Modes that mix the networks by ply or window have no equivalent in a tree search
+    /// and use the mean of both networks instead.
+    fn leaf_cp(
+        &self,
+        board: &Board,
+        mode: ModelMode,
+        buffer: &mut [f32; MAX_INPUT_SIZE],
+        stats: &mut SearchStats,
+    ) -> i32 {
+        let mut network = |use_large: bool| {
+            let evaluator = if use_large {
+                self.large_eval
+            } else {
+                self.small_eval
+            };
+            let Some(evaluator) = evaluator else {
+                return classical::evaluate(board);
+            };
+            if use_large {
+                stats.accurate_model_evals = stats.accurate_model_evals.saturating_add(1);
+            } else {
+                stats.fast_model_evals = stats.fast_model_evals.saturating_add(1);
+            }
+            evaluator.evaluate(board, buffer, self.eval_device)
+        };
+        match mode {
+            ModelMode::Classical => classical::evaluate(board),
+            ModelMode::Small => network(false),
+            ModelMode::Large => network(true),
+            ModelMode::HybridRoot | ModelMode::Adaptive | ModelMode::Ensemble => {
+                (network(false) + network(true)) / 2
+            }
+        }
+    }
+}
+
+fn print_info(tree: &MctsTree, playouts: u64, start: Instant) {
+    let Some((best_move, score_cp)) = tree.outcome() else {
+        return;
+    };
+    let (score_kind, score_value) = score_to_uci(score_cp);
+    println!(
+        "info depth {} score {} {} nodes {} time {} pv {}",
+        tree.principal_depth(),
+        score_kind,
+        score_value,
+        playouts,
+        start.elapsed().as_millis(),
+        best_move
+    );
+}
@@ -0,0 +1,41 @@
+use std::path::Path;
+use tract_onnx::prelude::*;
+
+/// An eval network exported to ONNX, run on the CPU with tract.
+pub struct OnnxModel {
+    plan: Arc<TypedRunnableModel>,
+    input_size: usize,
+}
+
+impl OnnxModel {
+    pub fn load(path: &Path, input_size: usize) -> Result<Self, String> {
+        let plan = tract_onnx::onnx()
+            .model_for_path(path)
+            .and_then(|model| model.with_input_fact(0, f32::fact([1, input_size]).into()))
+            .and_then(|model| model.into_optimized())
+            .and_then(|model| model.into_runnable())
+            .map_err(|err| format!("{err:#}"))?;
+        Ok(Self { plan, input_size })
+    }
+
+    pub fn forward(&self, features: &[f32]) -> Result<f32, String> {
+        let input = Tensor::from_shape(&[1, self.input_size], &features[..self.input_size])
+            .map_err(|err| err.to_string())?;
+        let outputs = self
+            .plan
+            .run(tvec!(input.into()))
+            .map_err(|err| err.to_string())?;
+        let output = outputs
+            .first()
+            .ok_or_else(|| "ONNX model produced no outputs".to_string())?
+            .to_plain_array_view::<f32>()
+            .map_err(|err| err.to_string())?;
+        if output.len() != 1 {
+            return Err(format!(
+                "expected a scalar output, got shape {:?}",
+                output.shape()
+            ));
+        }
+        Ok(output.iter().next().copied().unwrap_or(0.0))
+    }
+}
@@ -0,0 +1,39 @@
+use pleco::{BitMove, Board};
+
+/// Counts the leaf nodes of the legal move tree `depth` plies deep, making and unmaking
+/// every move on `board` the way the search does. The board is left as it was.
+pub fn perft(board: &mut Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = board.generate_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for mv in moves.iter() {
+        board.apply_move(*mv);
+        nodes += perft(board, depth - 1);
+        board.undo_move();
+    }
+    nodes
+}
+
+/// `perft` split by root move, in move generation order, for comparing against another
+/// move generator one subtree at a time.
+pub fn divide(board: &mut Board, depth: u32) -> Vec<(BitMove, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    board
+        .generate_moves()
+        .iter()
+        .map(|&mv| {
+            board.apply_move(mv);
+            let nodes = perft(board, depth - 1);
+            board.undo_move();
+            (mv, nodes)
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+use serde::Deserialize;
+use tch::{CModule, Device, Tensor};
+
+const INT8_MAX: f32 = 127.0;
+
+/// Activation applied to the network's single output neuron. Hidden layers always use
+/// ReLU, matching the `Sequential(Linear, ReLU, ..., Linear)` networks we train.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputActivation {
+    #[default]
+    None,
+    Tanh,
+}
+
+/// A linear layer with int8 weights and one scale for the whole layer. Weights are stored
+/// input-major so zero inputs (most of the one-hot board encoding, and every ReLU that
+/// did not fire) can be skipped. Activations stay f32 and each weight is widened to f32
+/// as it is used, so int8 shrinks the weights fourfold but the arithmetic is not integer;
+/// the speed comes from skipping zero inputs and libtorch's per-call overhead.
+struct QuantizedLinear {
+    inputs: usize,
+    outputs: usize,
+    weights: Vec<i8>,
+    scale: f32,
+    bias: Vec<f32>,
+}
+
+impl QuantizedLinear {
+    fn new(weight: &[f32], bias: Vec<f32>, outputs: usize, inputs: usize) -> Self {
+        let max_abs = weight
+            .iter()
+            .fold(0.0_f32, |max, value| max.max(value.abs()));
+        let scale = if max_abs > 0.0 {
+            max_abs / INT8_MAX
+        } else {
+            1.0
+        };
+
+        let mut weights = vec![0_i8; inputs * outputs];
+        for output in 0..outputs {
+            for input in 0..inputs {
+                let value = weight[output * inputs + input] / scale;
+                weights[input * outputs + output] = value.round().clamp(-INT8_MAX, INT8_MAX) as i8;
+            }
+        }
+
+        Self {
+            inputs,
+            outputs,
+            weights,
+            scale,
+            bias,
+        }
+    }
+
+    fn forward(&self, input: &[f32], output: &mut Vec<f32>) {
+        output.clear();
+        output.resize(self.outputs, 0.0);
+        for (value, column) in input.iter().zip(self.weights.chunks_exact(self.outputs)) {
+            if *value == 0.0 {
+                continue;
+            }
+            for (acc, &weight) in output.iter_mut().zip(column) {
+                *acc += *value * weight as f32;
+            }
+        }
+        for (acc, bias) in output.iter_mut().zip(&self.bias) {
+            *acc = *acc * self.scale + bias;
+        }
+    }
+}
+
+/// CPU inference for an eval MLP with int8 weights, built from the parameters of a
+/// TorchScript module.
+pub struct QuantizedMlp {
+    layers: Vec<QuantizedLinear>,
+    output_activation: OutputActivation,
+}
+
+impl QuantizedMlp {
+    pub fn from_module(
+        module: &CModule,
+        input_size: usize,
+        output_activation: OutputActivation,
+    ) -> Result<Self, String> {
+        let parameters = module
+            .named_parameters()
+            .map_err(|err| format!("failed to read parameters: {err}"))?;
+
+        let mut weights = Vec::new();
+        let mut biases = Vec::new();
+        for (name, tensor) in &parameters {
+            if name.ends_with(".weight") {
+                weights.push((layer_index(name)?, tensor));
+            } else if name.ends_with(".bias") {
+                biases.push((layer_index(name)?, tensor));
+            } else {
+                return Err(format!("unexpected parameter `{name}`"));
+            }
+        }
+        weights.sort_by_key(|&(index, _)| index);
+        biases.sort_by_key(|&(index, _)| index);
+        if weights.is_empty() || weights.len() != biases.len() {
+            return Err("expected a stack of linear layers with biases".to_string());
+        }
+
+        let mut layers = Vec::with_capacity(weights.len());
+        let mut expected_inputs = input_size;
+        for ((_, weight), (_, bias)) in weights.iter().zip(&biases) {
+            let size = weight.size();
+            if size.len() != 2 || size[1] as usize != expected_inputs {
+                return Err(format!(
+                    "linear layer of shape {size:?} does not take {expected_inputs} inputs"
+                ));
+            }
+            let (outputs, inputs) = (size[0] as usize, size[1] as usize);
+            let bias = flat_values(bias)?;
+            if bias.len() != outputs {
+                return Err(format!(
+                    "bias of length {} for {outputs} outputs",
+                    bias.len()
+                ));
+            }
+            layers.push(QuantizedLinear::new(
+                &flat_values(weight)?,
+                bias,
+                outputs,
+                inputs,
+            ));
+            expected_inputs = outputs;
+        }
+        if expected_inputs != 1 {
+            return Err(format!(
+                "expected a single output, got {expected_inputs} outputs"
+            ));
+        }
+
+        Ok(Self {
+            layers,
+            output_activation,
+        })
+    }
+
+    pub fn forward(&self, features: &[f32]) -> f32 {
+        let mut input = features.to_vec();
+        let mut output = Vec::new();
+        let last = self.layers.len() - 1;
+        for (index, layer) in self.layers.iter().enumerate() {
+            layer.forward(&input[..layer.inputs], &mut output);
+            if index < last {
+                output.iter_mut().for_each(|value| *value = value.max(0.0));
+            }
+            std::mem::swap(&mut input, &mut output);
+        }
+
+        match self.output_activation {
+            OutputActivation::None => input[0],
+            OutputActivation::Tanh => input[0].tanh(),
+        }
+    }
+}
+
+/// Position of a parameter's layer in a `Sequential`, from names such as `net.2.weight`.
+/// Other naming schemes (`fc1.weight`) say nothing reliable about the layer order.
+fn layer_index(name: &str) -> Result<usize, String> {
+    name.rsplit('.')
+        .nth(1)
+        .and_then(|index| index.parse().ok())
+        .ok_or_else(|| format!("parameter `{name}` is not numbered like a Sequential layer"))
+}
+
+fn flat_values(tensor: &Tensor) -> Result<Vec<f32>, String> {
+    Vec::<f32>::try_from(&tensor.to_device(Device::Cpu).view([-1]))
+        .map_err(|err| format!("failed to read parameter values: {err}"))
+}
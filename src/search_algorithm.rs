@@ -1,33 +1,56 @@
-use crate::utils::{encode_board_features, order_captures, order_moves, HistoryTable};
+use crate::classical::{self, game_phase, piece_value, MAX_PHASE};
+use crate::evaluator::Evaluator;
+use crate::features::MAX_INPUT_SIZE;
+use crate::mcts::{Mcts, MctsTree};
+use crate::time_manager::TimeManager;
+use crate::utils::{order_captures, order_moves, HistoryTable};
 use pleco::core::GenTypes;
-use pleco::{BitMove, Board, PieceType, Player};
+use pleco::{BitBoard, BitMove, Board, PieceType, Player};
 use rayon::ThreadPoolBuilder;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, Once};
 use std::time::{Duration, Instant};
-use tch::{CModule, Device};
+use tch::Device;
 
 const INF: i32 = 32_000;
 const MATE_SCORE: i32 = 31_000;
 const MATE_THRESHOLD: i32 = 30_000;
-const EVAL_SCALE_CP: f32 = 2_500.0;
 const MAX_PLY: usize = 128;
 const TIME_CHECK_INTERVAL: u64 = 1_024;
 const DEFAULT_HASH_MB: usize = 64;
 const DEFAULT_EVAL_CACHE_MB: usize = 16;
 const DEFAULT_THREADS_CAP: usize = 8;
-const DEFAULT_MAX_DEPTH: u32 = 64;
+pub(crate) const DEFAULT_MAX_DEPTH: u32 = 64;
 const EVAL_CACHE_EMPTY_KEY: u64 = u64::MAX;
 const EVAL_LARGE_KEY_MIX: u64 = 0x9e37_79b9_7f4a_7c15;
 const Q_DELTA_MARGIN_CP: i32 = 120;
+pub const MAX_CONTEMPT_CP: i32 = 200;
+const AUTO_CONTEMPT_MAX_CP: i32 = 24;
+/// `Adaptive` asks the accurate model once the fast score is this close to the window.
+const ADAPTIVE_WINDOW_MARGIN_CP: i32 = 50;
+/// With `lazy_eval`, quiescence stands pat without the network when the classical score
+/// beats beta by this much.
+const LAZY_EVAL_MARGIN_CP: i32 = 600;
+/// Non-PV nodes at least this deep also order moves by the policy head, if there is one.
+const POLICY_MIN_DEPTH: i32 = 4;
 static SET_INTEROP_THREADS_ONCE: Once = Once::new();
 
+/// Which network scores the leaves of the search.
+///
+/// * `HybridRoot`: the accurate model at the root and its children, the fast one below.
+/// * `Adaptive`: the fast model first, re-scored with the accurate one in PV nodes, in
+///   quiet positions, and when the fast score lands near the alpha-beta window.
+/// * `Ensemble`: the mean of both models everywhere.
+/// * `Classical`: the handcrafted evaluation in `classical`, no network at all.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ModelMode {
     Small,
     Large,
     HybridRoot,
+    Adaptive,
+    Ensemble,
+    Classical,
 }
 
 impl ModelMode {
@@ -36,6 +59,9 @@ impl ModelMode {
             "fast" => Some((Self::Small, None)),
             "balanced" => Some((Self::HybridRoot, None)),
             "accurate" => Some((Self::Large, None)),
+            "adaptive" => Some((Self::Adaptive, None)),
+            "ensemble" => Some((Self::Ensemble, None)),
+            "classical" => Some((Self::Classical, None)),
             "small" => Some((Self::Small, Some("model `small` is deprecated; use `fast`"))),
             "hybrid_root" => Some((
                 Self::HybridRoot,
@@ -49,16 +75,82 @@ impl ModelMode {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(value: &str) -> Option<Self> {
         Self::parse_with_alias_info(value).map(|(mode, _)| mode)
     }
 }
 
+/// How the engine searches: the alpha-beta searcher in this file, or the PUCT tree
+/// search in `mcts` that scores leaves with the value network and, when the model has
+/// one, takes move priors from its policy head.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SearchMode {
+    #[default]
+    AlphaBeta,
+    Mcts,
+}
+
+impl SearchMode {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "alphabeta" => Some(Self::AlphaBeta),
+            "mcts" => Some(Self::Mcts),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::AlphaBeta => "alphabeta",
+            Self::Mcts => "mcts",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Contempt {
+    Fixed(i32),
+    Auto,
+}
+
+impl Default for Contempt {
+    fn default() -> Self {
+        Self::Fixed(0)
+    }
+}
+
+impl Contempt {
+    /// `Auto` when `auto` is set, otherwise `cp` clamped to `MAX_CONTEMPT_CP` either way.
+    pub fn new(cp: i32, auto: bool) -> Self {
+        if auto {
+            Self::Auto
+        } else {
+            Self::Fixed(cp.clamp(-MAX_CONTEMPT_CP, MAX_CONTEMPT_CP))
+        }
+    }
+
+    /// Centipawns the root side gives up by accepting a draw. `Auto` scales with the
+    /// remaining non-pawn material so draws are avoided in the middlegame but accepted
+    /// in simplified endings.
+    fn resolve_cp(self, board: &Board) -> i32 {
+        match self {
+            Self::Fixed(cp) => cp,
+            Self::Auto => AUTO_CONTEMPT_MAX_CP * game_phase(board) / MAX_PHASE,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SearchOptions {
     pub hash_mb: usize,
     pub threads: usize,
     pub model_mode: ModelMode,
+    pub search_mode: SearchMode,
+    pub contempt: Contempt,
+    /// Skip the network in quiescence when the classical eval already fails high.
+    pub lazy_eval: bool,
     pub debug_log: bool,
 }
 
@@ -68,16 +160,22 @@ impl Default for SearchOptions {
             hash_mb: DEFAULT_HASH_MB,
             threads: default_search_threads(),
             model_mode: ModelMode::Small,
+            search_mode: SearchMode::default(),
+            contempt: Contempt::default(),
+            lazy_eval: false,
             debug_log: false,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct SearchRequest {
     pub max_depth: u32,
     pub soft_time_ms: Option<u64>,
     pub hard_time_ms: Option<u64>,
+    /// Stop after this many nodes (MCTS: playouts). Node-limited alpha-beta searches run
+    /// on one thread so the limit, and the result, are exact.
+    pub max_nodes: Option<u64>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -98,16 +196,36 @@ pub struct SearchStats {
     pub tt_hits: u64,
     pub q_nodes: u64,
     pub beta_cutoffs: u64,
+    /// Leaf scores taken from each model, cache hits included. An `Ensemble` leaf counts
+    /// once for each, an `Adaptive` one only for the model whose score it kept.
+    pub fast_model_evals: u64,
+    pub accurate_model_evals: u64,
+    /// Quiescence nodes cut by the classical eval before reaching a network.
+    pub lazy_eval_cutoffs: u64,
+    /// Nodes whose moves were ordered, or MCTS leaves whose priors were set, by a
+    /// policy head.
+    pub policy_orderings: u64,
+    /// Playouts already under the root when an MCTS search reused the previous tree.
+    pub reused_playouts: u64,
 }
 
 impl SearchStats {
-    fn saturating_add_assign(&mut self, other: SearchStats) {
+    pub(crate) fn saturating_add_assign(&mut self, other: SearchStats) {
         self.eval_calls = self.eval_calls.saturating_add(other.eval_calls);
         self.eval_cache_hits = self.eval_cache_hits.saturating_add(other.eval_cache_hits);
         self.tt_probes = self.tt_probes.saturating_add(other.tt_probes);
         self.tt_hits = self.tt_hits.saturating_add(other.tt_hits);
         self.q_nodes = self.q_nodes.saturating_add(other.q_nodes);
         self.beta_cutoffs = self.beta_cutoffs.saturating_add(other.beta_cutoffs);
+        self.fast_model_evals = self.fast_model_evals.saturating_add(other.fast_model_evals);
+        self.accurate_model_evals = self
+            .accurate_model_evals
+            .saturating_add(other.accurate_model_evals);
+        self.lazy_eval_cutoffs = self
+            .lazy_eval_cutoffs
+            .saturating_add(other.lazy_eval_cutoffs);
+        self.policy_orderings = self.policy_orderings.saturating_add(other.policy_orderings);
+        self.reused_playouts = self.reused_playouts.saturating_add(other.reused_playouts);
     }
 }
 
@@ -117,14 +235,22 @@ pub struct HashTableInfo {
     pub effective_mb: usize,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct EvalCacheInfo {
+    pub entries: usize,
+    pub filled: usize,
+}
+
 #[derive(Clone)]
 pub struct SearchAlgorithm {
-    small_evaluator: Arc<CModule>,
-    large_evaluator: Arc<CModule>,
+    small_evaluator: Option<Arc<Evaluator>>,
+    large_evaluator: Option<Arc<Evaluator>>,
     eval_device: Device,
     pub should_stop: Arc<AtomicBool>,
     tt: Arc<Mutex<TranspositionTable>>,
     eval_cache: Arc<Mutex<EvalCache>>,
+    /// Tree of the last `SearchMode::Mcts` search, reused when the game continues from it.
+    mcts_tree: Arc<Mutex<Option<MctsTree>>>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -187,6 +313,11 @@ impl TranspositionTable {
         self.configured_hash_mb = requested;
     }
 
+    fn clear(&mut self) {
+        self.entries.fill(TTEntry::default());
+        self.generation = 1;
+    }
+
     fn next_generation(&mut self) -> u8 {
         self.generation = self.generation.wrapping_add(1);
         if self.generation == 0 {
@@ -248,6 +379,10 @@ impl EvalCache {
         self.configured_mb = requested;
     }
 
+    fn clear(&mut self) {
+        self.entries.fill(EvalCacheEntry::default());
+    }
+
     fn probe(&self, key: u64) -> Option<i32> {
         let idx = (key as usize) & self.mask;
         let entry = self.entries[idx];
@@ -262,6 +397,26 @@ impl EvalCache {
         let idx = (key as usize) & self.mask;
         self.entries[idx] = EvalCacheEntry { key, score };
     }
+
+    fn info(&self) -> EvalCacheInfo {
+        EvalCacheInfo {
+            entries: self.entries.len(),
+            filled: self
+                .entries
+                .iter()
+                .filter(|entry| entry.key != EVAL_CACHE_EMPTY_KEY)
+                .count(),
+        }
+    }
+}
+
+fn eval_cache_key(board: &Board, use_large: bool) -> u64 {
+    let cache_key = board.zobrist() ^ if use_large { EVAL_LARGE_KEY_MIX } else { 0 };
+    if cache_key == EVAL_CACHE_EMPTY_KEY {
+        cache_key ^ 1
+    } else {
+        cache_key
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -301,18 +456,47 @@ impl ParallelWorkerState {
 
 impl SearchAlgorithm {
     pub fn new(
-        small_evaluator: Arc<CModule>,
-        large_evaluator: Arc<CModule>,
+        small_evaluator: Arc<Evaluator>,
+        large_evaluator: Arc<Evaluator>,
         eval_device: Device,
         should_stop: Arc<AtomicBool>,
     ) -> Self {
         Self {
-            small_evaluator,
-            large_evaluator,
+            small_evaluator: Some(small_evaluator),
+            large_evaluator: Some(large_evaluator),
             eval_device,
             should_stop,
             tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_MB))),
             eval_cache: Arc::new(Mutex::new(EvalCache::new(DEFAULT_EVAL_CACHE_MB))),
+            mcts_tree: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// A searcher without networks; every model mode falls back to the classical eval.
+    pub fn classical(should_stop: Arc<AtomicBool>) -> Self {
+        Self {
+            small_evaluator: None,
+            large_evaluator: None,
+            eval_device: Device::Cpu,
+            should_stop,
+            tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_MB))),
+            eval_cache: Arc::new(Mutex::new(EvalCache::new(DEFAULT_EVAL_CACHE_MB))),
+            mcts_tree: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// A searcher on the same networks but with its own stop flag, transposition table,
+    /// evaluation cache and MCTS tree, so independent searches can run side by side
+    /// without one ending another.
+    pub fn independent(&self) -> Self {
+        Self {
+            small_evaluator: self.small_evaluator.clone(),
+            large_evaluator: self.large_evaluator.clone(),
+            eval_device: self.eval_device,
+            should_stop: Arc::new(AtomicBool::new(false)),
+            tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_MB))),
+            eval_cache: Arc::new(Mutex::new(EvalCache::new(DEFAULT_EVAL_CACHE_MB))),
+            mcts_tree: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -324,6 +508,25 @@ impl SearchAlgorithm {
         default_search_threads()
     }
 
+    /// Occupancy of the shared evaluation cache and the scores it holds for `board` from
+    /// the (fast, accurate) evaluators.
+    pub fn eval_cache_state(&self, board: &Board) -> (EvalCacheInfo, Option<i32>, Option<i32>) {
+        let cache = self.eval_cache.lock().unwrap();
+        (
+            cache.info(),
+            cache.probe(eval_cache_key(board, false)),
+            cache.probe(eval_cache_key(board, true)),
+        )
+    }
+
+    /// Forgets everything learned by earlier searches: the transposition table, the
+    /// evaluation cache and the MCTS tree.
+    pub fn clear(&self) {
+        self.tt.lock().unwrap().clear();
+        self.eval_cache.lock().unwrap().clear();
+        *self.mcts_tree.lock().unwrap() = None;
+    }
+
     pub fn search(
         &self,
         board: &Board,
@@ -363,9 +566,6 @@ impl SearchAlgorithm {
             tch::set_num_interop_threads(1);
         });
 
-        let soft_deadline = request
-            .soft_time_ms
-            .map(|ms| start + Duration::from_millis(ms));
         let hard_deadline = request
             .hard_time_ms
             .map(|ms| start + Duration::from_millis(ms));
@@ -388,15 +588,41 @@ impl SearchAlgorithm {
             };
         }
 
-        let max_depth = request.max_depth.max(1).min(DEFAULT_MAX_DEPTH);
+        let max_depth = request.max_depth.clamp(1, DEFAULT_MAX_DEPTH);
+        if options.search_mode == SearchMode::Mcts {
+            let mcts = Mcts {
+                small_eval: self.small_evaluator.as_deref(),
+                large_eval: self.large_evaluator.as_deref(),
+                eval_device: self.eval_device,
+                should_stop: &self.should_stop,
+                tree: &self.mcts_tree,
+            };
+            let request = SearchRequest {
+                max_depth,
+                ..request
+            };
+            return mcts.search(
+                board,
+                &request,
+                options,
+                game_history,
+                self.mcts_threads(options),
+                fallback_move,
+                emit_info,
+                start,
+            );
+        }
+
+        let mut time_manager = TimeManager::new(start, &request, legal_moves.len());
         let effective_threads = self.effective_search_threads(options, legal_moves.len());
-        if effective_threads <= 1 {
+        if effective_threads <= 1 || request.max_nodes.is_some() {
             return self.search_single_threaded(
                 board,
                 options,
                 game_history,
-                soft_deadline,
+                &mut time_manager,
                 hard_deadline,
+                request.max_nodes,
                 max_depth,
                 fallback_move,
                 emit_info,
@@ -408,7 +634,7 @@ impl SearchAlgorithm {
             board,
             options,
             game_history,
-            soft_deadline,
+            &mut time_manager,
             hard_deadline,
             max_depth,
             fallback_move,
@@ -418,13 +644,15 @@ impl SearchAlgorithm {
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn search_single_threaded(
         &self,
         board: &Board,
         options: &SearchOptions,
         game_history: &[u64],
-        soft_deadline: Option<Instant>,
+        time_manager: &mut TimeManager,
         hard_deadline: Option<Instant>,
+        max_nodes: Option<u64>,
         max_depth: u32,
         fallback_move: BitMove,
         emit_info: bool,
@@ -438,18 +666,18 @@ impl SearchAlgorithm {
 
         let mut ctx = SearchContext::new(
             board,
-            &self.small_evaluator,
-            &self.large_evaluator,
+            self.small_evaluator.as_deref(),
+            self.large_evaluator.as_deref(),
             self.eval_device,
             self.should_stop.as_ref(),
             options,
             &mut tt_guard,
             tt_generation,
             &mut eval_cache_guard,
-            soft_deadline,
             hard_deadline,
             game_history,
         );
+        ctx.max_nodes = max_nodes;
 
         let mut root_board = board.shallow_clone();
         let mut best_move = fallback_move;
@@ -477,6 +705,7 @@ impl SearchAlgorithm {
                 }
 
                 if outcome.score <= alpha {
+                    time_manager.record_fail_low();
                     alpha = (alpha - window).max(-INF);
                     window = (window * 2).min(8_000);
                     continue;
@@ -513,7 +742,8 @@ impl SearchAlgorithm {
                 );
             }
 
-            if ctx.soft_deadline_reached() {
+            time_manager.record_iteration(best_move, best_score, ctx.nodes);
+            if time_manager.should_stop() {
                 break;
             }
         }
@@ -528,12 +758,13 @@ impl SearchAlgorithm {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn search_parallel_root(
         &self,
         board: &Board,
         options: &SearchOptions,
         game_history: &[u64],
-        soft_deadline: Option<Instant>,
+        time_manager: &mut TimeManager,
         hard_deadline: Option<Instant>,
         max_depth: u32,
         fallback_move: BitMove,
@@ -548,8 +779,9 @@ impl SearchAlgorithm {
                     board,
                     options,
                     game_history,
-                    soft_deadline,
+                    time_manager,
                     hard_deadline,
+                    None,
                     max_depth,
                     fallback_move,
                     emit_info,
@@ -602,7 +834,6 @@ impl SearchAlgorithm {
                 depth as i32,
                 options,
                 game_history,
-                soft_deadline,
                 hard_deadline,
                 &worker_states,
             );
@@ -646,7 +877,8 @@ impl SearchAlgorithm {
                 );
             }
 
-            if soft_deadline_reached(soft_deadline) {
+            time_manager.record_iteration(best_move, best_score, total_nodes);
+            if time_manager.should_stop() {
                 break;
             }
         }
@@ -670,7 +902,6 @@ impl SearchAlgorithm {
         depth: i32,
         options: &SearchOptions,
         game_history: &[u64],
-        soft_deadline: Option<Instant>,
         hard_deadline: Option<Instant>,
         worker_states: &[Mutex<ParallelWorkerState>],
     ) -> Vec<RootMoveEval> {
@@ -678,8 +909,7 @@ impl SearchAlgorithm {
         let results = Mutex::new(Vec::with_capacity(root_moves.len()));
 
         pool.scope(|scope| {
-            for worker_idx in 0..worker_states.len() {
-                let worker_state_ref = &worker_states[worker_idx];
+            for worker_state_ref in worker_states {
                 let next_idx_ref = &next_idx;
                 let results_ref = &results;
                 let root_moves_ref = root_moves;
@@ -687,7 +917,8 @@ impl SearchAlgorithm {
                 let options_ref = options;
                 let board_ref = board;
                 scope.spawn(move |_| {
-                    let mut worker_state = worker_state_ref.lock().unwrap();
+                    let mut worker_guard = worker_state_ref.lock().unwrap();
+                    let worker_state = &mut *worker_guard;
 
                     loop {
                         if self.should_abort_search(hard_deadline) {
@@ -706,7 +937,6 @@ impl SearchAlgorithm {
                             depth,
                             options_ref,
                             game_history_ref,
-                            soft_deadline,
                             hard_deadline,
                             &mut worker_state.tt,
                             tt_generation,
@@ -736,7 +966,6 @@ impl SearchAlgorithm {
         depth: i32,
         options: &SearchOptions,
         game_history: &[u64],
-        soft_deadline: Option<Instant>,
         hard_deadline: Option<Instant>,
         tt: &mut TranspositionTable,
         tt_generation: u8,
@@ -745,15 +974,14 @@ impl SearchAlgorithm {
         let mut worker_board = board.shallow_clone();
         let mut ctx = SearchContext::new(
             &worker_board,
-            &self.small_evaluator,
-            &self.large_evaluator,
+            self.small_evaluator.as_deref(),
+            self.large_evaluator.as_deref(),
             self.eval_device,
             self.should_stop.as_ref(),
             options,
             tt,
             tt_generation,
             eval_cache,
-            soft_deadline,
             hard_deadline,
             game_history,
         );
@@ -791,6 +1019,18 @@ impl SearchAlgorithm {
         workers.max(1)
     }
 
+    /// MCTS workers share one tree, so unlike root splitting they are not capped by the
+    /// number of legal moves or the hash budget.
+    fn mcts_threads(&self, options: &SearchOptions) -> usize {
+        if self.eval_device.is_cuda() {
+            return 1;
+        }
+        let max_hw_threads = std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1);
+        options.threads.clamp(1, max_hw_threads)
+    }
+
     fn should_abort_search(&self, hard_deadline: Option<Instant>) -> bool {
         if self.should_stop.load(Ordering::Relaxed) {
             return true;
@@ -805,6 +1045,24 @@ impl SearchAlgorithm {
     }
 }
 
+/// Network whose policy head guides the search, if any. Prefers the network the mode
+/// leans on; `Classical` stays network-free.
+pub(crate) fn policy_evaluator<'a>(
+    mode: ModelMode,
+    small_eval: Option<&'a Evaluator>,
+    large_eval: Option<&'a Evaluator>,
+) -> Option<&'a Evaluator> {
+    match mode {
+        ModelMode::Classical => None,
+        ModelMode::Small => small_eval,
+        ModelMode::Large => large_eval,
+        _ => large_eval
+            .filter(|evaluator| evaluator.has_policy())
+            .or(small_eval),
+    }
+    .filter(|evaluator| evaluator.has_policy())
+}
+
 fn default_search_threads() -> usize {
     let hw_threads = std::thread::available_parallelism()
         .map(|count| count.get())
@@ -812,13 +1070,6 @@ fn default_search_threads() -> usize {
     hw_threads.clamp(1, DEFAULT_THREADS_CAP)
 }
 
-fn soft_deadline_reached(soft_deadline: Option<Instant>) -> bool {
-    if let Some(deadline) = soft_deadline {
-        return Instant::now() >= deadline;
-    }
-    false
-}
-
 fn split_mb_budget(total_mb: usize, workers: usize) -> Vec<usize> {
     if workers == 0 {
         return Vec::new();
@@ -834,67 +1085,84 @@ fn split_mb_budget(total_mb: usize, workers: usize) -> Vec<usize> {
 }
 
 struct SearchContext<'a> {
-    small_eval: &'a CModule,
-    large_eval: &'a CModule,
+    small_eval: Option<&'a Evaluator>,
+    large_eval: Option<&'a Evaluator>,
+    /// Network whose policy head orders moves; `None` for value-only models.
+    policy_eval: Option<&'a Evaluator>,
     eval_device: Device,
     should_stop: &'a AtomicBool,
     model_mode: ModelMode,
+    lazy_eval: bool,
+    root_side: Player,
+    contempt_cp: i32,
     _debug_log: bool,
     tt: &'a mut TranspositionTable,
     tt_generation: u8,
     eval_cache: &'a mut EvalCache,
-    soft_deadline: Option<Instant>,
     hard_deadline: Option<Instant>,
+    max_nodes: Option<u64>,
+    /// Set once `max_nodes` is reached. The limit belongs to this search alone, so it
+    /// does not touch `should_stop`, which other searches may share.
+    node_limit_reached: bool,
     killers: Vec<[BitMove; 2]>,
     history: HistoryTable,
-    repetition_counts: HashMap<u64, u8>,
-    eval_features: [f32; 775],
+    game_repetitions: HashMap<u64, u8>,
+    tree_repetitions: HashMap<u64, u8>,
+    eval_features: [f32; MAX_INPUT_SIZE],
     stats: SearchStats,
     nodes: u64,
 }
 
 impl<'a> SearchContext<'a> {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         board: &Board,
-        small_eval: &'a CModule,
-        large_eval: &'a CModule,
+        small_eval: Option<&'a Evaluator>,
+        large_eval: Option<&'a Evaluator>,
         eval_device: Device,
         should_stop: &'a AtomicBool,
         options: &SearchOptions,
         tt: &'a mut TranspositionTable,
         tt_generation: u8,
         eval_cache: &'a mut EvalCache,
-        soft_deadline: Option<Instant>,
         hard_deadline: Option<Instant>,
         game_history: &[u64],
     ) -> Self {
-        let mut repetition_counts = HashMap::new();
+        let mut game_repetitions = HashMap::new();
         for &key in game_history {
-            let entry = repetition_counts.entry(key).or_insert(0u8);
+            let entry = game_repetitions.entry(key).or_insert(0u8);
             *entry = (*entry).saturating_add(1);
         }
         let root_key = board.zobrist();
-        let entry = repetition_counts.entry(root_key).or_insert(0u8);
+        let entry = game_repetitions.entry(root_key).or_insert(0u8);
         if *entry == 0 {
             *entry = 1;
         }
 
+        let policy_eval = policy_evaluator(options.model_mode, small_eval, large_eval);
+
         Self {
             small_eval,
             large_eval,
+            policy_eval,
             eval_device,
             should_stop,
             model_mode: options.model_mode,
+            lazy_eval: options.lazy_eval && options.model_mode != ModelMode::Classical,
+            root_side: board.turn(),
+            contempt_cp: options.contempt.resolve_cp(board),
             _debug_log: options.debug_log,
             tt,
             tt_generation,
             eval_cache,
-            soft_deadline,
             hard_deadline,
+            max_nodes: None,
+            node_limit_reached: false,
             killers: vec![[BitMove::null(), BitMove::null()]; MAX_PLY],
             history: [[[0; 64]; 64]; 2],
-            repetition_counts,
-            eval_features: [0.0; 775],
+            game_repetitions,
+            tree_repetitions: HashMap::new(),
+            eval_features: [0.0; MAX_INPUT_SIZE],
             stats: SearchStats::default(),
             nodes: 0,
         }
@@ -927,6 +1195,7 @@ impl<'a> SearchContext<'a> {
         let tt_move = self.tt_best_move(board.zobrist());
         let side_to_move = board.turn();
         let killers = self.killers[0];
+        let policy = self.policy_logits(board);
         order_moves(
             board,
             &mut moves,
@@ -934,6 +1203,7 @@ impl<'a> SearchContext<'a> {
             killers,
             &self.history,
             side_to_move,
+            policy.as_deref(),
         );
 
         let mut best_score = -INF;
@@ -1019,7 +1289,7 @@ impl<'a> SearchContext<'a> {
         depth: i32,
         ply: usize,
         mut alpha: i32,
-        beta: i32,
+        mut beta: i32,
         allow_null: bool,
     ) -> i32 {
         if self.bump_node_and_check_stop() {
@@ -1027,11 +1297,19 @@ impl<'a> SearchContext<'a> {
         }
 
         if ply >= MAX_PLY - 1 {
-            return self.evaluate(board, ply);
+            return self.evaluate(board, ply, alpha, beta, false);
         }
 
         if self.is_draw(board) {
-            return 0;
+            return self.draw_score(board);
+        }
+
+        // Mate distance pruning: no line through this node can beat mating on the next
+        // move or do worse than being mated right here.
+        alpha = alpha.max(-mate_in(ply));
+        beta = beta.min(mate_in(ply + 1));
+        if alpha >= beta {
+            return alpha;
         }
 
         let in_check = board.in_check();
@@ -1069,7 +1347,11 @@ impl<'a> SearchContext<'a> {
 
         let mut moves = board.generate_moves().to_vec();
         if moves.is_empty() {
-            return if in_check { -mate_in(ply) } else { 0 };
+            return if in_check {
+                -mate_in(ply)
+            } else {
+                self.draw_score(board)
+            };
         }
 
         let side_to_move = board.turn();
@@ -1079,6 +1361,12 @@ impl<'a> SearchContext<'a> {
             .copied()
             .unwrap_or([BitMove::null(), BitMove::null()]);
         let tt_move = self.tt_best_move(board.zobrist());
+        let pv_node = beta - alpha > 1;
+        let policy = if pv_node || depth >= POLICY_MIN_DEPTH {
+            self.policy_logits(board)
+        } else {
+            None
+        };
         order_moves(
             board,
             &mut moves,
@@ -1086,6 +1374,7 @@ impl<'a> SearchContext<'a> {
             killers,
             &self.history,
             side_to_move,
+            policy.as_deref(),
         );
 
         for (idx, mv) in moves.into_iter().enumerate() {
@@ -1143,6 +1432,12 @@ impl<'a> SearchContext<'a> {
             }
         }
 
+        // A partially searched node must not leave a bound in the TT; the caller discards
+        // the score once it sees the abort.
+        if self.node_limit_reached || self.should_stop.load(Ordering::Relaxed) {
+            return alpha;
+        }
+
         let bound = if best_score <= alpha_orig {
             Bound::Upper
         } else if best_score >= beta {
@@ -1169,7 +1464,7 @@ impl<'a> SearchContext<'a> {
         }
 
         if self.is_draw(board) {
-            return 0;
+            return self.draw_score(board);
         }
 
         if board.in_check() {
@@ -1190,6 +1485,7 @@ impl<'a> SearchContext<'a> {
                 killers,
                 &self.history,
                 side_to_move,
+                None,
             );
 
             for mv in evasions {
@@ -1220,7 +1516,18 @@ impl<'a> SearchContext<'a> {
             return alpha;
         }
 
-        let stand_pat = self.evaluate(board, ply);
+        if self.lazy_eval && classical::evaluate(board) - LAZY_EVAL_MARGIN_CP >= beta {
+            self.stats.lazy_eval_cutoffs = self.stats.lazy_eval_cutoffs.saturating_add(1);
+            self.stats.beta_cutoffs = self.stats.beta_cutoffs.saturating_add(1);
+            return beta;
+        }
+
+        // `Adaptive` asks the accurate model in quiet positions, so it needs the captures
+        // before scoring; the same list is searched below.
+        let early_captures = (self.model_mode == ModelMode::Adaptive)
+            .then(|| board.generate_moves_of_type(GenTypes::Captures).to_vec());
+        let quiet = early_captures.as_ref().is_some_and(Vec::is_empty);
+        let stand_pat = self.evaluate(board, ply, alpha, beta, quiet);
         if stand_pat >= beta {
             self.stats.beta_cutoffs = self.stats.beta_cutoffs.saturating_add(1);
             return beta;
@@ -1229,7 +1536,8 @@ impl<'a> SearchContext<'a> {
             alpha = stand_pat;
         }
 
-        let mut captures = board.generate_moves_of_type(GenTypes::Captures).to_vec();
+        let mut captures = early_captures
+            .unwrap_or_else(|| board.generate_moves_of_type(GenTypes::Captures).to_vec());
         if captures.is_empty() {
             return alpha;
         }
@@ -1272,67 +1580,119 @@ impl<'a> SearchContext<'a> {
         alpha
     }
 
-    fn evaluate(&mut self, board: &Board, ply: usize) -> i32 {
+    /// `quiet` says the side to move is not in check and has nothing to capture; only
+    /// `Adaptive` looks at it.
+    fn evaluate(&mut self, board: &Board, ply: usize, alpha: i32, beta: i32, quiet: bool) -> i32 {
         self.stats.eval_calls = self.stats.eval_calls.saturating_add(1);
 
-        let use_large = match self.model_mode {
-            ModelMode::Small => false,
-            ModelMode::Large => true,
-            ModelMode::HybridRoot => ply <= 1,
-        };
-
-        let mut cache_key = board.zobrist() ^ if use_large { EVAL_LARGE_KEY_MIX } else { 0 };
-        if cache_key == EVAL_CACHE_EMPTY_KEY {
-            cache_key ^= 1;
+        match self.model_mode {
+            ModelMode::Small => self.model_eval(board, false),
+            ModelMode::Large => self.model_eval(board, true),
+            ModelMode::HybridRoot => self.model_eval(board, ply <= 1),
+            ModelMode::Adaptive => {
+                // The leaf counts towards whichever model's score it ends up with.
+                let Some(fast) = self.network_eval(board, false) else {
+                    return self.model_eval(board, true);
+                };
+                let pv_node = beta - alpha > 1;
+                let near_window = fast > alpha.saturating_sub(ADAPTIVE_WINDOW_MARGIN_CP)
+                    && fast < beta.saturating_add(ADAPTIVE_WINDOW_MARGIN_CP);
+                if pv_node || near_window || quiet {
+                    self.model_eval(board, true)
+                } else {
+                    self.stats.fast_model_evals = self.stats.fast_model_evals.saturating_add(1);
+                    fast
+                }
+            }
+            ModelMode::Ensemble => {
+                let fast = self.model_eval(board, false);
+                let accurate = self.model_eval(board, true);
+                (fast + accurate) / 2
+            }
+            ModelMode::Classical => classical::evaluate(board),
         }
-        if let Some(score) = self.eval_cache.probe(cache_key) {
-            self.stats.eval_cache_hits = self.stats.eval_cache_hits.saturating_add(1);
-            return score;
+    }
+
+    fn policy_logits(&mut self, board: &Board) -> Option<Vec<f32>> {
+        let logits = self
+            .policy_eval?
+            .policy(board, &mut self.eval_features, self.eval_device)?;
+        self.stats.policy_orderings = self.stats.policy_orderings.saturating_add(1);
+        Some(logits)
+    }
+
+    /// Score from one network, or the classical eval when that network is not loaded.
+    fn model_eval(&mut self, board: &Board, use_large: bool) -> i32 {
+        let Some(score) = self.network_eval(board, use_large) else {
+            return classical::evaluate(board);
+        };
+        if use_large {
+            self.stats.accurate_model_evals = self.stats.accurate_model_evals.saturating_add(1);
+        } else {
+            self.stats.fast_model_evals = self.stats.fast_model_evals.saturating_add(1);
         }
+        score
+    }
 
-        encode_board_features(board, &mut self.eval_features);
-        let input = tch::Tensor::from_slice(&self.eval_features)
-            .view([1, 775])
-            .to_device(self.eval_device);
-        let raw = if use_large {
+    /// Score from one network through the eval cache, without counting it in the stats.
+    fn network_eval(&mut self, board: &Board, use_large: bool) -> Option<i32> {
+        let evaluator = if use_large {
             self.large_eval
-                .forward_ts(&[input])
-                .map(|tensor| tensor.double_value(&[]) as f32)
-                .unwrap_or(0.0)
         } else {
             self.small_eval
-                .forward_ts(&[input])
-                .map(|tensor| tensor.double_value(&[]) as f32)
-                .unwrap_or(0.0)
-        };
-        let mut score = (raw * EVAL_SCALE_CP).round() as i32;
-        if board.turn() == Player::Black {
-            score = -score;
+        }?;
+
+        let cache_key = eval_cache_key(board, use_large);
+        if let Some(score) = self.eval_cache.probe(cache_key) {
+            self.stats.eval_cache_hits = self.stats.eval_cache_hits.saturating_add(1);
+            return Some(score);
         }
+
+        let score = evaluator.evaluate(board, &mut self.eval_features, self.eval_device);
         self.eval_cache.store(cache_key, score);
-        score
+        Some(score)
     }
 
+    /// Game-history positions need a real three-fold repetition, but a position that
+    /// repeats inside the search tree is scored as a draw on its second occurrence since
+    /// either side could force the cycle again.
     fn is_draw(&self, board: &Board) -> bool {
         if board.rule_50() >= 100 {
+            // Mate delivered on the move that reaches the limit still wins.
+            return !board.checkmate();
+        }
+        if insufficient_material(board) {
             return true;
         }
-        self.repetition_counts
-            .get(&board.zobrist())
-            .copied()
-            .unwrap_or(0)
-            >= 3
+
+        let key = board.zobrist();
+        let tree_count = self.tree_repetitions.get(&key).copied().unwrap_or(0);
+        if tree_count >= 2 {
+            return true;
+        }
+        let game_count = self.game_repetitions.get(&key).copied().unwrap_or(0);
+        game_count.saturating_add(tree_count) >= 3
+    }
+
+    /// Draw value from the side to move's perspective: negative contempt for the root
+    /// side, positive for its opponent.
+    fn draw_score(&self, board: &Board) -> i32 {
+        if board.turn() == self.root_side {
+            -self.contempt_cp
+        } else {
+            self.contempt_cp
+        }
     }
 
     fn push_repetition(&mut self, key: u64) {
-        let entry = self.repetition_counts.entry(key).or_insert(0u8);
+        let entry = self.tree_repetitions.entry(key).or_insert(0u8);
         *entry = entry.saturating_add(1);
     }
 
     fn pop_repetition(&mut self, key: u64) {
-        if let Some(entry) = self.repetition_counts.get_mut(&key) {
+        if let Some(entry) = self.tree_repetitions.get_mut(&key) {
             if *entry <= 1 {
-                self.repetition_counts.remove(&key);
+                self.tree_repetitions.remove(&key);
             } else {
                 *entry -= 1;
             }
@@ -1367,7 +1727,7 @@ impl<'a> SearchContext<'a> {
     }
 
     fn should_abort(&self) -> bool {
-        if self.should_stop.load(Ordering::Relaxed) {
+        if self.node_limit_reached || self.should_stop.load(Ordering::Relaxed) {
             return true;
         }
         if let Some(deadline) = self.hard_deadline {
@@ -1379,16 +1739,16 @@ impl<'a> SearchContext<'a> {
         false
     }
 
-    fn soft_deadline_reached(&self) -> bool {
-        if let Some(deadline) = self.soft_deadline {
-            return Instant::now() >= deadline;
-        }
-        false
-    }
-
     fn bump_node_and_check_stop(&mut self) -> bool {
+        if self
+            .max_nodes
+            .is_some_and(|max_nodes| self.nodes >= max_nodes)
+        {
+            self.node_limit_reached = true;
+            return true;
+        }
         self.nodes = self.nodes.saturating_add(1);
-        if self.nodes % TIME_CHECK_INTERVAL == 0 {
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
             return self.should_abort();
         }
         self.should_stop.load(Ordering::Relaxed)
@@ -1481,16 +1841,25 @@ fn eval_cache_entries_from_mb(cache_mb: usize) -> usize {
     }
 }
 
-fn piece_value(piece_type: PieceType) -> i32 {
-    match piece_type {
-        PieceType::P => 100,
-        PieceType::N => 320,
-        PieceType::B => 330,
-        PieceType::R => 500,
-        PieceType::Q => 900,
-        PieceType::K => 20_000,
-        PieceType::None | PieceType::All => 0,
+/// KvK, KNvK, KBvK and any number of bishops confined to one square colour.
+pub fn insufficient_material(board: &Board) -> bool {
+    let heavy_or_pawns = board.piece_bb_both_players(PieceType::P)
+        | board.piece_bb_both_players(PieceType::R)
+        | board.piece_bb_both_players(PieceType::Q);
+    if !heavy_or_pawns.is_empty() {
+        return false;
+    }
+
+    let knights = board.piece_bb_both_players(PieceType::N);
+    let bishops = board.piece_bb_both_players(PieceType::B);
+    let minors = knights.count_bits() + bishops.count_bits();
+    if minors <= 1 {
+        return true;
     }
+
+    knights.is_empty()
+        && ((bishops & BitBoard::DARK_SQUARES).is_empty()
+            || (bishops & BitBoard::LIGHT_SQUARES).is_empty())
 }
 
 fn capture_move_gain(board: &Board, mv: BitMove) -> i32 {
@@ -1529,11 +1898,11 @@ fn score_from_tt(score: i32, ply: usize) -> i32 {
     }
 }
 
-fn mate_in(ply: usize) -> i32 {
+pub(crate) fn mate_in(ply: usize) -> i32 {
     MATE_SCORE - ply as i32
 }
 
-fn score_to_uci(score: i32) -> (&'static str, i32) {
+pub fn score_to_uci(score: i32) -> (&'static str, i32) {
     if score > MATE_THRESHOLD {
         let plies_to_mate = (MATE_SCORE - score).max(0);
         let mate_moves = (plies_to_mate + 1) / 2;
@@ -0,0 +1,319 @@
+use crate::search_algorithm::{
+    insufficient_material, SearchAlgorithm, SearchOptions, SearchRequest,
+};
+use crate::time_manager::{plan_search, GoOptions};
+use crate::training::{DataFormat, TrainingRecord};
+use crate::utils::board_from_epd;
+use pleco::{BitMove, Board, Player};
+
+pub const DEFAULT_SELFPLAY_GAMES: usize = 1;
+pub const DEFAULT_SELFPLAY_DEPTH: u32 = 4;
+/// Games still running after this many plies are scored as draws.
+pub const DEFAULT_MAX_PLIES: usize = 400;
+pub const DEFAULT_SELFPLAY_SEED: u64 = 1;
+/// Consecutive plies a score must stay past an adjudication threshold.
+pub const ADJUDICATION_PLIES: usize = 8;
+/// Draws are only adjudicated from this move number on.
+pub const DRAW_ADJUDICATION_MOVE: u32 = 40;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SelfPlayConfig {
+    pub games: usize,
+    /// Search limits per move. Without any, moves are searched to
+    /// `DEFAULT_SELFPLAY_DEPTH`.
+    pub depth: Option<u32>,
+    pub movetime_ms: Option<u64>,
+    pub nodes: Option<u64>,
+    pub max_plies: usize,
+    /// File of opening positions (FEN or EPD lines, or JSON lines with a `fen` field),
+    /// used in turn; games start from the initial position without one.
+    pub book: Option<String>,
+    /// Uniformly random moves played after the opening position, before searching starts.
+    pub random_plies: usize,
+    pub seed: u64,
+    /// A game is won once both sides' scores have agreed on a winner by at least this
+    /// many centipawns for `ADJUDICATION_PLIES` plies.
+    pub resign_cp: Option<i32>,
+    /// A game is drawn once the scores have stayed within this many centipawns of zero
+    /// for `ADJUDICATION_PLIES` plies, from move `DRAW_ADJUDICATION_MOVE` on.
+    pub draw_cp: Option<i32>,
+    /// Where to write every searched position as training data.
+    pub output: Option<String>,
+    pub format: DataFormat,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        Self {
+            games: DEFAULT_SELFPLAY_GAMES,
+            depth: None,
+            movetime_ms: None,
+            nodes: None,
+            max_plies: DEFAULT_MAX_PLIES,
+            book: None,
+            random_plies: 0,
+            seed: DEFAULT_SELFPLAY_SEED,
+            resign_cp: None,
+            draw_cp: None,
+            output: None,
+            format: DataFormat::default(),
+        }
+    }
+}
+
+impl SelfPlayConfig {
+    pub fn request(&self) -> SearchRequest {
+        let go_options = GoOptions {
+            depth: match (self.depth, self.movetime_ms, self.nodes) {
+                (None, None, None) => Some(DEFAULT_SELFPLAY_DEPTH),
+                (depth, _, _) => depth,
+            },
+            movetime_ms: self.movetime_ms,
+            nodes: self.nodes,
+            ..GoOptions::default()
+        };
+        plan_search(&go_options, Player::White, 0).request
+    }
+
+    /// The start of game `game` (counting from 0): the next book position, or the
+    /// initial position, followed by `random_plies` random moves that depend only on
+    /// `seed` and `game`.
+    pub fn opening(&self, book: &[Board], game: usize) -> Board {
+        let mut board = match book {
+            [] => Board::start_pos(),
+            book => book[game % book.len()].shallow_clone(),
+        };
+        let mut state = self.seed ^ (game as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..self.random_plies {
+            let moves = board.generate_moves();
+            if moves.is_empty() {
+                break;
+            }
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            board.apply_move(moves[(state >> 33) as usize % moves.len()]);
+        }
+        board
+    }
+}
+
+/// Parses an opening book: FEN or EPD lines, or JSON lines with a `fen` field such as
+/// `scripts/elo_openings.jsonl`. Blank lines and `#` comments are skipped.
+pub fn parse_openings(contents: &str) -> Result<Vec<Board>, String> {
+    let mut openings = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fen = if line.starts_with('{') {
+            let value: serde_json::Value =
+                serde_json::from_str(line).map_err(|err| format!("line {}: {err}", index + 1))?;
+            match value.get("fen").and_then(|fen| fen.as_str()) {
+                Some(fen) => fen.to_string(),
+                None => return Err(format!("line {}: missing `fen`", index + 1)),
+            }
+        } else {
+            line.to_string()
+        };
+        let board = board_from_epd(&fen)
+            .ok_or_else(|| format!("line {}: invalid position `{fen}`", index + 1))?;
+        openings.push(board);
+    }
+    Ok(openings)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    /// The result tag used in PGN.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw => "1/2-1/2",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    FiftyMoves,
+    InsufficientMaterial,
+    Repetition,
+    MaxPlies,
+    AdjudicatedWin,
+    AdjudicatedDraw,
+    /// The side to move ran out of time, played an illegal move or lost its engine; it
+    /// loses. Only engine matches end this way.
+    TimeForfeit,
+    IllegalMove,
+    EngineFailure,
+}
+
+impl Termination {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Checkmate => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::FiftyMoves => "fifty_moves",
+            Self::InsufficientMaterial => "insufficient_material",
+            Self::Repetition => "repetition",
+            Self::MaxPlies => "max_plies",
+            Self::AdjudicatedWin => "adjudicated_win",
+            Self::AdjudicatedDraw => "adjudicated_draw",
+            Self::TimeForfeit => "time_forfeit",
+            Self::IllegalMove => "illegal_move",
+            Self::EngineFailure => "engine_failure",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub start_fen: String,
+    pub moves: Vec<BitMove>,
+    /// The search score of each move, from the mover's point of view.
+    pub scores: Vec<i32>,
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl GameRecord {
+    /// Every position a move was searched in, labelled with its score and the game's
+    /// result.
+    pub fn training_records(&self) -> Vec<TrainingRecord> {
+        let mut board = Board::from_fen(&self.start_fen).expect("games start from valid FENs");
+        let mut records = Vec::with_capacity(self.moves.len());
+        for (&mv, &score_cp) in self.moves.iter().zip(&self.scores) {
+            let wdl = match (self.result, board.turn()) {
+                (GameResult::Draw, _) => 0.5,
+                (GameResult::WhiteWins, Player::White) | (GameResult::BlackWins, Player::Black) => {
+                    1.0
+                }
+                _ => 0.0,
+            };
+            records.push(TrainingRecord {
+                fen: board.fen(),
+                score_cp,
+                wdl,
+                best_move: mv,
+            });
+            board.apply_move(mv);
+        }
+        records
+    }
+}
+
+/// How the game stands by the rules alone: checkmate, stalemate, the fifty-move rule,
+/// insufficient material or a threefold repetition of `board` within `history` (which
+/// holds the keys of every position so far, `board` included).
+pub fn game_over(board: &Board, history: &[u64]) -> Option<(GameResult, Termination)> {
+    if board.generate_moves().is_empty() {
+        if !board.in_check() {
+            return Some((GameResult::Draw, Termination::Stalemate));
+        }
+        let winner = match board.turn() {
+            Player::White => GameResult::BlackWins,
+            Player::Black => GameResult::WhiteWins,
+        };
+        return Some((winner, Termination::Checkmate));
+    }
+    if board.rule_50() >= 100 {
+        return Some((GameResult::Draw, Termination::FiftyMoves));
+    }
+    if insufficient_material(board) {
+        return Some((GameResult::Draw, Termination::InsufficientMaterial));
+    }
+    let key = board.zobrist();
+    if history.iter().filter(|&&seen| seen == key).count() >= 3 {
+        return Some((GameResult::Draw, Termination::Repetition));
+    }
+    None
+}
+
+/// The adjudicated result once the last `ADJUDICATION_PLIES` scores (each from the
+/// mover's point of view, the last one by the side that is no longer to move) allow one
+/// under the `resign_cp` and `draw_cp` thresholds of `SelfPlayConfig`.
+pub fn adjudicate(
+    resign_cp: Option<i32>,
+    draw_cp: Option<i32>,
+    board: &Board,
+    scores: &[i32],
+) -> Option<(GameResult, Termination)> {
+    if scores.len() < ADJUDICATION_PLIES {
+        return None;
+    }
+    // Flip every score to White's point of view; the last move was made by the side
+    // not to move now.
+    let mut mover_is_white = board.turn() == Player::Black;
+    let mut white_scores = Vec::with_capacity(ADJUDICATION_PLIES);
+    for &score in scores.iter().rev().take(ADJUDICATION_PLIES) {
+        white_scores.push(if mover_is_white { score } else { -score });
+        mover_is_white = !mover_is_white;
+    }
+
+    if let Some(resign_cp) = resign_cp {
+        if white_scores.iter().all(|&score| score >= resign_cp) {
+            return Some((GameResult::WhiteWins, Termination::AdjudicatedWin));
+        }
+        if white_scores.iter().all(|&score| score <= -resign_cp) {
+            return Some((GameResult::BlackWins, Termination::AdjudicatedWin));
+        }
+    }
+    if let Some(draw_cp) = draw_cp {
+        if board.moves_played() / 2 + 1 >= DRAW_ADJUDICATION_MOVE as u16
+            && white_scores.iter().all(|score| score.abs() <= draw_cp)
+        {
+            return Some((GameResult::Draw, Termination::AdjudicatedDraw));
+        }
+    }
+    None
+}
+
+/// Plays `start` out with the searcher moving for both sides, within `config`'s search
+/// limits, ply cap and adjudication thresholds.
+pub fn play_game(
+    searcher: &SearchAlgorithm,
+    options: &SearchOptions,
+    config: &SelfPlayConfig,
+    start: &Board,
+) -> GameRecord {
+    let request = config.request();
+    let mut board = start.shallow_clone();
+    let mut history = vec![board.zobrist()];
+    let mut moves = Vec::new();
+    let mut scores = Vec::new();
+    let finish = |moves, scores, (result, termination)| GameRecord {
+        start_fen: start.fen(),
+        moves,
+        scores,
+        result,
+        termination,
+    };
+    loop {
+        if let Some(outcome) = game_over(&board, &history) {
+            return finish(moves, scores, outcome);
+        }
+        if moves.len() >= config.max_plies {
+            return finish(moves, scores, (GameResult::Draw, Termination::MaxPlies));
+        }
+
+        let result = searcher.search_quiet(&board, request, options, &history);
+        board.apply_move(result.best_move);
+        history.push(board.zobrist());
+        moves.push(result.best_move);
+        scores.push(result.score_cp);
+        if let Some(outcome) = adjudicate(config.resign_cp, config.draw_cp, &board, &scores) {
+            return finish(moves, scores, outcome);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::search_algorithm::{SearchRequest, DEFAULT_MAX_DEPTH};
+use pleco::{BitMove, Player};
+use std::time::Instant;
+
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+const DEFAULT_FALLBACK_MOVETIME_MS: u64 = 2_000;
+const MAX_TIME_FACTOR: u64 = 3;
+const EMERGENCY_TIME_MS: u64 = 1_000;
+const EMERGENCY_OVERHEAD_MULTIPLIER: u64 = 10;
+const EMERGENCY_MAX_DEPTH: u32 = 4;
+const DEFAULT_ITERATION_GROWTH: f64 = 3.0;
+const MIN_ITERATION_GROWTH: f64 = 1.5;
+const MAX_ITERATION_GROWTH: f64 = 8.0;
+const MAX_STABILITY_ITERATIONS: u32 = 4;
+const SCORE_DROP_CAP_CP: i32 = 150;
+const FEW_LEGAL_MOVES: usize = 3;
+
+/// Decides after each completed iteration whether another one is worth starting.
+///
+/// With a clock (`soft_time_ms < hard_time_ms`) the soft budget is stretched while the
+/// best move keeps changing, the score drops or the root fails low, and shrunk once the
+/// best move has been stable for a few iterations. Independently of that, an iteration
+/// is not started when the measured node rate says it cannot finish before the hard
+/// deadline, since incomplete iterations are thrown away. Fixed budgets (`movetime`)
+/// are never stretched or shrunk.
+pub struct TimeManager {
+    start: Instant,
+    optimum_ms: Option<u64>,
+    maximum_ms: Option<u64>,
+    legal_moves: usize,
+    best_move: BitMove,
+    stability: u32,
+    last_score: Option<i32>,
+    score_drop_cp: i32,
+    pending_fail_lows: u32,
+    fail_lows: u32,
+    prev_iteration_nodes: u64,
+    last_iteration_nodes: u64,
+    total_nodes: u64,
+}
+
+impl TimeManager {
+    pub fn new(start: Instant, request: &SearchRequest, legal_moves: usize) -> Self {
+        Self {
+            start,
+            optimum_ms: request.soft_time_ms,
+            maximum_ms: request.hard_time_ms,
+            legal_moves,
+            best_move: BitMove::null(),
+            stability: 0,
+            last_score: None,
+            score_drop_cp: 0,
+            pending_fail_lows: 0,
+            fail_lows: 0,
+            prev_iteration_nodes: 0,
+            last_iteration_nodes: 0,
+            total_nodes: 0,
+        }
+    }
+
+    pub fn record_fail_low(&mut self) {
+        self.pending_fail_lows = self.pending_fail_lows.saturating_add(1);
+    }
+
+    /// `total_nodes` is the cumulative node count of the whole search so far.
+    pub fn record_iteration(&mut self, best_move: BitMove, score: i32, total_nodes: u64) {
+        if best_move == self.best_move {
+            self.stability = self.stability.saturating_add(1);
+        } else {
+            self.stability = 0;
+            self.best_move = best_move;
+        }
+
+        self.score_drop_cp = match self.last_score {
+            Some(last) => (last - score).clamp(0, SCORE_DROP_CAP_CP),
+            None => 0,
+        };
+        self.last_score = Some(score);
+
+        self.fail_lows = self.pending_fail_lows;
+        self.pending_fail_lows = 0;
+
+        self.prev_iteration_nodes = self.last_iteration_nodes;
+        self.last_iteration_nodes = total_nodes.saturating_sub(self.total_nodes);
+        self.total_nodes = total_nodes;
+    }
+
+    pub fn should_stop(&self) -> bool {
+        let Some(optimum_ms) = self.optimum_ms else {
+            return false;
+        };
+        let elapsed_ms = self.elapsed_ms();
+        let maximum_ms = self.maximum_ms.unwrap_or(optimum_ms) as f64;
+
+        if (optimum_ms as f64) < maximum_ms {
+            if self.legal_moves == 1 {
+                return true;
+            }
+            let target_ms = (optimum_ms as f64 * self.scale()).min(maximum_ms);
+            if elapsed_ms >= target_ms {
+                return true;
+            }
+        } else if elapsed_ms >= optimum_ms as f64 {
+            return true;
+        }
+
+        self.predicted_next_iteration_ms(elapsed_ms)
+            .map(|predicted_ms| elapsed_ms + predicted_ms > maximum_ms)
+            .unwrap_or(false)
+    }
+
+    fn scale(&self) -> f64 {
+        let stability = self.stability.min(MAX_STABILITY_ITERATIONS) as f64;
+        let mut scale = 1.3 - 0.15 * stability;
+        scale *= 1.0 + self.score_drop_cp as f64 / (2 * SCORE_DROP_CAP_CP) as f64;
+        scale *= 1.0 + 0.25 * self.fail_lows.min(2) as f64;
+        if self.legal_moves <= FEW_LEGAL_MOVES {
+            scale *= 0.75;
+        }
+        scale
+    }
+
+    /// Network evaluation dominates the cost of a node, so the node rate measured so far
+    /// is a good predictor of how long the next, larger iteration will take.
+    fn predicted_next_iteration_ms(&self, elapsed_ms: f64) -> Option<f64> {
+        if self.total_nodes == 0 || elapsed_ms <= 0.0 {
+            return None;
+        }
+        let nodes_per_ms = self.total_nodes as f64 / elapsed_ms;
+        let growth = if self.prev_iteration_nodes > 0 {
+            (self.last_iteration_nodes as f64 / self.prev_iteration_nodes as f64)
+                .clamp(MIN_ITERATION_GROWTH, MAX_ITERATION_GROWTH)
+        } else {
+            DEFAULT_ITERATION_GROWTH
+        };
+        Some(self.last_iteration_nodes as f64 * growth / nodes_per_ms)
+    }
+
+    fn elapsed_ms(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1_000.0
+    }
+}
+
+/// Limits parsed from a UCI `go` command.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GoOptions {
+    pub depth: Option<u32>,
+    pub movetime_ms: Option<u64>,
+    pub wtime_ms: Option<u64>,
+    pub btime_ms: Option<u64>,
+    pub winc_ms: Option<u64>,
+    pub binc_ms: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+    pub nodes: Option<u64>,
+    /// `go perft N` counts moves instead of searching.
+    pub perft: Option<u32>,
+}
+
+impl GoOptions {
+    pub fn parse(command: &str) -> Self {
+        let mut options = Self::default();
+        let mut iter = command.split_whitespace().skip(1);
+
+        while let Some(token) = iter.next() {
+            match token {
+                "depth" => options.depth = iter.next().and_then(|value| value.parse::<u32>().ok()),
+                "movetime" => {
+                    options.movetime_ms = iter.next().and_then(|value| value.parse::<u64>().ok())
+                }
+                "wtime" => {
+                    options.wtime_ms = iter.next().and_then(|value| value.parse::<u64>().ok())
+                }
+                "btime" => {
+                    options.btime_ms = iter.next().and_then(|value| value.parse::<u64>().ok())
+                }
+                "winc" => options.winc_ms = iter.next().and_then(|value| value.parse::<u64>().ok()),
+                "binc" => options.binc_ms = iter.next().and_then(|value| value.parse::<u64>().ok()),
+                "movestogo" => {
+                    options.movestogo = iter.next().and_then(|value| value.parse::<u32>().ok())
+                }
+                "nodes" => options.nodes = iter.next().and_then(|value| value.parse::<u64>().ok()),
+                "infinite" => options.infinite = true,
+                "perft" => options.perft = iter.next().and_then(|value| value.parse::<u32>().ok()),
+                _ => {}
+            }
+        }
+
+        options
+    }
+
+    fn clock_for(&self, side: Player) -> Option<(u64, u64)> {
+        match side {
+            Player::White => Some((self.wtime_ms?, self.winc_ms.unwrap_or(0))),
+            Player::Black => Some((self.btime_ms?, self.binc_ms.unwrap_or(0))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SearchPlan {
+    pub request: SearchRequest,
+    /// Set when the clock is nearly exhausted; the caller should restrict itself to the
+    /// fast model so the capped-depth search stays cheap.
+    pub emergency: bool,
+}
+
+impl SearchPlan {
+    fn untimed(max_depth: u32) -> Self {
+        Self {
+            request: SearchRequest {
+                max_depth,
+                ..SearchRequest::default()
+            },
+            emergency: false,
+        }
+    }
+
+    fn fixed(max_depth: u32, budget_ms: u64) -> Self {
+        let budget_ms = budget_ms.max(1);
+        Self {
+            request: SearchRequest {
+                max_depth,
+                soft_time_ms: Some(budget_ms),
+                hard_time_ms: Some(budget_ms),
+                ..SearchRequest::default()
+            },
+            emergency: false,
+        }
+    }
+}
+
+/// Turns `go` limits into search deadlines. `move_overhead_ms` is reserved on every move
+/// for GUI/transport lag and evaluator warmup, so no deadline ever reaches the clock.
+pub fn plan_search(options: &GoOptions, side: Player, move_overhead_ms: u64) -> SearchPlan {
+    let mut plan = plan_limits(options, side, move_overhead_ms);
+    plan.request.max_nodes = options.nodes;
+    plan
+}
+
+fn plan_limits(options: &GoOptions, side: Player, move_overhead_ms: u64) -> SearchPlan {
+    let max_depth = options.depth.unwrap_or(DEFAULT_MAX_DEPTH).max(1);
+
+    if options.infinite {
+        return SearchPlan::untimed(max_depth);
+    }
+
+    if let Some(movetime_ms) = options.movetime_ms {
+        return SearchPlan::fixed(max_depth, movetime_ms.saturating_sub(move_overhead_ms));
+    }
+
+    if let Some((remaining_ms, increment_ms)) = options.clock_for(side) {
+        let usable_ms = remaining_ms.saturating_sub(move_overhead_ms);
+        let moves_to_go = options
+            .movestogo
+            .map(u64::from)
+            .unwrap_or(DEFAULT_MOVES_TO_GO)
+            .max(1);
+        let optimum = (usable_ms / moves_to_go).saturating_add(increment_ms.saturating_mul(4) / 5);
+        let cap = usable_ms.saturating_mul(7) / 10;
+        let maximum = optimum.saturating_mul(MAX_TIME_FACTOR).min(cap).max(1);
+        let optimum = optimum.clamp(1, maximum);
+
+        let emergency_threshold =
+            EMERGENCY_TIME_MS.max(move_overhead_ms.saturating_mul(EMERGENCY_OVERHEAD_MULTIPLIER));
+        let emergency = remaining_ms < emergency_threshold;
+        let max_depth = if emergency {
+            max_depth.min(EMERGENCY_MAX_DEPTH)
+        } else {
+            max_depth
+        };
+
+        return SearchPlan {
+            request: SearchRequest {
+                max_depth,
+                soft_time_ms: Some(optimum),
+                hard_time_ms: Some(maximum),
+                ..SearchRequest::default()
+            },
+            emergency,
+        };
+    }
+
+    if options.depth.is_some() || options.nodes.is_some() {
+        return SearchPlan::untimed(max_depth);
+    }
+
+    SearchPlan::fixed(
+        max_depth,
+        DEFAULT_FALLBACK_MOVETIME_MS.saturating_sub(move_overhead_ms),
+    )
+}
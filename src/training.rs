@@ -0,0 +1,355 @@
+use crate::utils::{encode_board_features, parse_move};
+use pleco::{BitMove, Board};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+/// Size of one record in the binary format.
+pub const RECORD_BYTES: usize = 35;
+
+const PIECE_CHARS: &[u8; 6] = b"PNBRQK";
+const CASTLING_CHARS: &[u8; 4] = b"KQkq";
+const NO_EP_SQUARE: u8 = 255;
+const WDL_SCALE: f32 = 10_000.0;
+/// Columns of an `encode_board_features` row.
+pub const FEATURE_COLUMNS: usize = 775;
+/// `.npy` headers are padded so the data starts on this boundary.
+const NPY_ALIGNMENT: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DataFormat {
+    /// One `fen | score_cp | wdl | best_move` line per position.
+    #[default]
+    Text,
+    /// `RECORD_BYTES` per position; see `TrainingRecord::to_bytes`.
+    Binary,
+}
+
+impl DataFormat {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" => Some(Self::Text),
+            "binary" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// A labelled position. The search score and `wdl` (1 win, 0.5 draw, 0 loss, or an
+/// expectation in between) are both from the side to move's point of view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainingRecord {
+    pub fen: String,
+    pub score_cp: i32,
+    pub wdl: f32,
+    pub best_move: BitMove,
+}
+
+impl TrainingRecord {
+    /// `fen | score_cp | wdl | best_move`, with the move in UCI notation (`0000` for none).
+    pub fn to_line(&self) -> String {
+        let best_move = if self.best_move.is_null() {
+            "0000".to_string()
+        } else {
+            self.best_move.to_string()
+        };
+        format!(
+            "{} | {} | {} | {}",
+            self.fen, self.score_cp, self.wdl, best_move
+        )
+    }
+
+    pub fn from_line(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+        let [fen, score_cp, wdl, best_move] = fields[..] else {
+            return Err(format!("expected four `|` separated fields in `{line}`"));
+        };
+        let board = Board::from_fen(fen).map_err(|_| format!("invalid FEN `{fen}`"))?;
+        Ok(Self {
+            fen: fen.to_string(),
+            score_cp: score_cp
+                .parse()
+                .map_err(|_| format!("invalid score `{score_cp}`"))?,
+            wdl: wdl.parse().map_err(|_| format!("invalid wdl `{wdl}`"))?,
+            best_move: match best_move {
+                "0000" => BitMove::null(),
+                text => parse_move(&board, text)
+                    .ok_or_else(|| format!("`{text}` is not a legal move in `{fen}`"))?,
+            },
+        })
+    }
+
+    /// Packs the record into `RECORD_BYTES` little-endian bytes:
+    ///
+    /// * `0..8`: occupied squares, bit 0 = a1 and bit 63 = h8
+    /// * `8..24`: a 4-bit code per occupied square in bit order, low nibble first:
+    ///   1..=6 for P N B R Q K, plus 8 for black
+    /// * `24`: bit 0 set with black to move, bits 1..=4 the castling rights K Q k q
+    /// * `25`: en-passant square (0 = a1), 255 for none
+    /// * `26`: halfmove clock, saturating
+    /// * `27..29`: fullmove number
+    /// * `29..31`: score in centipawns as an `i16`, saturating
+    /// * `31..33`: `wdl` × 10000
+    /// * `33..35`: best move in pleco's 16-bit encoding
+    pub fn to_bytes(&self) -> Result<[u8; RECORD_BYTES], String> {
+        let invalid = || format!("invalid FEN `{}`", self.fen);
+        let fields: Vec<&str> = self.fen.split_whitespace().collect();
+        let [placement, turn, castling, ep, halfmove, fullmove] = fields[..] else {
+            return Err(invalid());
+        };
+
+        let mut squares = [0u8; 64];
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(invalid());
+        }
+        for (row, rank) in ranks.iter().enumerate() {
+            let mut file = 0;
+            for ch in rank.bytes() {
+                if ch.is_ascii_digit() {
+                    file += usize::from(ch - b'0');
+                    continue;
+                }
+                let kind = PIECE_CHARS
+                    .iter()
+                    .position(|&piece| piece == ch.to_ascii_uppercase())
+                    .ok_or_else(invalid)?;
+                if file >= 8 {
+                    return Err(invalid());
+                }
+                squares[(7 - row) * 8 + file] =
+                    kind as u8 + 1 + 8 * u8::from(ch.is_ascii_lowercase());
+                file += 1;
+            }
+        }
+
+        let mut bytes = [0u8; RECORD_BYTES];
+        let mut occupied = 0u64;
+        let occupied_squares = squares.iter().enumerate().filter(|(_, &code)| code != 0);
+        for (piece, (square, &code)) in occupied_squares.enumerate() {
+            if piece == 32 {
+                return Err(invalid());
+            }
+            occupied |= 1 << square;
+            bytes[8 + piece / 2] |= code << (4 * (piece % 2));
+        }
+        bytes[..8].copy_from_slice(&occupied.to_le_bytes());
+
+        bytes[24] = u8::from(turn == "b");
+        for (bit, &right) in CASTLING_CHARS.iter().enumerate() {
+            if castling.bytes().any(|ch| ch == right) {
+                bytes[24] |= 2 << bit;
+            }
+        }
+        bytes[25] = match ep.as_bytes() {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => (rank - b'1') * 8 + (file - b'a'),
+            _ => NO_EP_SQUARE,
+        };
+        let halfmove: u32 = halfmove.parse().map_err(|_| invalid())?;
+        bytes[26] = halfmove.min(u32::from(u8::MAX)) as u8;
+        let fullmove: u16 = fullmove.parse().map_err(|_| invalid())?;
+        bytes[27..29].copy_from_slice(&fullmove.to_le_bytes());
+        let score = self.score_cp.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+        bytes[29..31].copy_from_slice(&score.to_le_bytes());
+        let wdl = (self.wdl.clamp(0.0, 1.0) * WDL_SCALE).round() as u16;
+        bytes[31..33].copy_from_slice(&wdl.to_le_bytes());
+        bytes[33..35].copy_from_slice(&self.best_move.get_raw().to_le_bytes());
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8; RECORD_BYTES]) -> Self {
+        let mut occupied = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let mut squares = [0u8; 64];
+        let mut pieces = 0;
+        while occupied != 0 {
+            let square = occupied.trailing_zeros() as usize;
+            squares[square] = (bytes[8 + pieces / 2] >> (4 * (pieces % 2))) & 0xf;
+            occupied &= occupied - 1;
+            pieces += 1;
+        }
+
+        let mut fen = String::new();
+        for row in 0..8 {
+            let mut empty = 0;
+            for file in 0..8 {
+                let code = squares[(7 - row) * 8 + file];
+                if code == 0 {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                let piece = PIECE_CHARS[usize::from((code & 7).saturating_sub(1)).min(5)];
+                fen.push(if code & 8 != 0 {
+                    piece.to_ascii_lowercase()
+                } else {
+                    piece
+                } as char);
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if row < 7 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if bytes[24] & 1 != 0 { " b " } else { " w " });
+        let castling: String = CASTLING_CHARS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| bytes[24] & (2 << bit) != 0)
+            .map(|(_, &right)| right as char)
+            .collect();
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
+        match bytes[25] {
+            square @ 0..=63 => {
+                fen.push(' ');
+                fen.push((b'a' + square % 8) as char);
+                fen.push((b'1' + square / 8) as char);
+            }
+            _ => fen.push_str(" -"),
+        }
+        let fullmove = u16::from_le_bytes([bytes[27], bytes[28]]);
+        fen.push_str(&format!(" {} {fullmove}", bytes[26]));
+
+        Self {
+            fen,
+            score_cp: i16::from_le_bytes([bytes[29], bytes[30]]).into(),
+            wdl: f32::from(u16::from_le_bytes([bytes[31], bytes[32]])) / WDL_SCALE,
+            best_move: BitMove::new(u16::from_le_bytes([bytes[33], bytes[34]])),
+        }
+    }
+}
+
+/// Buffered writer for a training data file in either format.
+pub struct TrainingWriter {
+    out: BufWriter<File>,
+    format: DataFormat,
+    written: usize,
+}
+
+impl TrainingWriter {
+    pub fn create(path: &str, format: DataFormat) -> Result<Self, String> {
+        let file = File::create(path).map_err(|err| format!("cannot create `{path}`: {err}"))?;
+        Ok(Self {
+            out: BufWriter::new(file),
+            format,
+            written: 0,
+        })
+    }
+
+    pub fn write(&mut self, record: &TrainingRecord) -> Result<(), String> {
+        let result = match self.format {
+            DataFormat::Text => writeln!(self.out, "{}", record.to_line()),
+            DataFormat::Binary => self.out.write_all(&record.to_bytes()?),
+        };
+        result.map_err(|err| format!("cannot write training data: {err}"))?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Records written so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn finish(mut self) -> Result<usize, String> {
+        self.out
+            .flush()
+            .map_err(|err| format!("cannot write training data: {err}"))?;
+        Ok(self.written)
+    }
+}
+
+/// Writes `encode_board_features` rows to a NumPy `.npy` file of `float32` with shape
+/// `(rows, 775)`, which `numpy.load` and `torch.from_numpy` read directly. The row count
+/// goes in the header, so it must be known up front.
+pub struct FeatureWriter {
+    out: BufWriter<File>,
+    rows: usize,
+    written: usize,
+    features: Box<[f32; FEATURE_COLUMNS]>,
+}
+
+impl FeatureWriter {
+    pub fn create(path: &str, rows: usize) -> Result<Self, String> {
+        let file = File::create(path).map_err(|err| format!("cannot create `{path}`: {err}"))?;
+        let mut out = BufWriter::new(file);
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({rows}, {FEATURE_COLUMNS}), }}"
+        );
+        // Magic (6 bytes), version (2) and header length (2) come before the header text,
+        // which ends in a newline.
+        let padding = NPY_ALIGNMENT - (10 + header.len() + 1) % NPY_ALIGNMENT;
+        header.extend(std::iter::repeat_n(' ', padding % NPY_ALIGNMENT));
+        header.push('\n');
+        let header_len = u16::try_from(header.len()).expect("npy headers are short");
+        out.write_all(b"\x93NUMPY\x01\x00")
+            .and_then(|_| out.write_all(&header_len.to_le_bytes()))
+            .and_then(|_| out.write_all(header.as_bytes()))
+            .map_err(|err| format!("cannot write features: {err}"))?;
+        Ok(Self {
+            out,
+            rows,
+            written: 0,
+            features: Box::new([0.0; FEATURE_COLUMNS]),
+        })
+    }
+
+    pub fn write(&mut self, board: &Board) -> Result<(), String> {
+        if self.written == self.rows {
+            return Err(format!("feature file holds only {} rows", self.rows));
+        }
+        encode_board_features(board, &mut self.features);
+        for value in self.features.iter() {
+            self.out
+                .write_all(&value.to_le_bytes())
+                .map_err(|err| format!("cannot write features: {err}"))?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<usize, String> {
+        if self.written != self.rows {
+            return Err(format!(
+                "wrote {} feature rows, expected {}",
+                self.written, self.rows
+            ));
+        }
+        self.out
+            .flush()
+            .map_err(|err| format!("cannot write features: {err}"))?;
+        Ok(self.written)
+    }
+}
+
+/// Reads back a file written by `TrainingWriter`.
+pub fn read_training_file(path: &str, format: DataFormat) -> Result<Vec<TrainingRecord>, String> {
+    let file = File::open(path).map_err(|err| format!("cannot read `{path}`: {err}"))?;
+    let mut contents = Vec::new();
+    BufReader::new(file)
+        .read_to_end(&mut contents)
+        .map_err(|err| format!("cannot read `{path}`: {err}"))?;
+    match format {
+        DataFormat::Text => String::from_utf8_lossy(&contents)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(TrainingRecord::from_line)
+            .collect(),
+        DataFormat::Binary => {
+            if contents.len() % RECORD_BYTES != 0 {
+                return Err(format!(
+                    "`{path}` is not a whole number of {RECORD_BYTES}-byte records"
+                ));
+            }
+            Ok(contents
+                .chunks_exact(RECORD_BYTES)
+                .map(|chunk| TrainingRecord::from_bytes(chunk.try_into().unwrap()))
+                .collect())
+        }
+    }
+}
@@ -1,8 +1,14 @@
+use crate::classical::piece_value;
 use pleco::{BitMove, Board, Piece, PieceType, Player};
+use std::cmp::Reverse;
 use tch::Tensor;
 
 pub type HistoryTable = [[[i32; 64]; 64]; 2];
 
+/// Policy logits become move scores with three decimals kept, staying below captures.
+const POLICY_SCORE_SCALE: f32 = 1_000.0;
+const MAX_POLICY_SCORE: f32 = 800_000.0;
+
 pub fn piece_to_offset(piece: Piece) -> usize {
     match piece {
         Piece::WhitePawn => 0,
@@ -51,24 +57,117 @@ pub fn encode_board_features(board: &Board, features: &mut [f32; 775]) {
         board.can_castle(Player::Black, pleco::core::CastleType::QueenSide) as i32 as f32;
 }
 
+/// Parses an EPD record (or a full FEN) into a board, ignoring any opcodes after the four
+/// position fields. Blank lines and `#` comments yield `None`.
+pub fn board_from_epd(line: &str) -> Option<Board> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 4 {
+        return None;
+    }
+    let counters = match (tokens.get(4), tokens.get(5)) {
+        (Some(halfmove), Some(fullmove))
+            if halfmove.parse::<u32>().is_ok() && fullmove.parse::<u32>().is_ok() =>
+        {
+            format!("{halfmove} {fullmove}")
+        }
+        _ => "0 1".to_string(),
+    };
+    Board::from_fen(&format!("{} {counters}", tokens[..4].join(" "))).ok()
+}
+
+/// Standard algebraic notation of the legal move `mv` on `board`, e.g. `Nbd7`, `exd6`,
+/// `e8=Q+` or `O-O#`.
+pub fn move_to_san(board: &Board, mv: BitMove) -> String {
+    let mut san = String::new();
+    if mv.is_castle() {
+        san.push_str(if mv.is_king_castle() { "O-O" } else { "O-O-O" });
+    } else {
+        let src = mv.get_src();
+        let dest = mv.get_dest();
+        let piece = board.piece_at_sq(src).type_of();
+        let capture = board.is_capture(mv);
+        if piece == PieceType::P {
+            if capture {
+                san.push_str(&src.to_string()[..1]);
+                san.push('x');
+            }
+            san.push_str(&dest.to_string());
+            if mv.is_promo() {
+                san.push('=');
+                san.push(mv.promo_piece().char_upper());
+            }
+        } else {
+            san.push(piece.char_upper());
+            let rivals: Vec<BitMove> = board
+                .generate_moves()
+                .iter()
+                .copied()
+                .filter(|other| {
+                    *other != mv
+                        && !other.is_castle()
+                        && other.get_dest() == dest
+                        && board.piece_at_sq(other.get_src()).type_of() == piece
+                })
+                .collect();
+            if !rivals.is_empty() {
+                let src_name = src.to_string();
+                let same_file = rivals
+                    .iter()
+                    .any(|other| other.get_src().file() == src.file());
+                let same_rank = rivals
+                    .iter()
+                    .any(|other| other.get_src().rank() == src.rank());
+                match (same_file, same_rank) {
+                    (false, _) => san.push_str(&src_name[..1]),
+                    (true, false) => san.push_str(&src_name[1..]),
+                    (true, true) => san.push_str(&src_name),
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&dest.to_string());
+        }
+    }
+
+    let mut after = board.shallow_clone();
+    after.apply_move(mv);
+    if after.checkmate() {
+        san.push('#');
+    } else if after.in_check() {
+        san.push('+');
+    }
+    san
+}
+
+/// Finds the legal move written as `text` in SAN (check marks, annotations and `0-0`
+/// spellings are tolerated) or in UCI coordinates.
+pub fn parse_move(board: &Board, text: &str) -> Option<BitMove> {
+    let wanted = normalize_san(text);
+    board
+        .generate_moves()
+        .iter()
+        .copied()
+        .find(|&mv| mv.to_string() == text || normalize_san(&move_to_san(board, mv)) == wanted)
+}
+
+fn normalize_san(text: &str) -> String {
+    text.replace('0', "O")
+        .chars()
+        .filter(|c| !matches!(c, '+' | '#' | '!' | '?' | '='))
+        .collect()
+}
+
 pub fn board_to_tensor(board: &Board) -> Tensor {
     let mut features = [0.0_f32; 775];
     encode_board_features(board, &mut features);
     Tensor::from_slice(&features).view([1, 775])
 }
 
-fn piece_value(piece_type: PieceType) -> i32 {
-    match piece_type {
-        PieceType::P => 100,
-        PieceType::N => 320,
-        PieceType::B => 330,
-        PieceType::R => 500,
-        PieceType::Q => 900,
-        PieceType::K => 20_000,
-        PieceType::None | PieceType::All => 0,
-    }
-}
-
 fn mvv_lva_score(board: &Board, mv: BitMove) -> i32 {
     let victim_value = piece_value(board.piece_at_sq(mv.get_dest()).type_of());
     let attacker_value = piece_value(board.piece_at_sq(mv.get_src()).type_of());
@@ -90,6 +189,7 @@ fn move_score(
     killers: [BitMove; 2],
     history: &HistoryTable,
     side_to_move: Player,
+    policy: Option<&[f32]>,
 ) -> i32 {
     if Some(mv) == tt_move {
         return 2_000_000;
@@ -99,6 +199,11 @@ fn move_score(
         return 1_000_000 + mvv_lva_score(board, mv);
     }
 
+    if let Some(policy) = policy {
+        let logit = policy.get(policy_index(mv)).copied().unwrap_or(f32::MIN);
+        return (logit * POLICY_SCORE_SCALE).clamp(-MAX_POLICY_SCORE, MAX_POLICY_SCORE) as i32;
+    }
+
     if mv == killers[0] {
         return 900_000;
     }
@@ -106,11 +211,25 @@ fn move_score(
         return 899_000;
     }
 
+    history[side_index(side_to_move)][mv.get_src_u8() as usize][mv.get_dest_u8() as usize]
+}
+
+/// Where `mv` sits in a `from * 64 + to` policy. pleco encodes castling as the king
+/// taking its own rook, but policy heads see the king's destination: e1g1, not e1h1.
+pub fn policy_index(mv: BitMove) -> usize {
     let from = mv.get_src_u8() as usize;
-    let to = mv.get_dest_u8() as usize;
-    history[side_index(side_to_move)][from][to]
+    let to = if mv.is_king_castle() {
+        from + 2
+    } else if mv.is_queen_castle() {
+        from - 2
+    } else {
+        mv.get_dest_u8() as usize
+    };
+    from * 64 + to
 }
 
+/// Orders `moves` best first: the TT move, captures by MVV-LVA, then quiet moves by the
+/// policy logits (see `policy_index`) when given, otherwise by killers and history.
 pub fn order_moves(
     board: &Board,
     moves: &mut [BitMove],
@@ -118,16 +237,17 @@ pub fn order_moves(
     killers: [BitMove; 2],
     history: &HistoryTable,
     side_to_move: Player,
+    policy: Option<&[f32]>,
 ) {
     let mut scored = Vec::with_capacity(moves.len());
     for &mv in moves.iter() {
         scored.push((
-            move_score(board, mv, tt_move, killers, history, side_to_move),
+            move_score(board, mv, tt_move, killers, history, side_to_move, policy),
             mv,
         ));
     }
 
-    scored.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    scored.sort_unstable_by_key(|&(score, _)| Reverse(score));
     for (idx, (_, mv)) in scored.into_iter().enumerate() {
         moves[idx] = mv;
     }
@@ -139,7 +259,7 @@ pub fn order_captures(board: &Board, moves: &mut [BitMove]) {
         scored.push((mvv_lva_score(board, mv), mv));
     }
 
-    scored.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    scored.sort_unstable_by_key(|&(score, _)| Reverse(score));
     for (idx, (_, mv)) in scored.into_iter().enumerate() {
         moves[idx] = mv;
     }
@@ -0,0 +1,100 @@
+mod common;
+
+use brainstorm::bench::{run_bench, BenchConfig, BENCH_FENS, DEFAULT_BENCH_DEPTH};
+use brainstorm::search_algorithm::{ModelMode, SearchAlgorithm, SearchOptions};
+use common::searcher;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// The positions shared with `benches/speed_benchmarks.rs`, enough to exercise the networks.
+const NETWORK_FENS: &[&str] = &[BENCH_FENS[0], BENCH_FENS[1], BENCH_FENS[2], BENCH_FENS[3]];
+
+fn config(depth: u32) -> BenchConfig {
+    BenchConfig {
+        depth,
+        ..BenchConfig::default()
+    }
+}
+
+fn classical_options() -> SearchOptions {
+    SearchOptions {
+        model_mode: ModelMode::Classical,
+        ..SearchOptions::default()
+    }
+}
+
+#[test]
+fn bench_arguments_are_positional() {
+    assert_eq!(BenchConfig::parse(&[]).unwrap().depth, DEFAULT_BENCH_DEPTH);
+    let parsed = BenchConfig::parse(&["4", "2", "32"]).unwrap();
+    assert_eq!(
+        parsed,
+        BenchConfig {
+            depth: 4,
+            threads: 2,
+            hash_mb: 32
+        }
+    );
+    assert!(BenchConfig::parse(&["0"]).is_err());
+    assert!(BenchConfig::parse(&["deep"]).is_err());
+    assert!(BenchConfig::parse(&["4", "1", "16", "extra"]).is_err());
+}
+
+#[test]
+fn bench_signature_is_reproducible() {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let options = classical_options();
+    let mut visited = 0;
+    let first = run_bench(
+        &searcher,
+        &options,
+        config(4),
+        &BENCH_FENS,
+        |index, fen, result| {
+            assert_eq!(fen, BENCH_FENS[index]);
+            assert!(!result.best_move.is_null());
+            visited += 1;
+        },
+    );
+    assert_eq!(visited, BENCH_FENS.len());
+    assert_eq!(
+        first.nodes,
+        first.results.iter().map(|result| result.nodes).sum::<u64>()
+    );
+
+    // Leftover table entries from the first run must not leak into the second.
+    let second = run_bench(&searcher, &options, config(4), &BENCH_FENS, |_, _, _| {});
+    assert_eq!(second.nodes, first.nodes);
+    assert_eq!(second.signature, first.signature);
+
+    let shallower = run_bench(&searcher, &options, config(3), &BENCH_FENS, |_, _, _| {});
+    assert_ne!(shallower.signature, first.signature);
+}
+
+#[test]
+fn bench_signature_tracks_the_evaluation() {
+    let networks = searcher();
+    let classical = run_bench(
+        &networks,
+        &classical_options(),
+        config(3),
+        NETWORK_FENS,
+        |_, _, _| {},
+    );
+    let fast = run_bench(
+        &networks,
+        &SearchOptions::default(),
+        config(3),
+        NETWORK_FENS,
+        |_, _, _| {},
+    );
+    assert_ne!(fast.signature, classical.signature);
+    let again = run_bench(
+        &networks,
+        &SearchOptions::default(),
+        config(3),
+        NETWORK_FENS,
+        |_, _, _| {},
+    );
+    assert_eq!(again.signature, fast.signature);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use brainstorm::classical::{self, ClassicalEval};
+use brainstorm::features::color_flipped;
+use brainstorm::search_algorithm::{
+    score_to_uci, ModelMode, SearchAlgorithm, SearchOptions, SearchRequest,
+};
+use common::{sample_positions, searcher};
+use pleco::Board;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+const QUEEN_UP_FEN: &str = "rnb1kbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3";
+
+fn request(max_depth: u32) -> SearchRequest {
+    SearchRequest {
+        max_depth,
+        ..SearchRequest::default()
+    }
+}
+
+#[test]
+fn classical_eval_is_color_symmetric() {
+    assert_eq!(ClassicalEval::of(&Board::start_pos()).total(), 0);
+    for board in sample_positions(0xc1a5, 200) {
+        let flipped = color_flipped(&board).unwrap();
+        assert_eq!(
+            classical::evaluate(&board),
+            classical::evaluate(&flipped),
+            "{}",
+            board.fen()
+        );
+    }
+}
+
+#[test]
+fn classical_eval_counts_material() {
+    let board = Board::from_fen(QUEEN_UP_FEN).unwrap();
+    let eval = ClassicalEval::of(&board);
+    assert_eq!(eval.material, 900);
+    assert!(classical::evaluate(&board) > 700, "{eval}");
+}
+
+#[test]
+fn classical_searcher_plays_without_networks() {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let options = SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        ..SearchOptions::default()
+    };
+    let board = Board::from_fen("k7/8/1QK5/8/8/8/8/8 w - - 0 1").unwrap();
+    let result = searcher.search_quiet(&board, request(4), &options, &[board.zobrist()]);
+    assert_eq!(result.best_move.to_string(), "b6b7");
+    assert_eq!(score_to_uci(result.score_cp), ("mate", 1));
+    assert_eq!(result.stats.fast_model_evals, 0);
+    assert_eq!(result.stats.accurate_model_evals, 0);
+}
+
+#[test]
+fn classical_mode_skips_loaded_networks() {
+    let searcher = searcher();
+    let options = SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        model_mode: ModelMode::Classical,
+        ..SearchOptions::default()
+    };
+    let board = Board::start_pos();
+    let result = searcher.search_quiet(&board, request(3), &options, &[board.zobrist()]);
+    assert!(!result.best_move.is_null());
+    assert!(result.stats.eval_calls > 0);
+    assert_eq!(result.stats.fast_model_evals, 0);
+    assert_eq!(result.stats.accurate_model_evals, 0);
+}
+
+#[test]
+fn lazy_eval_cuts_lopsided_quiescence_nodes() {
+    let searcher = searcher();
+    let board = Board::from_fen(QUEEN_UP_FEN).unwrap();
+    let mut options = SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        ..SearchOptions::default()
+    };
+    let baseline = searcher.search_quiet(&board, request(3), &options, &[board.zobrist()]);
+    assert_eq!(baseline.stats.lazy_eval_cutoffs, 0);
+
+    options.lazy_eval = true;
+    let lazy = searcher.search_quiet(&board, request(3), &options, &[board.zobrist()]);
+    assert!(lazy.stats.lazy_eval_cutoffs > 0);
+    assert!(!lazy.best_move.is_null());
+}
@@ -0,0 +1,137 @@
+use brainstorm::bench::BenchConfig;
+use brainstorm::cli::{Cli, Command};
+use brainstorm::selfplay::{game_over, GameResult, SelfPlayConfig, Termination};
+use pleco::Board;
+
+const MATED_FEN: &str = "k7/1Q6/2K5/8/8/8/8/8 b - - 0 1";
+
+fn parse(args: &[&str]) -> Result<Cli, String> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Cli::parse(&args)
+}
+
+#[test]
+fn uci_is_the_default_command() {
+    let cli = parse(&[]).unwrap();
+    assert_eq!(cli.command, Command::Uci);
+    assert!(cli.options.is_empty());
+
+    let cli = parse(&["-o", "Threads=1", "--option", "Model = accurate", "uci"]).unwrap();
+    assert_eq!(cli.command, Command::Uci);
+    assert_eq!(
+        cli.options,
+        [
+            ("Threads".to_string(), "1".to_string()),
+            ("Model".to_string(), "accurate".to_string())
+        ]
+    );
+    assert!(parse(&["-o", "Threads"]).is_err());
+    assert!(parse(&["uci", "now"]).is_err());
+    assert!(parse(&["play"]).is_err());
+    assert_eq!(parse(&["--help"]).unwrap().command, Command::Help);
+    assert_eq!(parse(&["bench", "--help"]).unwrap().command, Command::Help);
+}
+
+#[test]
+fn bad_options_print_the_usage() {
+    for option in ["Modle=accurate", "Threads=x"] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_brainstorm"))
+            .args(["-o", option, "perft", "1"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{option}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("error: "), "{stderr}");
+        assert!(stderr.contains("usage: brainstorm"));
+        assert!(output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn positions_may_be_quoted_or_split() {
+    let quoted = parse(&["eval", "8/8/8/8/8/1QK5/8/k7 w - -"]).unwrap();
+    let split = parse(&["eval", "8/8/8/8/8/1QK5/8/k7", "w", "-", "-"]).unwrap();
+    assert_eq!(quoted, split);
+    assert_eq!(
+        quoted.command,
+        Command::Eval {
+            fen: "8/8/8/8/8/1QK5/8/k7 w - - 0 1".to_string()
+        }
+    );
+    assert!(parse(&["eval", "not a fen"]).is_err());
+    assert!(parse(&["eval"]).is_err());
+}
+
+#[test]
+fn analyse_takes_an_epd_record_and_limits() {
+    let cli = parse(&[
+        "analyse",
+        "8/8/8/8/8/1QK5/8/k7 w - - bm Qb2#; id \"mate\";",
+        "--movetime",
+        "250",
+    ])
+    .unwrap();
+    let Command::Analyse {
+        fen,
+        depth,
+        movetime_ms,
+    } = cli.command
+    else {
+        panic!("expected analyse, got {:?}", cli.command);
+    };
+    assert_eq!(fen, "8/8/8/8/8/1QK5/8/k7 w - - 0 1");
+    assert_eq!(Command::go_command(depth, movetime_ms), "go movetime 250");
+    assert_eq!(Command::go_command(Some(6), None), "go depth 6");
+    assert!(parse(&["analyse", "8/8/8/8/8/1QK5/8/k7 w - -", "--nodes", "5"]).is_err());
+}
+
+#[test]
+fn bench_perft_and_selfplay_arguments() {
+    assert_eq!(
+        parse(&["bench", "5"]).unwrap().command,
+        Command::Bench(BenchConfig {
+            depth: 5,
+            ..BenchConfig::default()
+        })
+    );
+    assert_eq!(
+        parse(&["perft", "4"]).unwrap().command,
+        Command::Perft {
+            depth: 4,
+            fen: None
+        }
+    );
+    assert!(parse(&["perft"]).is_err());
+
+    let cli = parse(&["selfplay", "--games", "3", "--movetime", "100"]).unwrap();
+    assert_eq!(
+        cli.command,
+        Command::SelfPlay(SelfPlayConfig {
+            games: 3,
+            movetime_ms: Some(100),
+            ..SelfPlayConfig::default()
+        })
+    );
+    assert!(parse(&["selfplay", "--games"]).is_err());
+}
+
+#[test]
+fn selfplay_games_end_by_the_rules() {
+    let mated = Board::from_fen(MATED_FEN).unwrap();
+    assert_eq!(
+        game_over(&mated, &[mated.zobrist()]),
+        Some((GameResult::WhiteWins, Termination::Checkmate))
+    );
+
+    let board = Board::start_pos();
+    assert_eq!(game_over(&board, &[board.zobrist(); 2]), None);
+    assert_eq!(
+        game_over(&board, &[board.zobrist(); 3]),
+        Some((GameResult::Draw, Termination::Repetition))
+    );
+    let bare_kings = Board::from_fen("8/8/4k3/8/8/3K4/8/8 w - - 0 1").unwrap();
+    assert_eq!(
+        game_over(&bare_kings, &[bare_kings.zobrist()]),
+        Some((GameResult::Draw, Termination::InsufficientMaterial))
+    );
+}
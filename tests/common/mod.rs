@@ -0,0 +1,98 @@
+// Each test crate uses a different subset of these helpers.
+#![allow(dead_code)]
+
+use brainstorm::evaluator::sidecar_path;
+use brainstorm::evaluator::Evaluator;
+use brainstorm::search_algorithm::SearchAlgorithm;
+use pleco::Board;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tch::Device;
+
+pub const FAST_MODEL_PATH: &str = "models/brainstorm_fast_eval_jit.pt";
+const ACCURATE_MODEL_PATH: &str = "models/brainstorm_accurate_eval_jit.pt";
+
+pub fn searcher() -> SearchAlgorithm {
+    let small = Evaluator::load(Path::new(FAST_MODEL_PATH), Device::Cpu)
+        .unwrap_or_else(|err| panic!("failed to load `{FAST_MODEL_PATH}`: {err}"));
+    let large = Evaluator::load(Path::new(ACCURATE_MODEL_PATH), Device::Cpu)
+        .unwrap_or_else(|err| panic!("failed to load `{ACCURATE_MODEL_PATH}`: {err}"));
+    SearchAlgorithm::new(
+        Arc::new(small),
+        Arc::new(large),
+        Device::Cpu,
+        Arc::new(AtomicBool::new(false)),
+    )
+}
+
+static SCRATCH_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh directory under the system temp dir, deleted with its contents on drop.
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn new() -> Self {
+        let index = SCRATCH_DIRS.fetch_add(1, Ordering::Relaxed);
+        let dir =
+            std::env::temp_dir().join(format!("brainstorm-tests-{}-{index}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A model file that lives as long as its scratch directory.
+pub struct ScratchModel {
+    path: PathBuf,
+    _dir: ScratchDir,
+}
+
+impl Deref for ScratchModel {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Copies the fast model into a scratch directory with the given metadata sidecar.
+pub fn model_with_sidecar(name: &str, sidecar: &str) -> ScratchModel {
+    let dir = ScratchDir::new();
+    let path = dir.join(&format!("{name}.pt"));
+    fs::copy(FAST_MODEL_PATH, &path).unwrap();
+    fs::write(sidecar_path(&path), sidecar).unwrap();
+    ScratchModel { path, _dir: dir }
+}
+
+/// Positions from pseudo-random playouts, deterministic for a given seed.
+pub fn sample_positions(seed: u64, count: usize) -> Vec<Board> {
+    let mut state = seed;
+    let mut positions = Vec::with_capacity(count);
+    while positions.len() < count {
+        let mut board = Board::start_pos();
+        for _ in 0..60 {
+            let moves = board.generate_moves();
+            if moves.is_empty() || positions.len() == count {
+                break;
+            }
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            board.apply_move(moves[(state >> 33) as usize % moves.len()]);
+            positions.push(board.shallow_clone());
+        }
+    }
+    positions
+}
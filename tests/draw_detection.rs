@@ -0,0 +1,99 @@
+use brainstorm::search_algorithm::{
+    insufficient_material, score_to_uci, SearchAlgorithm, SearchOptions, SearchRequest,
+};
+use pleco::Board;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// White, a queen and rook down, can check forever: Qe8+ Kh7 Qh5+ Kg8.
+const PERPETUAL_FEN: &str = "6k1/6p1/8/7Q/8/8/qr6/7K w - - 0 1";
+
+fn search(board: &Board, max_depth: u32, game_history: &[u64]) -> (String, i32) {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let options = SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        ..SearchOptions::default()
+    };
+    let request = SearchRequest {
+        max_depth,
+        ..SearchRequest::default()
+    };
+    let result = searcher.search_quiet(board, request, &options, game_history);
+    (result.best_move.to_string(), result.score_cp)
+}
+
+fn after(board: &Board, uci: &str) -> Board {
+    let mut board = board.shallow_clone();
+    assert!(board.apply_uci_move(uci));
+    board
+}
+
+#[test]
+fn bare_minor_pieces_cannot_mate() {
+    let drawn = [
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+        // Both bishops on dark squares.
+        "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+    ];
+    for fen in drawn {
+        let board = Board::from_fen(fen).unwrap();
+        assert!(insufficient_material(&board), "{fen}");
+        assert_eq!(search(&board, 4, &[board.zobrist()]).1, 0, "{fen}");
+    }
+
+    let playable = [
+        // Opposite-coloured bishops can still mate with help.
+        "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+    ];
+    for fen in playable {
+        assert!(
+            !insufficient_material(&Board::from_fen(fen).unwrap()),
+            "{fen}"
+        );
+    }
+}
+
+#[test]
+fn one_repetition_inside_the_tree_is_a_draw() {
+    let board = Board::from_fen(PERPETUAL_FEN).unwrap();
+    // Without the perpetual, White is lost.
+    let (_, score) = search(&board, 3, &[board.zobrist()]);
+    assert!(score < -500, "{score}");
+    // Qe8+ reached a second time within the tree, five plies in, is already a draw.
+    assert_eq!(
+        search(&board, 6, &[board.zobrist()]),
+        ("h5e8".to_string(), 0)
+    );
+}
+
+#[test]
+fn game_history_repetitions_need_three_fold() {
+    let board = Board::from_fen(PERPETUAL_FEN).unwrap();
+    let check = after(&board, "h5e8").zobrist();
+
+    // The position after Qe8+ was seen once before: playing it again is only a second
+    // occurrence, so the game goes on.
+    let (_, score) = search(&board, 3, &[check, board.zobrist()]);
+    assert!(score < -500, "{score}");
+
+    // Seen twice, the third occurrence ends the game.
+    let history = [check, check, board.zobrist()];
+    assert_eq!(search(&board, 3, &history), ("h5e8".to_string(), 0));
+}
+
+#[test]
+fn mate_on_the_fiftieth_move_still_counts() {
+    let board = Board::from_fen("k7/8/1QK5/8/8/8/8/8 w - - 99 80").unwrap();
+    let (best_move, score) = search(&board, 4, &[board.zobrist()]);
+    assert_eq!(best_move, "b6b7");
+    assert_eq!(score_to_uci(score), ("mate", 1));
+
+    // Without a mate in one, every move reaches the limit.
+    let board = Board::from_fen("k7/8/2K5/8/8/8/7Q/8 w - - 99 80").unwrap();
+    assert_eq!(search(&board, 4, &[board.zobrist()]).1, 0);
+}
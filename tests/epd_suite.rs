@@ -0,0 +1,188 @@
+mod common;
+
+use brainstorm::cli::{Cli, Command};
+use brainstorm::epd::{parse_epd_suite, run_suite, EpdRecord, ReportFormat, SuiteLimits};
+use brainstorm::search_algorithm::{SearchAlgorithm, SearchOptions};
+use brainstorm::utils::{move_to_san, parse_move};
+use common::searcher;
+use pleco::Board;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+const MATE_SUITE: &str = include_str!("../scripts/mate_suite.epd");
+
+fn san(fen: &str, uci: &str) -> String {
+    let board = Board::from_fen(fen).unwrap();
+    let mv = parse_move(&board, uci).unwrap();
+    assert_eq!(parse_move(&board, &move_to_san(&board, mv)), Some(mv));
+    move_to_san(&board, mv)
+}
+
+fn options() -> SearchOptions {
+    SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        ..SearchOptions::default()
+    }
+}
+
+#[test]
+fn moves_print_in_standard_algebraic_notation() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(san(start, "e2e4"), "e4");
+    assert_eq!(san(start, "g1f3"), "Nf3");
+    assert_eq!(san("k7/8/1QK5/8/8/8/8/8 w - - 0 1", "b6b7"), "Qb7#");
+    assert_eq!(san("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1"), "O-O");
+    assert_eq!(san("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1c1"), "O-O-O");
+    // Knights on b1 and f1 both reach d2; rooks on a1 and a5 both reach a3.
+    assert_eq!(san("4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1", "b1d2"), "Nbd2");
+    assert_eq!(san("4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1", "a5a3"), "R5a3");
+    assert_eq!(san("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), "exd5");
+    assert_eq!(san("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), "b8=Q+");
+
+    let board = Board::from_fen(start).unwrap();
+    assert_eq!(parse_move(&board, "0-0"), None);
+    assert_eq!(parse_move(&board, "e5"), None);
+    assert_eq!(parse_move(&board, "Ng1f3"), None);
+}
+
+#[test]
+fn epd_lines_carry_best_and_avoid_moves() {
+    let line =
+        r#"4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1 bm exd5 Kd2; am e5; id "pawn grab"; c0 "note";"#;
+    let record = EpdRecord::parse(line, 7).unwrap().unwrap();
+    assert_eq!(record.id, "pawn grab");
+    let moves = |moves: &[pleco::BitMove]| -> Vec<String> {
+        moves.iter().map(|mv| mv.to_string()).collect()
+    };
+    assert_eq!(moves(&record.best_moves), ["e4d5", "e1d2"]);
+    assert_eq!(moves(&record.avoid_moves), ["e4e5"]);
+    assert!(record.is_solved_by(record.best_moves[1]));
+    assert!(!record.is_solved_by(record.avoid_moves[0]));
+
+    let record = EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 w - - am Ke2", 3)
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.id, "line3");
+    assert!(record.best_moves.is_empty());
+
+    // Semicolons inside quoted operands do not end the operation.
+    let record = EpdRecord::parse(r#"4k3/8/8/8/8/8/8/4K3 w - - id "a;b"; bm Kd2;"#, 1)
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.id, "a;b");
+    assert_eq!(record.best_moves[0].to_string(), "e1d2");
+
+    assert!(EpdRecord::parse("# comment", 1).unwrap().is_none());
+    assert!(EpdRecord::parse("   ", 1).unwrap().is_none());
+    assert!(EpdRecord::parse("not a position", 1).is_err());
+    assert!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;", 1).is_err());
+    // Nothing to score the engine's move against.
+    assert!(EpdRecord::parse(r#"4k3/8/8/8/8/8/8/4K3 w - - id "bare";"#, 1).is_err());
+    assert_eq!(parse_epd_suite(MATE_SUITE).unwrap().len(), 4);
+}
+
+#[test]
+fn classical_search_solves_the_mate_suite() {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let records = parse_epd_suite(MATE_SUITE).unwrap();
+    let limits = SuiteLimits {
+        depth: Some(3),
+        ..SuiteLimits::default()
+    };
+    let mut seen = Vec::new();
+    let report = run_suite(&searcher, &options(), &records, limits, |outcome| {
+        seen.push(outcome.id.clone())
+    });
+    assert_eq!(seen, ["M1-W-1", "M1-W-2", "M1-B-1", "M1-B-4"]);
+    assert_eq!(report.solved(), 4);
+    assert_eq!(report.outcomes[0].played, "Qb7#");
+    assert!(report.to_text().starts_with("solved 4/4 (100.0%) in "));
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["summary"]["solved"], 4);
+    assert_eq!(json["summary"]["total"], 4);
+    assert_eq!(json["positions"][2]["id"], "M1-B-1");
+    assert_eq!(json["positions"][2]["best_moves"][0], "Qb2#");
+}
+
+#[test]
+fn failures_are_listed_with_the_move_played() {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let records =
+        parse_epd_suite("k7/8/1QK5/8/8/8/8/8 w - - am Qb7#; id \"refuse mate\";").unwrap();
+    let limits = SuiteLimits {
+        depth: Some(2),
+        ..SuiteLimits::default()
+    };
+    let report = run_suite(&searcher, &options(), &records, limits, |_| {});
+    assert_eq!(report.solved(), 0);
+    let text = report.to_text();
+    assert!(text.starts_with("failed refuse mate played Qb7# am Qb7# fen k7/8/1QK5/"));
+    assert!(text.contains("solved 0/1 (0.0%)"));
+}
+
+#[test]
+fn node_limits_are_exact_and_repeatable() {
+    let searcher = searcher();
+    let records = parse_epd_suite(
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4 am a3; id \"italian\";",
+    )
+    .unwrap();
+    let limits = SuiteLimits {
+        nodes: Some(3000),
+        ..SuiteLimits::default()
+    };
+    let options = SearchOptions {
+        threads: 4,
+        ..options()
+    };
+    let first = run_suite(&searcher, &options, &records, limits, |_| {});
+    let second = run_suite(&searcher, &options, &records, limits, |_| {});
+    assert!(first.outcomes[0].nodes <= 3000);
+    assert_eq!(first.outcomes[0].nodes, second.outcomes[0].nodes);
+    assert_eq!(first.outcomes[0].played, second.outcomes[0].played);
+}
+
+#[test]
+fn node_limits_leave_the_stop_flag_alone() {
+    let stop = Arc::new(AtomicBool::new(false));
+    let searcher = SearchAlgorithm::classical(Arc::clone(&stop));
+    let records = parse_epd_suite("k7/8/1QK5/8/8/8/8/8 w - - bm Qb7#;").unwrap();
+    let limits = SuiteLimits {
+        nodes: Some(50),
+        ..SuiteLimits::default()
+    };
+    let report = run_suite(&searcher, &options(), &records, limits, |_| {});
+    assert!(report.outcomes[0].nodes <= 50);
+    // Other searches sharing the flag must not be stopped by this one's node limit.
+    assert!(!stop.load(std::sync::atomic::Ordering::Relaxed));
+}
+
+#[test]
+fn epd_command_parses_limits_and_format() {
+    let args: Vec<String> = ["epd", "suite.epd", "--nodes", "5000", "--format", "json"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    assert_eq!(
+        Cli::parse(&args).unwrap().command,
+        Command::Epd {
+            path: "suite.epd".to_string(),
+            limits: SuiteLimits {
+                nodes: Some(5000),
+                ..SuiteLimits::default()
+            },
+            format: ReportFormat::Json,
+        }
+    );
+    for bad in [
+        &["epd"][..],
+        &["epd", "a.epd", "b.epd"],
+        &["epd", "a.epd", "--format", "xml"],
+        &["epd", "a.epd", "--games", "2"],
+    ] {
+        let args: Vec<String> = bad.iter().map(|arg| arg.to_string()).collect();
+        assert!(Cli::parse(&args).is_err(), "{bad:?}");
+    }
+}
@@ -0,0 +1,102 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+const KIWIPETE_FEN: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+/// Runs the engine on `commands` and returns what it printed up to the final `readyok`.
+fn run_uci(commands: &[&str]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_brainstorm"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    for command in commands {
+        writeln!(stdin, "{command}").unwrap();
+    }
+    writeln!(stdin, "isready").unwrap();
+
+    let mut output = String::new();
+    let mut line = String::new();
+    while line.trim() != "readyok" {
+        line.clear();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "{output}");
+        output.push_str(&line);
+    }
+    writeln!(stdin, "quit").unwrap();
+    assert!(child.wait().unwrap().success());
+    output
+}
+
+/// The value of `key=` on the first line starting with `prefix`.
+fn field(output: &str, prefix: &str, key: &str) -> String {
+    let line = output
+        .lines()
+        .find(|line| line.starts_with(prefix))
+        .unwrap_or_else(|| panic!("no `{prefix}` line in\n{output}"));
+    line.split_whitespace()
+        .find_map(|token| token.strip_prefix(key)?.strip_prefix('='))
+        .unwrap_or_else(|| panic!("no `{key}` in `{line}`"))
+        .to_string()
+}
+
+#[test]
+fn eval_reports_both_networks_and_the_cache() {
+    let output = run_uci(&[&format!("position fen {KIWIPETE_FEN}"), "eval"]);
+    assert!(output.contains(&format!("info string eval fen {KIWIPETE_FEN}")));
+    for model in ["fast", "accurate"] {
+        let prefix = format!("info string eval {model} raw=");
+        let raw: f32 = field(&output, &prefix, "raw").parse().unwrap();
+        assert!(raw.is_finite());
+        let scaled: i32 = field(&output, &prefix, "scaled_cp").parse().unwrap();
+        let side_to_move: i32 = field(&output, &prefix, "side_to_move_cp").parse().unwrap();
+        // White is to move, so both scores share a point of view.
+        assert_eq!(scaled, side_to_move, "{output}");
+        assert!(output.contains(&format!("info string eval {model} features set=")));
+    }
+    let prefix = "info string eval cache ";
+    assert!(field(&output, prefix, "entries").parse::<usize>().unwrap() > 0);
+    // Nothing has been searched, so neither score is cached yet.
+    assert_eq!(field(&output, prefix, "fast"), "none");
+    assert_eq!(field(&output, prefix, "accurate"), "none");
+}
+
+#[test]
+fn evalcheck_summarises_the_color_flip_discrepancies() {
+    let dir = std::env::temp_dir().join(format!("brainstorm-evalcheck-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("positions.epd");
+    std::fs::write(
+        &path,
+        format!(
+            "{KIWIPETE_FEN}\n\
+             rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 id \"e4\";\n\
+             8/2k5/3p4/p2P1p2/P2P1P2/8/6K1/8 w - - 0 50\n"
+        ),
+    )
+    .unwrap();
+    let output = run_uci(&[&format!("evalcheck {}", path.display())]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    for model in ["fast", "accurate"] {
+        let prefix = format!("info string evalcheck {model} positions=");
+        assert_eq!(field(&output, &prefix, "positions"), "3", "{output}");
+        let max: i32 = field(&output, &prefix, "max_diff_cp").parse().unwrap();
+        let mean: f64 = field(&output, &prefix, "mean_diff_cp").parse().unwrap();
+        let asymmetric: usize = field(&output, &prefix, "asymmetric").parse().unwrap();
+        assert!(mean >= 0.0 && mean <= f64::from(max), "{output}");
+        assert!(asymmetric <= 3);
+        assert_eq!(max == 0, asymmetric == 0);
+        // Each asymmetric position is listed with its scores, largest first.
+        let listed = output
+            .lines()
+            .filter(|line| line.starts_with(&format!("info string evalcheck {model} diff_cp=")))
+            .count();
+        assert_eq!(listed, asymmetric);
+    }
+
+    let output = run_uci(&["evalcheck /nonexistent/positions.epd"]);
+    assert!(output.contains("info string evalcheck failed to read `/nonexistent/positions.epd`"));
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use brainstorm::evaluator::{Evaluator, ModelMetadata};
+use brainstorm::features::{
+    attacked_squares, color_flipped, FeatureSet, MAX_INPUT_SIZE, V1_INPUT_SIZE,
+};
+use brainstorm::utils::encode_board_features;
+use pleco::{Board, Player};
+use tch::Device;
+
+const EN_PASSANT_FEN: &str = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+
+#[test]
+fn v1_matches_legacy_encoder() {
+    let board = Board::from_fen(EN_PASSANT_FEN).unwrap();
+    let mut legacy = [0_f32; V1_INPUT_SIZE];
+    encode_board_features(&board, &mut legacy);
+
+    let mut buffer = [0_f32; MAX_INPUT_SIZE];
+    let features = FeatureSet::V1.encode(&board, &mut buffer);
+    assert_eq!(features, &legacy[..]);
+}
+
+#[test]
+fn v2_extends_v1_with_attacks_en_passant_and_rule_50() {
+    let board = Board::from_fen(EN_PASSANT_FEN).unwrap();
+    let mut buffer = [0_f32; MAX_INPUT_SIZE];
+    let v1 = FeatureSet::V1.encode(&board, &mut buffer).to_vec();
+    let v2 = FeatureSet::V2.encode(&board, &mut buffer).to_vec();
+
+    assert_eq!(v2.len(), FeatureSet::V2.input_size());
+    assert_eq!(&v2[..V1_INPUT_SIZE], &v1[..]);
+
+    let white_attacks = &v2[V1_INPUT_SIZE..V1_INPUT_SIZE + 64];
+    let black_attacks = &v2[V1_INPUT_SIZE + 64..V1_INPUT_SIZE + 128];
+    let count = |squares: &[f32]| squares.iter().filter(|&&value| value == 1.0).count();
+    assert_eq!(
+        count(white_attacks),
+        attacked_squares(&board, Player::White).count_bits() as usize
+    );
+    assert_eq!(
+        count(black_attacks),
+        attacked_squares(&board, Player::Black).count_bits() as usize
+    );
+    // The e5 pawn attacks d6 and f6.
+    assert_eq!(white_attacks[5 * 8 + 3], 1.0);
+    assert_eq!(white_attacks[5 * 8 + 5], 1.0);
+
+    let en_passant = &v2[V1_INPUT_SIZE + 128..V1_INPUT_SIZE + 136];
+    assert_eq!(en_passant, &[0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    assert_eq!(v2[V1_INPUT_SIZE + 136], 0.0);
+
+    let quiet = Board::from_fen("8/8/4k3/8/8/4K3/8/8 w - - 50 80").unwrap();
+    let v2 = FeatureSet::V2.encode(&quiet, &mut buffer);
+    assert_eq!(v2[V1_INPUT_SIZE + 136], 0.5);
+    assert!(v2[V1_INPUT_SIZE + 128..V1_INPUT_SIZE + 136]
+        .iter()
+        .all(|&value| value == 0.0));
+}
+
+#[test]
+fn metadata_selects_feature_set() {
+    let v2 = ModelMetadata::from_json(
+        r#"{"feature_set": 2, "input_size": 912, "output_scale_cp": 2500.0}"#,
+    )
+    .unwrap();
+    assert_eq!(v2.validate(), Ok(FeatureSet::V2));
+    assert_eq!(FeatureSet::V2.input_size(), 912);
+
+    let v1_sized = ModelMetadata {
+        feature_set: 2,
+        ..ModelMetadata::legacy()
+    };
+    assert!(v1_sized.validate().is_err());
+}
+
+const SYMMETRY_FENS: [&str; 4] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r2q1rk1/pp1b1ppp/2n1pn2/2bp4/2P5/2NP1NP1/PP2PPBP/R1BQ1RK1 b - - 0 8",
+    EN_PASSANT_FEN,
+    "r3k2r/8/8/8/8/8/8/R3K1R1 b Qkq - 3 30",
+];
+
+#[test]
+fn mirrored_encoding_is_color_symmetric() {
+    let mut buffer = [0_f32; MAX_INPUT_SIZE];
+    let mut flipped_buffer = [0_f32; MAX_INPUT_SIZE];
+
+    for fen in SYMMETRY_FENS {
+        let board = Board::from_fen(fen).unwrap();
+        let flipped = color_flipped(&board).unwrap();
+        assert_eq!(color_flipped(&flipped).unwrap().fen(), board.fen());
+
+        let features = FeatureSet::V1Mirrored.encode(&board, &mut buffer);
+        let flipped_features = FeatureSet::V1Mirrored.encode(&flipped, &mut flipped_buffer);
+        assert_eq!(features, flipped_features, "{fen}");
+    }
+}
+
+#[test]
+fn mirrored_positions_get_identical_scores() {
+    let model = common::model_with_sidecar(
+        "mirrored",
+        r#"{"feature_set": 3, "input_size": 775, "output_scale_cp": 2500.0}"#,
+    );
+    let evaluator = Evaluator::load(&model, Device::Cpu).unwrap();
+    assert!(evaluator.feature_set().is_side_relative());
+
+    let mut buffer = [0_f32; MAX_INPUT_SIZE];
+    for fen in SYMMETRY_FENS {
+        let board = Board::from_fen(fen).unwrap();
+        let flipped = color_flipped(&board).unwrap();
+        assert_eq!(
+            evaluator.evaluate(&board, &mut buffer, Device::Cpu),
+            evaluator.evaluate(&flipped, &mut buffer, Device::Cpu),
+            "{fen}"
+        );
+    }
+}
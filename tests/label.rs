@@ -0,0 +1,294 @@
+mod common;
+
+use brainstorm::cli::{Cli, Command};
+use brainstorm::label::{
+    label_positions, pgn_positions, read_positions, wdl_from_score, LabelConfig, LabelPosition,
+};
+use brainstorm::search_algorithm::{SearchAlgorithm, SearchOptions};
+use brainstorm::training::{DataFormat, FeatureWriter, TrainingRecord, FEATURE_COLUMNS};
+use brainstorm::utils::encode_board_features;
+use common::sample_positions;
+use pleco::Board;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+const PGN: &str = r#"[Event "Casual"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {developing; a comment} Nc6 (2... d6 3. d4 (3. Bc4) exd4) 3. Bc4 $1
+Nf6?! 4. Ng5 d5 5. exd5 Nxd5?? ; the Fried Liver
+6. Nxf7 1-0
+
+[Event "Endgame"]
+[SetUp "1"]
+[FEN "k7/8/1QK5/8/8/8/8/8 w - - 0 1"]
+[Result "1-0"]
+
+1.Qb7# 1-0
+"#;
+
+fn scratch_file(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("brainstorm-tests-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name).to_string_lossy().into_owned()
+}
+
+#[test]
+fn pgn_games_yield_every_playable_position() {
+    let positions = pgn_positions(PGN).unwrap();
+    // Twelve positions from the first game and the start of the second, whose final
+    // position is checkmate.
+    assert_eq!(positions.len(), 13);
+    assert_eq!(positions[0].board.fen(), Board::start_pos().fen());
+    assert_eq!(
+        positions[11].board.fen(),
+        "r1bqkb1r/ppp2Npp/2n5/3np3/2B5/8/PPPP1PPP/RNBQK2R b KQkq - 0 6"
+    );
+    assert_eq!(positions[12].board.fen(), "k7/8/1QK5/8/8/8/8/8 w - - 0 1");
+
+    // White won both games, seen from each position's side to move.
+    assert_eq!(positions[0].result, Some(1.0));
+    assert_eq!(positions[11].result, Some(0.0));
+    assert_eq!(positions[12].result, Some(1.0));
+    let results = |pgn: &str| -> Vec<Option<f32>> {
+        pgn_positions(pgn)
+            .unwrap()
+            .iter()
+            .map(|position| position.result)
+            .collect()
+    };
+    // Without a result token the tag decides; with neither the result is unknown.
+    assert_eq!(
+        results("[Result \"1/2-1/2\"]\n\n1. d4 d5"),
+        [Some(0.5), Some(0.5), Some(0.5)]
+    );
+    assert_eq!(
+        results("[Result \"0-1\"]\n\n1. d4 d5\n\n[Result \"*\"]\n\n1. c4"),
+        [Some(0.0), Some(1.0), Some(0.0), None, None]
+    );
+    assert_eq!(results("1. e4 e5 *"), [None, None, None]);
+
+    assert_eq!(pgn_positions("1. e4 e5 2. Ke2").unwrap().len(), 4);
+    assert_eq!(
+        pgn_positions("1. e4 0-0").unwrap_err(),
+        "game 1: `0-0` is not a legal move"
+    );
+    assert!(pgn_positions("[FEN \"not a fen\"]").is_err());
+}
+
+#[test]
+fn epd_inputs_skip_finished_positions() {
+    let path = scratch_file("label-input.epd");
+    std::fs::write(
+        &path,
+        "# positions\nk7/8/1QK5/8/8/8/8/8 w - - bm Qb7#;\nk7/1Q6/2K5/8/8/8/8/8 b - - 0 1\n",
+    )
+    .unwrap();
+    assert_eq!(read_positions(&path).unwrap().len(), 1);
+    std::fs::write(&path, "k7/8/1QK5/8/8/8/8/8 w - -\nnonsense\n").unwrap();
+    assert_eq!(
+        read_positions(&path).unwrap_err(),
+        "line 2: invalid EPD position"
+    );
+}
+
+#[test]
+fn scores_map_to_expected_results() {
+    assert_eq!(wdl_from_score(0), 0.5);
+    assert_eq!(wdl_from_score(400), 0.909);
+    assert_eq!(wdl_from_score(-400), 0.091);
+    assert_eq!(wdl_from_score(30_990), 1.0);
+    assert_eq!(wdl_from_score(-30_990), 0.0);
+}
+
+#[test]
+fn labels_do_not_depend_on_the_thread_count() {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let positions: Vec<LabelPosition> = sample_positions(5, 24)
+        .into_iter()
+        .map(LabelPosition::from)
+        .collect();
+    let label = |threads| {
+        let config = LabelConfig {
+            depth: Some(3),
+            threads,
+            ..LabelConfig::new("in.epd", "out.txt")
+        };
+        let mut records = Vec::new();
+        label_positions(
+            &searcher,
+            &SearchOptions::default(),
+            &positions,
+            &config,
+            |index, record| {
+                assert_eq!(index, records.len());
+                records.push(record);
+                Ok(())
+            },
+        )
+        .unwrap();
+        records
+    };
+    let single = label(1);
+    assert_eq!(single.len(), positions.len());
+    assert_eq!(single, label(4));
+    for (record, position) in single.iter().zip(&positions) {
+        assert_eq!(record.fen, position.board.fen());
+        assert_eq!(record.wdl, wdl_from_score(record.score_cp));
+        assert!(position.board.generate_moves().contains(&record.best_move));
+    }
+
+    let mate = [LabelPosition::from(
+        Board::from_fen("k7/8/1QK5/8/8/8/8/8 w - - 0 1").unwrap(),
+    )];
+    let config = LabelConfig {
+        depth: Some(3),
+        threads: 2,
+        ..LabelConfig::new("in.epd", "out.txt")
+    };
+    let mut records: Vec<TrainingRecord> = Vec::new();
+    label_positions(
+        &searcher,
+        &SearchOptions::default(),
+        &mate,
+        &config,
+        |_, record| {
+            records.push(record);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(records[0].best_move.to_string(), "b6b7");
+    assert_eq!(records[0].wdl, 1.0);
+
+    // A game's result overrides the search score: here White resigned a won position.
+    let resigned = pgn_positions("[FEN \"k7/8/1QK5/8/8/8/8/8 w - - 0 1\"]\n\n0-1").unwrap();
+    records.clear();
+    label_positions(
+        &searcher,
+        &SearchOptions::default(),
+        &resigned,
+        &config,
+        |_, record| {
+            records.push(record);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(records[0].best_move.to_string(), "b6b7");
+    assert_eq!(records[0].wdl, 0.0);
+
+    let failed = label_positions(
+        &searcher,
+        &SearchOptions::default(),
+        &positions,
+        &config,
+        |_, _| Err("disk full".to_string()),
+    );
+    assert_eq!(failed.unwrap_err(), "disk full");
+}
+
+#[test]
+fn node_limited_labels_do_not_depend_on_the_thread_count() {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let positions: Vec<LabelPosition> = sample_positions(7, 24)
+        .into_iter()
+        .map(LabelPosition::from)
+        .collect();
+    let label = |threads| {
+        let config = LabelConfig {
+            nodes: Some(3000),
+            threads,
+            ..LabelConfig::new("in.epd", "out.txt")
+        };
+        let mut records = Vec::new();
+        label_positions(
+            &searcher,
+            &SearchOptions::default(),
+            &positions,
+            &config,
+            |_, record| {
+                records.push(record);
+                Ok(())
+            },
+        )
+        .unwrap();
+        records
+    };
+    // Every worker stops at its own node limit, never at another's.
+    let single = label(1);
+    assert_eq!(single.len(), positions.len());
+    assert_eq!(single, label(4));
+}
+
+#[test]
+fn features_are_written_as_npy() {
+    let positions = sample_positions(3, 5);
+    let path = scratch_file("features.npy");
+    let mut writer = FeatureWriter::create(&path, positions.len()).unwrap();
+    for board in &positions {
+        writer.write(board).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), positions.len());
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    assert_eq!((10 + header_len) % 64, 0);
+    let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+    assert!(header.contains("'descr': '<f4'"));
+    assert!(header.contains("'shape': (5, 775)"));
+    assert!(header.ends_with('\n'));
+    let data = &bytes[10 + header_len..];
+    assert_eq!(data.len(), positions.len() * FEATURE_COLUMNS * 4);
+
+    let mut expected = [0.0f32; FEATURE_COLUMNS];
+    encode_board_features(&positions[4], &mut expected);
+    let row: Vec<f32> = data[4 * FEATURE_COLUMNS * 4..]
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    assert_eq!(row, expected);
+
+    let mut short = FeatureWriter::create(&path, 2).unwrap();
+    short.write(&positions[0]).unwrap();
+    assert!(short.finish().is_err());
+}
+
+#[test]
+fn label_command_requires_an_output() {
+    let parse = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Cli::parse(&args)
+    };
+    let cli = parse(&[
+        "label",
+        "games.pgn",
+        "--output",
+        "labels.bin",
+        "--nodes",
+        "20000",
+        "--threads",
+        "6",
+        "--format",
+        "binary",
+        "--features",
+        "labels.npy",
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.command,
+        Command::Label(LabelConfig {
+            nodes: Some(20_000),
+            threads: 6,
+            format: DataFormat::Binary,
+            features: Some("labels.npy".to_string()),
+            ..LabelConfig::new("games.pgn", "labels.bin")
+        })
+    );
+    assert!(parse(&["label", "games.pgn"]).is_err());
+    assert!(parse(&["label", "--output", "x.txt"]).is_err());
+    assert!(parse(&["label", "a.epd", "--output", "x.txt", "--movetime", "100"]).is_err());
+}
@@ -0,0 +1,419 @@
+use brainstorm::cli::{Cli, Command};
+use brainstorm::label::pgn_positions;
+use brainstorm::match_runner::{
+    go_command, run_match, BuiltinPlayer, EngineKind, EngineSpec, MatchConfig, MatchPlayer,
+    MatchScore, PlayerError, SprtConfig, SprtDecision, TimeControl,
+};
+use brainstorm::selfplay::{GameResult, Termination};
+use brainstorm::time_manager::GoOptions;
+use std::time::{Duration, Instant};
+
+fn scratch_file(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("brainstorm-tests-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name).to_string_lossy().into_owned()
+}
+
+fn classical(name: &str) -> EngineSpec {
+    EngineSpec::parse(&format!("builtin,name={name},Model=classical")).unwrap()
+}
+
+#[test]
+fn specs_and_limits_parse() {
+    assert_eq!(
+        TimeControl::from_str("10+0.1"),
+        Some(TimeControl {
+            base_ms: 10_000,
+            inc_ms: 100
+        })
+    );
+    assert_eq!(TimeControl::from_str("2.5").unwrap().inc_ms, 0);
+    assert_eq!(TimeControl::from_str("10+0.1").unwrap().pgn_tag(), "10+0.1");
+    assert_eq!(TimeControl::from_str("0+1"), None);
+    assert_eq!(TimeControl::from_str("fast"), None);
+
+    assert_eq!(
+        EngineSpec::parse("cmd=/opt/engines/stockfish,Threads=2").unwrap(),
+        EngineSpec {
+            name: "stockfish".to_string(),
+            kind: EngineKind::Uci {
+                command: "/opt/engines/stockfish".to_string()
+            },
+            options: vec![("Threads".to_string(), "2".to_string())],
+        }
+    );
+    let builtin = EngineSpec::parse("builtin, Model=accurate").unwrap();
+    assert_eq!(builtin.name, "brainstorm");
+    assert_eq!(builtin.kind, EngineKind::Builtin);
+    assert!(EngineSpec::parse("name=x").is_err());
+    assert!(EngineSpec::parse("builtin,cmd=sf").is_err());
+    assert!(EngineSpec::parse("builtin,fast").is_err());
+
+    let sprt = SprtConfig::from_str("elo1=10,alpha=0.1").unwrap();
+    assert_eq!(sprt.elo0, 0.0);
+    assert_eq!(sprt.elo1, 10.0);
+    assert_eq!(sprt.alpha, 0.1);
+    assert_eq!(sprt.beta, 0.05);
+    assert!(SprtConfig::from_str("elo0=5,elo1=0").is_err());
+    assert!(SprtConfig::from_str("alpha=0").is_err());
+    assert!(SprtConfig::from_str("gamma=1").is_err());
+
+    let go = GoOptions {
+        wtime_ms: Some(900),
+        btime_ms: Some(1000),
+        winc_ms: Some(10),
+        binc_ms: Some(10),
+        nodes: Some(5000),
+        ..GoOptions::default()
+    };
+    assert_eq!(
+        go_command(&go),
+        "go wtime 900 btime 1000 winc 10 binc 10 nodes 5000"
+    );
+}
+
+#[test]
+fn elo_and_sprt_follow_the_score() {
+    let even = MatchScore {
+        wins: 10,
+        draws: 20,
+        losses: 10,
+    };
+    assert_eq!(even.games(), 40);
+    assert_eq!(even.score(), 0.5);
+    let (elo, margin) = even.elo();
+    assert_eq!(elo, 0.0);
+    assert!(margin > 50.0 && margin < 100.0, "{margin}");
+
+    let ahead = MatchScore {
+        wins: 300,
+        draws: 150,
+        losses: 50,
+    };
+    let (elo, _) = ahead.elo();
+    // A 75% score is worth about 191 Elo.
+    assert!((elo - 190.8).abs() < 0.1, "{elo}");
+
+    let sprt = SprtConfig::default();
+    let (lower, upper) = sprt.bounds();
+    assert!((lower + 2.944).abs() < 1e-3 && (upper - 2.944).abs() < 1e-3);
+    assert!(ahead.llr(&sprt) > upper);
+    assert_eq!(
+        sprt.decision(ahead.llr(&sprt)),
+        Some(SprtDecision::AcceptH1)
+    );
+    let behind = MatchScore {
+        wins: 50,
+        draws: 150,
+        losses: 300,
+    };
+    assert_eq!(
+        sprt.decision(behind.llr(&sprt)),
+        Some(SprtDecision::AcceptH0)
+    );
+    assert_eq!(sprt.decision(even.llr(&sprt)), None);
+    // The same 75% score over a fifth of the games is not yet conclusive.
+    let early = MatchScore {
+        wins: 60,
+        draws: 30,
+        losses: 10,
+    };
+    assert_eq!(sprt.decision(early.llr(&sprt)), None);
+    assert_eq!(MatchScore::default().llr(&sprt), 0.0);
+}
+
+#[test]
+fn builtin_engines_play_mirrored_games() {
+    let openings = scratch_file("match-openings.epd");
+    std::fs::write(
+        &openings,
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2\n\
+         4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\n",
+    )
+    .unwrap();
+    let pgn = scratch_file("match.pgn");
+    let config = MatchConfig {
+        openings: Some(openings),
+        depth: Some(2),
+        max_plies: 40,
+        pgn: Some(pgn.clone()),
+        ..MatchConfig::new(classical("first"), classical("second"))
+    };
+    let mut seen = 0;
+    let report = run_match(&config, |game, score| {
+        seen += 1;
+        assert_eq!(game.round, seen);
+        assert_eq!(score.games() as usize, seen);
+    })
+    .unwrap();
+    assert_eq!(report.games.len(), 4);
+    assert_eq!(report.score.games(), 4);
+    assert_eq!(report.decision, None);
+
+    // Identical deterministic engines repeat each game with colours swapped.
+    for pair in report.games.chunks(2) {
+        assert_eq!(pair[0].white, 0);
+        assert_eq!(pair[1].white, 1);
+        assert_eq!(pair[0].start_fen, pair[1].start_fen);
+        assert_eq!(pair[0].moves, pair[1].moves);
+        assert_eq!(pair[0].result, pair[1].result);
+    }
+    assert_eq!(report.score.wins, report.score.losses);
+
+    let text = std::fs::read_to_string(&pgn).unwrap();
+    assert_eq!(text.matches("[Event ").count(), 4);
+    assert!(text.contains("[White \"second\"]"));
+    assert!(text.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]"));
+    assert!(text.contains("40... "));
+    assert!(!text.contains("[TimeControl"));
+    assert!(text.lines().all(|line| line.len() <= 80));
+    let plies: usize = report.games.iter().map(|game| game.moves.len()).sum();
+    // Every position of every game except the final ones, which may be checkmate or
+    // stalemate.
+    let positions = pgn_positions(&text).unwrap().len();
+    assert!(positions >= plies && positions <= plies + 4, "{positions}");
+}
+
+#[test]
+fn drawn_games_leave_the_sprt_undecided() {
+    let config = MatchConfig {
+        games: Some(50),
+        depth: Some(1),
+        max_plies: 2,
+        sprt: Some(SprtConfig::default()),
+        ..MatchConfig::new(classical("a"), classical("b"))
+    };
+    // Games cut at two plies are all draws, which never move the test, so every game is
+    // played.
+    let report = run_match(&config, |_, _| {}).unwrap();
+    assert_eq!(report.games.len(), 50);
+    assert_eq!(report.score.draws, 50);
+    assert_eq!(report.decision, None);
+}
+
+#[test]
+fn builtin_engines_check_their_options_and_deadline() {
+    let options = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    };
+    assert_eq!(
+        BuiltinPlayer::new(&options(&[("Hash", "lots")])).err(),
+        Some("builtin engine: invalid value `lots` for option `Hash`".to_string())
+    );
+    assert_eq!(
+        BuiltinPlayer::new(&options(&[("Thread", "2")])).err(),
+        Some("builtin engine: unknown option `Thread`".to_string())
+    );
+    assert!(run_match(
+        &MatchConfig::new(
+            EngineSpec::parse("builtin,name=a,Model=quick").unwrap(),
+            classical("b")
+        ),
+        |_, _| {}
+    )
+    .is_err());
+
+    let mut player = BuiltinPlayer::new(&options(&[("Model", "classical")])).unwrap();
+    player.new_game().unwrap();
+    let start_fen = pleco::Board::start_pos().fen();
+    let go = GoOptions {
+        depth: Some(60),
+        ..GoOptions::default()
+    };
+    // A search far longer than its deadline is stopped instead of running on.
+    let started = Instant::now();
+    let reply = player.play(&start_fen, &[], &go, Some(Duration::from_millis(100)));
+    assert_eq!(reply, Err(PlayerError::Timeout));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    // The next move searches normally.
+    let go = GoOptions {
+        depth: Some(2),
+        ..GoOptions::default()
+    };
+    let reply = player
+        .play(
+            &start_fen,
+            &["e2e4".to_string()],
+            &go,
+            Some(Duration::from_secs(30)),
+        )
+        .unwrap();
+    assert!(!reply.uci_move.is_empty());
+}
+
+#[test]
+fn uci_engines_play_through_pipes() {
+    let engine = EngineSpec::parse(&format!(
+        "cmd={},name=external,Model=classical",
+        env!("CARGO_BIN_EXE_brainstorm")
+    ))
+    .unwrap();
+    let config = MatchConfig {
+        games: Some(2),
+        depth: Some(2),
+        max_plies: 30,
+        ..MatchConfig::new(classical("builtin"), engine)
+    };
+    let report = run_match(&config, |_, _| {}).unwrap();
+    assert_eq!(report.games.len(), 2);
+    for game in &report.games {
+        assert!(
+            !matches!(
+                game.termination,
+                Termination::EngineFailure | Termination::IllegalMove | Termination::TimeForfeit
+            ),
+            "{:?}",
+            game.termination
+        );
+    }
+    // Both sides run the same search, so the games mirror each other.
+    assert_eq!(report.games[0].moves, report.games[1].moves);
+}
+
+#[cfg(unix)]
+fn fake_engine(name: &str, on_go: &str) -> EngineSpec {
+    use std::os::unix::fs::PermissionsExt;
+    let path = scratch_file(name);
+    std::fs::write(
+        &path,
+        format!(
+            "#!/bin/sh\nwhile read cmd rest; do\n  case \"$cmd\" in\n    uci) echo uciok ;;\n    \
+             isready) echo readyok ;;\n    go) {on_go} ;;\n    stop) echo bestmove 0000 ;;\n    \
+             quit) exit 0 ;;\n  esac\ndone\n"
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    EngineSpec::parse(&format!("cmd={path}")).unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn misbehaving_engines_lose() {
+    let illegal = fake_engine("illegal-engine", "echo bestmove a1a1");
+    let config = MatchConfig {
+        games: Some(2),
+        depth: Some(1),
+        ..MatchConfig::new(classical("brainstorm"), illegal)
+    };
+    let report = run_match(&config, |_, _| {}).unwrap();
+    assert_eq!(report.score.wins, 2);
+    assert!(report
+        .games
+        .iter()
+        .all(|game| game.termination == Termination::IllegalMove));
+    assert_eq!(report.games[1].result, GameResult::BlackWins);
+    assert!(report.games[1].moves.is_empty());
+
+    let silent = fake_engine("silent-engine", ":");
+    let config = MatchConfig {
+        games: Some(1),
+        time_control: TimeControl::from_str("0.2"),
+        ..MatchConfig::new(silent, classical("brainstorm"))
+    };
+    let report = run_match(&config, |_, _| {}).unwrap();
+    assert_eq!(report.games[0].termination, Termination::TimeForfeit);
+    assert_eq!(report.games[0].result, GameResult::BlackWins);
+    assert_eq!(report.score.losses, 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn match_exit_status_reports_failures_and_rejected_patches() {
+    let brainstorm = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_brainstorm"))
+            .arg("match")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let output = brainstorm(&[
+        "--engine",
+        "cmd=/nonexistent/engine",
+        "--engine",
+        "builtin,Model=classical",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("error: cannot start `/nonexistent/engine`"),
+        "{stderr}"
+    );
+
+    // An engine that only plays illegal moves loses its games as White and draws as
+    // Black, where the one-ply limit ends the game first, so the SPRT rejects it.
+    let EngineKind::Uci { command } = fake_engine("exit-status-engine", "echo bestmove a1a1").kind
+    else {
+        unreachable!();
+    };
+    let output = brainstorm(&[
+        "--engine",
+        &format!("cmd={command}"),
+        "--engine",
+        "builtin,Model=classical",
+        "--depth",
+        "1",
+        "--max-plies",
+        "1",
+        "--games",
+        "200",
+        "--sprt",
+        "elo0=0,elo1=10",
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("H0 was accepted"));
+}
+
+#[test]
+fn match_command_needs_two_named_engines() {
+    let parse = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Cli::parse(&args)
+    };
+    let cli = parse(&[
+        "match",
+        "--engine",
+        "builtin,name=new",
+        "--engine",
+        "cmd=./old-brainstorm",
+        "--openings",
+        "scripts/elo_openings.jsonl",
+        "--tc",
+        "5+0.05",
+        "--pgn",
+        "games.pgn",
+        "--sprt",
+        "elo0=0,elo1=10",
+    ])
+    .unwrap();
+    let Command::Match(config) = cli.command else {
+        panic!("expected a match command");
+    };
+    assert_eq!(config.engines[0].name, "new");
+    assert_eq!(config.engines[1].name, "old-brainstorm");
+    assert_eq!(
+        config.openings.as_deref(),
+        Some("scripts/elo_openings.jsonl")
+    );
+    assert_eq!(config.time_control, TimeControl::from_str("5+0.05"));
+    assert_eq!(config.pgn.as_deref(), Some("games.pgn"));
+    assert_eq!(config.sprt.unwrap().elo1, 10.0);
+
+    assert!(parse(&["match", "--engine", "builtin"]).is_err());
+    assert!(parse(&["match", "--engine", "builtin", "--engine", "builtin"]).is_err());
+    assert!(parse(&[
+        "match",
+        "--engine",
+        "builtin,name=a",
+        "--engine",
+        "builtin,name=b",
+        "--tc",
+        "soon"
+    ])
+    .is_err());
+}
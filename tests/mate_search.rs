@@ -0,0 +1,133 @@
+mod common;
+
+use brainstorm::search_algorithm::{score_to_uci, ModelMode, SearchOptions, SearchRequest};
+use common::searcher;
+use pleco::Board;
+
+struct MateCase {
+    id: &'static str,
+    fen: &'static str,
+    best_move: &'static str,
+    mate_moves: i32,
+    depth: u32,
+}
+
+const MATE_CASES: [MateCase; 5] = [
+    MateCase {
+        id: "M1-W-1",
+        fen: "k7/8/1QK5/8/8/8/8/8 w - - 0 1",
+        best_move: "b6b7",
+        mate_moves: 1,
+        depth: 4,
+    },
+    MateCase {
+        id: "M1-W-2",
+        fen: "7k/5Q2/6K1/8/8/8/8/8 w - - 0 1",
+        best_move: "f7g7",
+        mate_moves: 1,
+        depth: 4,
+    },
+    MateCase {
+        id: "M1-B-1",
+        fen: "8/8/8/8/8/1qk5/8/K7 b - - 0 1",
+        best_move: "b3b2",
+        mate_moves: 1,
+        depth: 4,
+    },
+    MateCase {
+        id: "M1-B-4",
+        fen: "8/8/8/8/8/1k6/2q5/K7 b - - 0 1",
+        best_move: "c2c1",
+        mate_moves: 1,
+        depth: 4,
+    },
+    MateCase {
+        id: "M2-W-1",
+        fen: "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+        best_move: "d5f6",
+        mate_moves: 2,
+        depth: 5,
+    },
+];
+
+fn assert_mates(threads: usize, model_mode: ModelMode) {
+    let searcher = searcher();
+    let options = SearchOptions {
+        hash_mb: 16,
+        threads,
+        model_mode,
+        ..SearchOptions::default()
+    };
+
+    for case in &MATE_CASES {
+        let board = Board::from_fen(case.fen).expect("mate case FEN must be valid");
+        let request = SearchRequest {
+            max_depth: case.depth,
+            ..SearchRequest::default()
+        };
+        let result = searcher.search_quiet(&board, request, &options, &[board.zobrist()]);
+
+        assert_eq!(
+            result.best_move.to_string(),
+            case.best_move,
+            "{} (threads={threads}) picked the wrong move",
+            case.id
+        );
+        assert_eq!(
+            score_to_uci(result.score_cp),
+            ("mate", case.mate_moves),
+            "{} (threads={threads}) reported the wrong mate distance at depth {}",
+            case.id,
+            result.depth
+        );
+    }
+}
+
+#[test]
+fn finds_shortest_mates_single_threaded() {
+    assert_mates(1, ModelMode::Small);
+}
+
+#[test]
+fn finds_shortest_mates_parallel_root() {
+    assert_mates(4, ModelMode::HybridRoot);
+}
+
+#[test]
+fn mate_distance_survives_deeper_iterations() {
+    let searcher = searcher();
+    let options = SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        ..SearchOptions::default()
+    };
+    let board = Board::from_fen(MATE_CASES[0].fen).unwrap();
+
+    // Later iterations reuse TT entries stored at other plies; the reported distance
+    // must stay anchored to the root.
+    for depth in [2, 5, 8] {
+        let request = SearchRequest {
+            max_depth: depth,
+            ..SearchRequest::default()
+        };
+        let result = searcher.search_quiet(&board, request, &options, &[board.zobrist()]);
+        assert_eq!(score_to_uci(result.score_cp), ("mate", 1), "depth {depth}");
+    }
+}
+
+#[test]
+fn mated_side_reports_negative_distance() {
+    let searcher = searcher();
+    let options = SearchOptions {
+        threads: 1,
+        ..SearchOptions::default()
+    };
+    // Ka7 is forced and Qb7 mates.
+    let board = Board::from_fen("k7/8/2K5/8/8/8/8/1Q6 b - - 0 1").unwrap();
+    let request = SearchRequest {
+        max_depth: 4,
+        ..SearchRequest::default()
+    };
+    let result = searcher.search_quiet(&board, request, &options, &[board.zobrist()]);
+    assert_eq!(score_to_uci(result.score_cp), ("mate", -1));
+}
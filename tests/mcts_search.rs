@@ -0,0 +1,82 @@
+mod common;
+
+use brainstorm::search_algorithm::{
+    score_to_uci, SearchAlgorithm, SearchMode, SearchOptions, SearchRequest,
+};
+use common::searcher;
+use pleco::Board;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+fn request(max_depth: u32) -> SearchRequest {
+    SearchRequest {
+        max_depth,
+        ..SearchRequest::default()
+    }
+}
+
+fn mcts_options(threads: usize) -> SearchOptions {
+    SearchOptions {
+        hash_mb: 16,
+        threads,
+        search_mode: SearchMode::Mcts,
+        ..SearchOptions::default()
+    }
+}
+
+#[test]
+fn mcts_finds_mate_in_one() {
+    let searcher = SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)));
+    let board = Board::from_fen("k7/8/1QK5/8/8/8/8/8 w - - 0 1").unwrap();
+    let result = searcher.search_quiet(&board, request(8), &mcts_options(1), &[board.zobrist()]);
+    assert_eq!(result.best_move.to_string(), "b6b7");
+    assert_eq!(score_to_uci(result.score_cp), ("mate", 1));
+}
+
+#[test]
+fn mcts_scores_leaves_with_the_value_network() {
+    let searcher = searcher();
+    let board = Board::start_pos();
+    let result = searcher.search_quiet(&board, request(6), &mcts_options(1), &[board.zobrist()]);
+    assert!(board.generate_moves().contains(&result.best_move));
+    assert_eq!(result.nodes, 16 << 6);
+    assert!(result.depth >= 2);
+    assert!(result.stats.fast_model_evals > 0);
+    assert_eq!(result.stats.accurate_model_evals, 0);
+    // Value-only models search with uniform priors.
+    assert_eq!(result.stats.policy_orderings, 0);
+    assert_eq!(result.stats.reused_playouts, 0);
+}
+
+#[test]
+fn mcts_reuses_the_tree_after_the_played_move() {
+    let searcher = searcher();
+    let options = mcts_options(1);
+    let mut board = Board::start_pos();
+    let mut history = vec![board.zobrist()];
+    let first = searcher.search_quiet(&board, request(6), &options, &history);
+
+    board.apply_move(first.best_move);
+    history.push(board.zobrist());
+    let second = searcher.search_quiet(&board, request(6), &options, &history);
+    assert!(second.stats.reused_playouts > 0);
+    assert!(second.stats.reused_playouts < first.nodes);
+    assert!(board.generate_moves().contains(&second.best_move));
+
+    // A position the tree has never seen starts from scratch.
+    let other = Board::from_fen("k7/8/1QK5/8/8/8/8/8 w - - 0 1").unwrap();
+    let third = searcher.search_quiet(&other, request(4), &options, &[other.zobrist()]);
+    assert_eq!(third.stats.reused_playouts, 0);
+}
+
+#[test]
+fn mcts_workers_share_one_tree() {
+    let searcher = searcher();
+    let board =
+        Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4")
+            .unwrap();
+    let result = searcher.search_quiet(&board, request(7), &mcts_options(4), &[board.zobrist()]);
+    assert!(board.generate_moves().contains(&result.best_move));
+    assert_eq!(result.nodes, 16 << 7);
+    assert_eq!(result.stats.fast_model_evals, result.stats.eval_calls);
+}
@@ -0,0 +1,191 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+const FAST_MODEL_ENV: &str = "BRAINSTORM_FAST_MODEL";
+
+/// The engine binary, without model paths inherited from the environment.
+fn engine() -> Command {
+    let mut engine = Command::new(env!("CARGO_BIN_EXE_brainstorm"));
+    engine
+        .env_remove(FAST_MODEL_ENV)
+        .env_remove("BRAINSTORM_ACCURATE_MODEL");
+    engine
+}
+
+fn run_uci(commands: &[&str]) -> String {
+    run_engine(engine(), commands)
+}
+
+/// Runs `engine` on `commands`, waiting for the search if they start one, and returns
+/// what it printed.
+fn run_engine(mut engine: Command, commands: &[&str]) -> String {
+    let mut child = engine
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    for command in commands {
+        writeln!(stdin, "{command}").unwrap();
+    }
+    let searching = commands.iter().any(|command| command.starts_with("go"));
+    let readies = commands
+        .iter()
+        .filter(|&&command| command == "isready")
+        .count()
+        + 1;
+    writeln!(stdin, "isready").unwrap();
+
+    let mut output = String::new();
+    let mut line = String::new();
+    let (mut ready, mut done) = (0, !searching);
+    while ready < readies || !done {
+        line.clear();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "{output}");
+        ready += usize::from(line.trim() == "readyok");
+        done |= line.starts_with("bestmove");
+        output.push_str(&line);
+    }
+    writeln!(stdin, "quit").unwrap();
+    assert!(child.wait().unwrap().success());
+    output
+}
+
+fn count(output: &str, needle: &str) -> usize {
+    output.lines().filter(|line| line.contains(needle)).count()
+}
+
+#[test]
+fn failed_loads_are_reported_once() {
+    let output = run_uci(&[
+        "setoption name FastModelPath value /nonexistent/fast.pt",
+        "isready",
+        "isready",
+        "position startpos",
+        "go depth 1",
+    ]);
+    assert_eq!(count(&output, "fast model load failed"), 1, "{output}");
+    assert_eq!(count(&output, "falling back to classical evaluation"), 1);
+    assert_eq!(count(&output, "bestmove"), 1);
+
+    // A new path is worth another attempt.
+    let output = run_uci(&[
+        "setoption name FastModelPath value /nonexistent/fast.pt",
+        "isready",
+        "setoption name FastModelPath value /nonexistent/other.pt",
+        "isready",
+    ]);
+    assert_eq!(count(&output, "fast model load failed"), 2, "{output}");
+}
+
+#[test]
+fn a_loaded_network_stands_in_for_one_that_failed() {
+    let output = run_uci(&[
+        "setoption name Model value balanced",
+        "setoption name AccurateModelPath value /nonexistent/accurate.pt",
+        "isready",
+        "position startpos",
+        "go depth 1",
+    ]);
+    assert_eq!(count(&output, "accurate model load failed"), 1, "{output}");
+    assert_eq!(
+        count(&output, "using the fast model in place of the accurate one"),
+        1
+    );
+    assert_eq!(count(&output, "fast_model_metadata"), 1);
+    assert_eq!(count(&output, "falling back to classical evaluation"), 0);
+    assert_eq!(count(&output, "bestmove"), 1);
+}
+
+#[test]
+fn relative_model_paths_fall_back_to_the_executable_directory() {
+    // Nothing under this directory, so `models/` is only found next to the binary.
+    let dir = std::env::temp_dir().join(format!("brainstorm-model-paths-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let in_dir = || {
+        let mut engine = engine();
+        engine.current_dir(&dir);
+        engine
+    };
+
+    let output = run_engine(
+        in_dir(),
+        &[
+            "setoption name FastModelPath value models/brainstorm_fast_eval_jit.pt",
+            "isready",
+        ],
+    );
+    assert_eq!(count(&output, "fast_model_metadata"), 1, "{output}");
+    assert_eq!(count(&output, "load failed"), 0);
+
+    let output = run_engine(
+        in_dir(),
+        &[
+            "setoption name FastModelPath value models/missing.pt",
+            "isready",
+        ],
+    );
+    assert_eq!(count(&output, "from `models/missing.pt`"), 1, "{output}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn the_model_option_beats_the_environment_which_beats_the_default() {
+    let with_env = || {
+        let mut engine = engine();
+        engine.env(FAST_MODEL_ENV, "/nonexistent/env.pt");
+        engine
+    };
+
+    let output = run_engine(with_env(), &["isready"]);
+    assert_eq!(count(&output, "from `/nonexistent/env.pt`"), 1, "{output}");
+
+    let output = run_engine(
+        with_env(),
+        &[
+            "setoption name FastModelPath value /nonexistent/option.pt",
+            "isready",
+        ],
+    );
+    assert_eq!(
+        count(&output, "from `/nonexistent/option.pt`"),
+        1,
+        "{output}"
+    );
+    assert_eq!(count(&output, "/nonexistent/env.pt"), 0);
+
+    // `<default>` clears the option, which leaves the environment variable in charge.
+    let output = run_engine(
+        with_env(),
+        &[
+            "setoption name FastModelPath value /nonexistent/option.pt",
+            "setoption name FastModelPath value <default>",
+        ],
+    );
+    assert_eq!(
+        count(&output, "info string fast_model=/nonexistent/env.pt"),
+        1,
+        "{output}"
+    );
+
+    let default = if cfg!(feature = "embedded-models") {
+        "<embedded>"
+    } else {
+        "models/brainstorm_fast_eval_jit.pt"
+    };
+    let output = run_uci(&[
+        "setoption name FastModelPath value /nonexistent/option.pt",
+        "setoption name FastModelPath value <default>",
+        "isready",
+    ]);
+    assert_eq!(
+        count(&output, &format!("info string fast_model={default}")),
+        1,
+        "{output}"
+    );
+    assert_eq!(count(&output, "fast_model_metadata"), 1);
+    assert_eq!(count(&output, "load failed"), 0);
+}
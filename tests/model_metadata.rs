@@ -0,0 +1,70 @@
+mod common;
+
+use brainstorm::evaluator::{sidecar_path, Evaluator, MetadataSource, ModelMetadata};
+use std::fs;
+use std::path::Path;
+use tch::Device;
+
+#[test]
+fn shipped_models_carry_valid_metadata() {
+    for path in [
+        common::FAST_MODEL_PATH,
+        "models/brainstorm_accurate_eval_jit.pt",
+    ] {
+        let text = fs::read_to_string(sidecar_path(Path::new(path))).unwrap();
+        let metadata = ModelMetadata::from_json(&text).unwrap();
+        let legacy = ModelMetadata::legacy();
+        assert_eq!(metadata.feature_set, legacy.feature_set);
+        assert_eq!(metadata.input_size, legacy.input_size);
+        assert_eq!(metadata.output_scale_cp, legacy.output_scale_cp);
+        metadata.validate().unwrap();
+    }
+}
+
+#[test]
+fn rejects_incompatible_metadata() {
+    let cases = [
+        r#"{"feature_set": 1, "input_size": 768, "output_scale_cp": 2500.0}"#,
+        r#"{"feature_set": 9, "input_size": 775, "output_scale_cp": 2500.0}"#,
+        r#"{"feature_set": 1, "input_size": 775, "output_scale_cp": 0.0}"#,
+        r#"{"format_version": 99, "feature_set": 1, "input_size": 775, "output_scale_cp": 2500.0}"#,
+    ];
+    for text in cases {
+        let metadata = ModelMetadata::from_json(text).unwrap();
+        assert!(metadata.validate().is_err(), "accepted {text}");
+    }
+    assert!(ModelMetadata::from_json(r#"{"feature_set": 1}"#).is_err());
+}
+
+#[test]
+fn loader_reads_and_enforces_sidecar() {
+    let model = common::model_with_sidecar(
+        "mismatched",
+        r#"{"feature_set": 1, "input_size": 512, "output_scale_cp": 2500.0}"#,
+    );
+    let err = Evaluator::load(&model, Device::Cpu)
+        .err()
+        .expect("mismatch must be rejected");
+    assert!(err.contains("input_size"), "{err}");
+
+    let model = common::model_with_sidecar(
+        "rescaled",
+        r#"{"feature_set": 1, "input_size": 775, "output_scale_cp": 600.0, "training_data": "test"}"#,
+    );
+    let evaluator = Evaluator::load(&model, Device::Cpu).unwrap();
+    assert_eq!(evaluator.metadata().output_scale_cp, 600.0);
+    assert_eq!(
+        evaluator.metadata_source(),
+        &MetadataSource::Sidecar(sidecar_path(&model))
+    );
+    evaluator.probe(Device::Cpu).unwrap();
+}
+
+#[cfg(not(feature = "onnx"))]
+#[test]
+fn onnx_models_need_the_onnx_feature() {
+    let err = Evaluator::load(Path::new("models/missing.onnx"), Device::Cpu)
+        .err()
+        .expect("ONNX support is not compiled in");
+    assert!(err.contains("--features onnx"), "{err}");
+}
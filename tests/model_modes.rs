@@ -0,0 +1,60 @@
+mod common;
+
+use brainstorm::search_algorithm::{ModelMode, SearchOptions, SearchRequest, SearchStats};
+use common::searcher;
+use pleco::Board;
+
+const MIDDLEGAME_FEN: &str = "r2q1rk1/pp1b1ppp/2n1pn2/2bp4/2P5/2NP1NP1/PP2PPBP/R1BQ1RK1 b - - 0 8";
+
+fn search_stats(model_mode: ModelMode) -> SearchStats {
+    let searcher = searcher();
+    let options = SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        model_mode,
+        ..SearchOptions::default()
+    };
+    let board = Board::from_fen(MIDDLEGAME_FEN).unwrap();
+    let request = SearchRequest {
+        max_depth: 3,
+        ..SearchRequest::default()
+    };
+    let result = searcher.search_quiet(&board, request, &options, &[board.zobrist()]);
+    assert!(!result.best_move.is_null(), "{model_mode:?} found no move");
+    result.stats
+}
+
+#[test]
+fn single_model_modes_use_one_network() {
+    let fast = search_stats(ModelMode::Small);
+    assert_eq!(fast.fast_model_evals, fast.eval_calls);
+    assert_eq!(fast.accurate_model_evals, 0);
+
+    let accurate = search_stats(ModelMode::Large);
+    assert_eq!(accurate.fast_model_evals, 0);
+    assert_eq!(accurate.accurate_model_evals, accurate.eval_calls);
+}
+
+#[test]
+fn ensemble_consults_both_networks_for_every_leaf() {
+    let stats = search_stats(ModelMode::Ensemble);
+    assert!(stats.eval_calls > 0);
+    assert_eq!(stats.fast_model_evals, stats.eval_calls);
+    assert_eq!(stats.accurate_model_evals, stats.eval_calls);
+}
+
+#[test]
+fn adaptive_escalates_only_some_leaves() {
+    let stats = search_stats(ModelMode::Adaptive);
+    // Each leaf is counted once, for the model whose score it kept.
+    assert_eq!(
+        stats.fast_model_evals + stats.accurate_model_evals,
+        stats.eval_calls
+    );
+    assert!(stats.fast_model_evals > 0);
+    assert!(stats.accurate_model_evals > 0);
+    assert!(
+        stats.accurate_model_evals < stats.eval_calls,
+        "every leaf was escalated: {stats:?}"
+    );
+}
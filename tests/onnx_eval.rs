@@ -0,0 +1,146 @@
+#![cfg(feature = "onnx")]
+
+mod common;
+
+use brainstorm::evaluator::{compare_precision, sidecar_path, Evaluator, Precision};
+use brainstorm::quantized::OutputActivation;
+use common::{sample_positions, FAST_MODEL_PATH};
+use prost::Message;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tch::{CModule, Device, Tensor};
+use tract_onnx::pb;
+
+const ACCURATE_MODEL_PATH: &str = "models/brainstorm_accurate_eval_jit.pt";
+const SAMPLE_POSITIONS: usize = 200;
+const ONNX_OPSET: i64 = 13;
+const FLOAT: i32 = pb::tensor_proto::DataType::Float as i32;
+
+/// Writes the TorchScript MLP as an ONNX graph of `Gemm`/`Relu` nodes, the way
+/// `torch.onnx.export` lays it out, next to a copy of its metadata sidecar.
+fn export_onnx(model_path: &str, name: &str, activation: OutputActivation) -> PathBuf {
+    let module = CModule::load_on_device(model_path, Device::Cpu).unwrap();
+    let mut parameters = module.named_parameters().unwrap();
+    parameters.sort_by_key(|(name, _)| {
+        let index: usize = name.rsplit('.').nth(1).unwrap().parse().unwrap();
+        (index, name.ends_with(".bias"))
+    });
+
+    let mut graph = pb::GraphProto {
+        name: "evaluator".to_string(),
+        input: vec![value_info("features")],
+        ..Default::default()
+    };
+    let mut input = "features".to_string();
+    let layers = parameters.len() / 2;
+    for (layer, pair) in parameters.chunks(2).enumerate() {
+        let (weight, bias) = (format!("weight{layer}"), format!("bias{layer}"));
+        graph.initializer.push(initializer(&weight, &pair[0].1));
+        graph.initializer.push(initializer(&bias, &pair[1].1));
+
+        let output = format!("linear{layer}");
+        graph.node.push(pb::NodeProto {
+            op_type: "Gemm".to_string(),
+            input: vec![input, weight, bias],
+            output: vec![output.clone()],
+            attribute: vec![pb::AttributeProto {
+                name: "transB".to_string(),
+                r#type: pb::attribute_proto::AttributeType::Int as i32,
+                i: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        input = output;
+
+        let activation = match (layer + 1 < layers, activation) {
+            (true, _) => Some("Relu"),
+            (false, OutputActivation::Tanh) => Some("Tanh"),
+            (false, OutputActivation::None) => None,
+        };
+        if let Some(op_type) = activation {
+            let output = format!("{}{layer}", op_type.to_lowercase());
+            graph.node.push(pb::NodeProto {
+                op_type: op_type.to_string(),
+                input: vec![input],
+                output: vec![output.clone()],
+                ..Default::default()
+            });
+            input = output;
+        }
+    }
+    graph.output.push(value_info(&input));
+
+    let model = pb::ModelProto {
+        ir_version: 7,
+        opset_import: vec![pb::OperatorSetIdProto {
+            domain: String::new(),
+            version: ONNX_OPSET,
+        }],
+        graph: Some(graph),
+        ..Default::default()
+    };
+
+    let dir = std::env::temp_dir().join(format!("brainstorm-onnx-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.onnx"));
+    fs::write(&path, model.encode_to_vec()).unwrap();
+    fs::copy(sidecar_path(Path::new(model_path)), sidecar_path(&path)).unwrap();
+    path
+}
+
+fn value_info(name: &str) -> pb::ValueInfoProto {
+    pb::ValueInfoProto {
+        name: name.to_string(),
+        r#type: Some(pb::TypeProto {
+            value: Some(pb::type_proto::Value::TensorType(pb::type_proto::Tensor {
+                elem_type: FLOAT,
+                shape: None,
+            })),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn initializer(name: &str, tensor: &Tensor) -> pb::TensorProto {
+    pb::TensorProto {
+        name: name.to_string(),
+        dims: tensor.size(),
+        data_type: FLOAT,
+        float_data: Vec::<f32>::try_from(&tensor.view([-1])).unwrap(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn onnx_matches_torchscript_scores() {
+    let positions = sample_positions(0x0abc, SAMPLE_POSITIONS);
+    for (path, name) in [(FAST_MODEL_PATH, "fast"), (ACCURATE_MODEL_PATH, "accurate")] {
+        let reference = Evaluator::load(Path::new(path), Device::Cpu).unwrap();
+        let onnx_path = export_onnx(path, name, reference.metadata().output_activation);
+        let onnx = Evaluator::load(&onnx_path, Device::Cpu).unwrap();
+        assert!(onnx.module().is_none());
+        assert_eq!(onnx.metadata(), reference.metadata());
+        onnx.probe(Device::Cpu).unwrap();
+
+        let report = compare_precision(&reference, &onnx, &positions, Device::Cpu);
+        assert_eq!(report.positions, SAMPLE_POSITIONS);
+        assert!(
+            report.max_abs_error_cp <= 1,
+            "{path}: max error {}cp",
+            report.max_abs_error_cp
+        );
+    }
+}
+
+#[test]
+fn onnx_models_only_run_in_f32() {
+    let onnx_path = export_onnx(FAST_MODEL_PATH, "f32_only", OutputActivation::None);
+    let onnx = Evaluator::load(&onnx_path, Device::Cpu).unwrap();
+    assert!(onnx.with_precision(Precision::Int8, Device::Cpu).is_err());
+
+    let onnx = Evaluator::load(&onnx_path, Device::Cpu).unwrap();
+    let onnx = onnx.with_precision(Precision::F32, Device::Cpu).unwrap();
+    assert_eq!(onnx.precision(), Precision::F32);
+}
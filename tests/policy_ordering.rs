@@ -0,0 +1,141 @@
+mod common;
+
+use brainstorm::evaluator::{Evaluator, ModelMetadata, PolicyFormat};
+use brainstorm::features::MAX_INPUT_SIZE;
+use brainstorm::search_algorithm::{SearchOptions, SearchRequest};
+use brainstorm::utils::{order_moves, policy_index, HistoryTable};
+use pleco::{BitMove, Board};
+use std::path::Path;
+use tch::Device;
+
+fn policy_favoring(moves: &[(u8, u8)]) -> Vec<f32> {
+    let mut logits = vec![0.0_f32; PolicyFormat::FromTo.size()];
+    for (rank, &(from, to)) in moves.iter().enumerate() {
+        logits[from as usize * 64 + to as usize] = 10.0 - rank as f32;
+    }
+    logits
+}
+
+#[test]
+fn policy_orders_quiet_moves_after_captures() {
+    // White can capture on d5; among quiet moves the policy prefers g1f3, then b1c3.
+    let board =
+        Board::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2").unwrap();
+    let policy = policy_favoring(&[(6, 21), (1, 18)]);
+    let history: HistoryTable = [[[0; 64]; 64]; 2];
+    let killer = board
+        .generate_moves()
+        .iter()
+        .copied()
+        .find(|mv| mv.to_string() == "a2a3")
+        .unwrap();
+
+    let mut moves = board.generate_moves().to_vec();
+    order_moves(
+        &board,
+        &mut moves,
+        None,
+        [killer, BitMove::null()],
+        &history,
+        board.turn(),
+        Some(&policy),
+    );
+    let ordered: Vec<String> = moves.iter().map(|mv| mv.to_string()).collect();
+    assert_eq!(&ordered[..3], ["e4d5", "g1f3", "b1c3"]);
+
+    // Value-only models keep the killer heuristic.
+    order_moves(
+        &board,
+        &mut moves,
+        None,
+        [killer, BitMove::null()],
+        &history,
+        board.turn(),
+        None,
+    );
+    assert_eq!(moves[0].to_string(), "e4d5");
+    assert_eq!(moves[1], killer);
+}
+
+#[test]
+fn policy_scores_castling_by_the_king_destination() {
+    // Nothing to capture, so the policy alone orders the moves.
+    let board = Board::from_fen("r3k2r/p6p/8/8/8/8/P6P/R3K2R w KQkq - 0 1").unwrap();
+    let castle = |name: &str| {
+        board
+            .generate_moves()
+            .iter()
+            .copied()
+            .find(|mv| mv.to_string() == name)
+            .unwrap()
+    };
+    assert_eq!(policy_index(castle("e1g1")), 4 * 64 + 6);
+    assert_eq!(policy_index(castle("e1c1")), 4 * 64 + 2);
+
+    // The policy prefers long castling, then short; neither points at a rook square.
+    let policy = policy_favoring(&[(4, 2), (4, 6)]);
+    let history: HistoryTable = [[[0; 64]; 64]; 2];
+    let mut moves = board.generate_moves().to_vec();
+    order_moves(
+        &board,
+        &mut moves,
+        None,
+        [BitMove::null(), BitMove::null()],
+        &history,
+        board.turn(),
+        Some(&policy),
+    );
+    let ordered: Vec<String> = moves.iter().map(|mv| mv.to_string()).collect();
+    assert_eq!(&ordered[..2], ["e1c1", "e1g1"]);
+}
+
+#[test]
+fn metadata_declares_policy_head() {
+    let metadata = ModelMetadata::from_json(
+        r#"{"feature_set": 1, "input_size": 775, "output_scale_cp": 2500.0, "policy": "from_to"}"#,
+    )
+    .unwrap();
+    assert_eq!(metadata.policy, Some(PolicyFormat::FromTo));
+    assert!(metadata.to_string().contains("policy=from_to"));
+    assert!(ModelMetadata::from_json(
+        r#"{"feature_set": 1, "input_size": 775, "output_scale_cp": 2500.0, "policy": "uci"}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn policy_head_must_exist_when_declared() {
+    // The shipped networks are value-only, so declaring a policy head must fail the probe.
+    let model = common::model_with_sidecar(
+        "declared_policy",
+        r#"{"feature_set": 1, "input_size": 775, "output_scale_cp": 2500.0, "policy": "from_to"}"#,
+    );
+    let evaluator = Evaluator::load(&model, Device::Cpu).unwrap();
+    assert!(evaluator.has_policy());
+    assert!(evaluator.probe(Device::Cpu).is_err());
+}
+
+#[test]
+fn value_only_models_fall_back_to_heuristics() {
+    let evaluator = Evaluator::load(Path::new(common::FAST_MODEL_PATH), Device::Cpu).unwrap();
+    let mut buffer = [0_f32; MAX_INPUT_SIZE];
+    assert!(!evaluator.has_policy());
+    assert!(evaluator
+        .policy(&Board::start_pos(), &mut buffer, Device::Cpu)
+        .is_none());
+
+    let searcher = common::searcher();
+    let options = SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        ..SearchOptions::default()
+    };
+    let board = Board::start_pos();
+    let request = SearchRequest {
+        max_depth: 4,
+        ..SearchRequest::default()
+    };
+    let result = searcher.search_quiet(&board, request, &options, &[board.zobrist()]);
+    assert!(!result.best_move.is_null());
+    assert_eq!(result.stats.policy_orderings, 0);
+}
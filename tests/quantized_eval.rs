@@ -0,0 +1,41 @@
+mod common;
+
+use brainstorm::evaluator::{compare_precision, Evaluator, Precision};
+use common::{sample_positions, FAST_MODEL_PATH};
+use std::path::Path;
+use tch::Device;
+
+const ACCURATE_MODEL_PATH: &str = "models/brainstorm_accurate_eval_jit.pt";
+const SAMPLE_POSITIONS: usize = 400;
+const MAX_MEAN_ERROR_CP: f64 = 15.0;
+const MAX_ERROR_CP: i32 = 80;
+
+#[test]
+fn int8_tracks_f32_scores() {
+    let positions = sample_positions(0x5eed, SAMPLE_POSITIONS);
+    for path in [FAST_MODEL_PATH, ACCURATE_MODEL_PATH] {
+        let reference = Evaluator::load(Path::new(path), Device::Cpu).unwrap();
+        let quantized = Evaluator::load(Path::new(path), Device::Cpu)
+            .unwrap()
+            .with_precision(Precision::Int8, Device::Cpu)
+            .unwrap();
+        assert_eq!(quantized.precision(), Precision::Int8);
+
+        let report = compare_precision(&reference, &quantized, &positions, Device::Cpu);
+        println!(
+            "{path}: positions={} mean_abs_error_cp={:.2} max_abs_error_cp={}",
+            report.positions, report.mean_abs_error_cp, report.max_abs_error_cp
+        );
+        assert_eq!(report.positions, SAMPLE_POSITIONS);
+        assert!(
+            report.mean_abs_error_cp <= MAX_MEAN_ERROR_CP,
+            "{path}: mean error {:.2}cp",
+            report.mean_abs_error_cp
+        );
+        assert!(
+            report.max_abs_error_cp <= MAX_ERROR_CP,
+            "{path}: max error {}cp",
+            report.max_abs_error_cp
+        );
+    }
+}
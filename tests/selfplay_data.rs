@@ -0,0 +1,216 @@
+mod common;
+
+use brainstorm::cli::{Cli, Command};
+use brainstorm::search_algorithm::{SearchAlgorithm, SearchOptions};
+use brainstorm::selfplay::{
+    parse_openings, play_game, GameResult, SelfPlayConfig, Termination, ADJUDICATION_PLIES,
+};
+use brainstorm::training::{
+    read_training_file, DataFormat, TrainingRecord, TrainingWriter, RECORD_BYTES,
+};
+use common::sample_positions;
+use pleco::{BitMove, Board};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+fn classical() -> SearchAlgorithm {
+    SearchAlgorithm::classical(Arc::new(AtomicBool::new(false)))
+}
+
+fn options() -> SearchOptions {
+    SearchOptions {
+        hash_mb: 16,
+        threads: 1,
+        ..SearchOptions::default()
+    }
+}
+
+fn scratch_file(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("brainstorm-tests-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name).to_string_lossy().into_owned()
+}
+
+#[test]
+fn binary_records_round_trip() {
+    let mut positions = sample_positions(11, 200);
+    positions.push(
+        Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+            .unwrap(),
+    );
+    positions.push(Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 57").unwrap());
+    for (index, board) in positions.iter().enumerate() {
+        let record = TrainingRecord {
+            fen: board.fen(),
+            score_cp: index as i32 * 37 - 3_000,
+            wdl: [0.0, 0.5, 1.0, 0.731][index % 4],
+            best_move: board.generate_moves()[index % board.generate_moves().len()],
+        };
+        let bytes = record.to_bytes().unwrap();
+        assert_eq!(bytes.len(), RECORD_BYTES);
+        assert_eq!(TrainingRecord::from_bytes(&bytes), record);
+        assert_eq!(
+            TrainingRecord::from_line(&record.to_line()).unwrap(),
+            record
+        );
+    }
+
+    let mate = TrainingRecord {
+        fen: Board::start_pos().fen(),
+        score_cp: 99_999,
+        wdl: 1.0,
+        best_move: BitMove::null(),
+    };
+    assert_eq!(
+        TrainingRecord::from_bytes(&mate.to_bytes().unwrap()).score_cp,
+        i32::from(i16::MAX)
+    );
+    assert_eq!(
+        mate.to_line(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 99999 | 1 | 0000"
+    );
+    assert!(TrainingRecord::from_line("8/8/8/8/8/8/8/8 w - - 0 1 | 0 | 1").is_err());
+}
+
+#[test]
+fn games_label_every_searched_position_with_the_result() {
+    let searcher = classical();
+    let config = SelfPlayConfig {
+        depth: Some(2),
+        max_plies: 24,
+        random_plies: 6,
+        seed: 7,
+        ..SelfPlayConfig::default()
+    };
+    let start = config.opening(&[], 0);
+    assert_eq!(start.moves_played(), 6);
+    assert_eq!(config.opening(&[], 0).fen(), start.fen());
+    assert_ne!(config.opening(&[], 1).fen(), start.fen());
+
+    let game = play_game(&searcher, &options(), &config, &start);
+    assert_eq!(game.moves.len(), game.scores.len());
+    let records = game.training_records();
+    assert_eq!(records.len(), game.moves.len());
+    assert_eq!(records[0].fen, start.fen());
+    for record in &records {
+        let white_to_move = record.fen.split_whitespace().nth(1) == Some("w");
+        let expected = match game.result {
+            GameResult::Draw => 0.5,
+            GameResult::WhiteWins if white_to_move => 1.0,
+            GameResult::BlackWins if !white_to_move => 1.0,
+            _ => 0.0,
+        };
+        assert_eq!(record.wdl, expected);
+    }
+
+    for format in [DataFormat::Text, DataFormat::Binary] {
+        let path = scratch_file(&format!("selfplay-{format:?}.data"));
+        let mut writer = TrainingWriter::create(&path, format).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), records.len());
+        assert_eq!(read_training_file(&path, format).unwrap(), records);
+    }
+}
+
+#[test]
+fn lopsided_and_dead_level_games_are_adjudicated() {
+    let searcher = classical();
+    let queen_up = Board::from_fen("4k3/8/8/8/8/8/3QK3/8 w - - 0 1").unwrap();
+    let config = SelfPlayConfig {
+        depth: Some(2),
+        resign_cp: Some(400),
+        ..SelfPlayConfig::default()
+    };
+    let game = play_game(&searcher, &options(), &config, &queen_up);
+    assert_eq!(game.result, GameResult::WhiteWins);
+    assert_eq!(game.termination, Termination::AdjudicatedWin);
+    assert_eq!(game.moves.len(), ADJUDICATION_PLIES);
+
+    let level = Board::from_fen("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 60").unwrap();
+    let config = SelfPlayConfig {
+        depth: Some(2),
+        draw_cp: Some(100),
+        ..SelfPlayConfig::default()
+    };
+    let game = play_game(&searcher, &options(), &config, &level);
+    assert_eq!(game.result, GameResult::Draw);
+    assert_eq!(game.termination, Termination::AdjudicatedDraw);
+    assert_eq!(game.moves.len(), ADJUDICATION_PLIES);
+
+    // Early in the game the same position is played on.
+    let early = Board::from_fen("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 10").unwrap();
+    let config = SelfPlayConfig {
+        max_plies: 12,
+        ..config
+    };
+    let game = play_game(&searcher, &options(), &config, &early);
+    assert_ne!(game.termination, Termination::AdjudicatedDraw);
+}
+
+#[test]
+fn openings_come_from_fen_epd_or_json_lines() {
+    let book = parse_openings(include_str!("../scripts/elo_openings.jsonl")).unwrap();
+    assert_eq!(book.len(), 24);
+    let mixed = parse_openings(
+        "# openings\n\
+         {\"id\": \"e4\", \"fen\": \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1\"}\n\
+         \n\
+         k7/8/1QK5/8/8/8/8/8 w - - bm Qb7#;\n",
+    )
+    .unwrap();
+    assert_eq!(mixed.len(), 2);
+    let config = SelfPlayConfig::default();
+    assert_eq!(config.opening(&mixed, 3).fen(), mixed[1].fen());
+    assert!(parse_openings("{\"id\": \"x\"}").is_err());
+    assert!(parse_openings("not a position").is_err());
+}
+
+#[test]
+fn selfplay_command_takes_data_generation_flags() {
+    let args: Vec<String> = [
+        "selfplay",
+        "--games",
+        "10",
+        "--nodes",
+        "5000",
+        "--book",
+        "scripts/elo_openings.jsonl",
+        "--random-plies",
+        "4",
+        "--seed",
+        "9",
+        "--resign",
+        "800",
+        "--draw",
+        "10",
+        "--output",
+        "games.bin",
+        "--format",
+        "binary",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    assert_eq!(
+        Cli::parse(&args).unwrap().command,
+        Command::SelfPlay(SelfPlayConfig {
+            games: 10,
+            nodes: Some(5000),
+            book: Some("scripts/elo_openings.jsonl".to_string()),
+            random_plies: 4,
+            seed: 9,
+            resign_cp: Some(800),
+            draw_cp: Some(10),
+            output: Some("games.bin".to_string()),
+            format: DataFormat::Binary,
+            ..SelfPlayConfig::default()
+        })
+    );
+    let args: Vec<String> = ["selfplay", "--format", "csv"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    assert!(Cli::parse(&args).is_err());
+}
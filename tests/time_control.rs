@@ -0,0 +1,240 @@
+mod common;
+
+use brainstorm::search_algorithm::{ModelMode, SearchOptions, SearchRequest};
+use brainstorm::time_manager::{plan_search, GoOptions, TimeManager};
+use common::searcher;
+use pleco::{BitMove, Board, Player};
+use std::time::{Duration, Instant};
+
+const MIDDLEGAME_FEN: &str = "r2q1rk1/pp1b1ppp/2n1pn2/2bp4/2P5/2NP1NP1/PP2PPBP/R1BQ1RK1 w - - 0 8";
+const MOVE_OVERHEAD_MS: u64 = 30;
+// Simulated GUI/transport lag charged on top of the hard deadline; it must stay below
+// the configured overhead for the clock guarantee to hold.
+const SIMULATED_LAG_MS: u64 = 10;
+const MAX_PLIES: usize = 24;
+
+#[test]
+fn movetime_respects_budget() {
+    let searcher = searcher();
+    let board = Board::from_fen(MIDDLEGAME_FEN).unwrap();
+    let options = SearchOptions {
+        hash_mb: 64,
+        threads: 4,
+        model_mode: ModelMode::Small,
+        ..SearchOptions::default()
+    };
+
+    for movetime_ms in [100_u64, 500, 2_000] {
+        let go = GoOptions::parse(&format!("go movetime {movetime_ms}"));
+        let plan = plan_search(&go, board.turn(), MOVE_OVERHEAD_MS);
+        assert_eq!(
+            plan.request.soft_time_ms,
+            Some(movetime_ms - MOVE_OVERHEAD_MS)
+        );
+        assert_eq!(plan.request.hard_time_ms, plan.request.soft_time_ms);
+
+        let t0 = Instant::now();
+        let result = searcher.search_quiet(&board, plan.request, &options, &[board.zobrist()]);
+        let elapsed_ms = t0.elapsed().as_millis() as u64;
+
+        // Only catches a search that ignores its deadline; scheduling on a loaded machine
+        // can add far more than the overhead.
+        let tolerance_ms = 500.max(movetime_ms / 2);
+        assert!(
+            elapsed_ms <= movetime_ms + tolerance_ms,
+            "movetime {movetime_ms}ms exceeded: actual={elapsed_ms}ms"
+        );
+        assert!(board.generate_moves().contains(&result.best_move));
+    }
+}
+
+fn clock_request(soft_ms: u64, hard_ms: u64) -> SearchRequest {
+    SearchRequest {
+        max_depth: 64,
+        soft_time_ms: Some(soft_ms),
+        hard_time_ms: Some(hard_ms),
+        ..SearchRequest::default()
+    }
+}
+
+/// A time manager whose search started `elapsed_ms` ago. The budgets in these tests are
+/// hundreds of milliseconds away from every decision boundary, so the few microseconds
+/// the test itself takes cannot change the outcome.
+fn started_ago(elapsed_ms: u64, request: &SearchRequest) -> TimeManager {
+    let start = Instant::now() - Duration::from_millis(elapsed_ms);
+    TimeManager::new(start, request, 30)
+}
+
+#[test]
+fn fixed_budgets_stop_at_the_deadline() {
+    let request = clock_request(1_000, 1_000);
+    assert!(!started_ago(0, &request).should_stop());
+    assert!(started_ago(1_000, &request).should_stop());
+
+    let untimed = SearchRequest {
+        soft_time_ms: None,
+        hard_time_ms: None,
+        ..request
+    };
+    assert!(!started_ago(60_000, &untimed).should_stop());
+}
+
+#[test]
+fn stable_best_moves_shrink_the_budget() {
+    let request = clock_request(1_000, 3_000);
+    let moves = Board::start_pos().generate_moves();
+    let (first, second) = (moves[0], moves[1]);
+
+    // Five iterations of equal size predict a short sixth one, so only the soft budget,
+    // stretched to 1300ms while the best move keeps changing, decides.
+    let mut unstable = started_ago(1_200, &request);
+    for iteration in 0..5_u64 {
+        let best = if iteration % 2 == 0 { first } else { second };
+        unstable.record_iteration(best, 20, (iteration + 1) * 1_000);
+    }
+    assert!(!unstable.should_stop());
+
+    // Four repeats of the same move shrink it to 700ms.
+    let mut stable = started_ago(1_200, &request);
+    for iteration in 0..5_u64 {
+        stable.record_iteration(first, 20, (iteration + 1) * 1_000);
+    }
+    assert!(stable.should_stop());
+
+    let mut forced = TimeManager::new(Instant::now(), &request, 1);
+    forced.record_iteration(first, 0, 1_000);
+    assert!(forced.should_stop());
+}
+
+#[test]
+fn iterations_that_cannot_finish_are_not_started() {
+    // After one 1000-node iteration in 1200ms the next is predicted to take three times
+    // as long, which ends past a 4000ms hard deadline but not past a 6000ms one.
+    let mut tight = started_ago(1_200, &clock_request(2_000, 4_000));
+    tight.record_iteration(BitMove::null(), 0, 1_000);
+    assert!(tight.should_stop());
+
+    let mut roomy = started_ago(1_200, &clock_request(2_000, 6_000));
+    roomy.record_iteration(BitMove::null(), 0, 1_000);
+    assert!(!roomy.should_stop());
+}
+
+#[test]
+fn go_options_parse_clock_fields() {
+    let go = GoOptions::parse("go wtime 61000 btime 59000 winc 1000 binc 900 movestogo 12");
+    assert_eq!(go.wtime_ms, Some(61_000));
+    assert_eq!(go.btime_ms, Some(59_000));
+    assert_eq!(go.winc_ms, Some(1_000));
+    assert_eq!(go.binc_ms, Some(900));
+    assert_eq!(go.movestogo, Some(12));
+    assert!(!go.infinite);
+}
+
+#[test]
+fn deadlines_never_reach_the_clock() {
+    for remaining_ms in [1_u64, 10, 30, 100, 500, 2_000, 60_000] {
+        for increment_ms in [0_u64, 10, 1_000] {
+            let go = GoOptions {
+                wtime_ms: Some(remaining_ms),
+                winc_ms: Some(increment_ms),
+                ..GoOptions::default()
+            };
+            let plan = plan_search(&go, Player::White, MOVE_OVERHEAD_MS);
+            let soft = plan.request.soft_time_ms.unwrap();
+            let hard = plan.request.hard_time_ms.unwrap();
+
+            assert!(soft <= hard, "soft {soft} > hard {hard}");
+            assert!(
+                hard <= remaining_ms.saturating_sub(MOVE_OVERHEAD_MS).max(1),
+                "hard deadline {hard}ms does not leave the overhead on a {remaining_ms}ms clock"
+            );
+        }
+    }
+}
+
+#[test]
+fn low_clock_enters_emergency_mode() {
+    let go = GoOptions::parse("go wtime 400 btime 400");
+    let plan = plan_search(&go, Player::White, MOVE_OVERHEAD_MS);
+    assert!(plan.emergency);
+    assert!(plan.request.max_depth <= 4);
+
+    let go = GoOptions::parse("go wtime 60000 btime 60000");
+    let plan = plan_search(&go, Player::White, MOVE_OVERHEAD_MS);
+    assert!(!plan.emergency);
+}
+
+#[test]
+fn never_flags_under_simulated_clocks() {
+    let searcher = searcher();
+    let time_controls: [(u64, u64, Option<u32>); 4] = [
+        (2_000, 0, None),
+        (1_000, 50, None),
+        (3_000, 0, Some(10)),
+        (400, 20, None),
+    ];
+
+    for (base_ms, increment_ms, movestogo) in time_controls {
+        let mut board = Board::from_fen(MIDDLEGAME_FEN).unwrap();
+        let mut history = vec![board.zobrist()];
+        let mut clocks = [base_ms, base_ms];
+        let mut moves_to_go = [movestogo, movestogo];
+
+        for _ in 0..MAX_PLIES {
+            if board.generate_moves().is_empty() {
+                break;
+            }
+            let side = if board.turn() == Player::White { 0 } else { 1 };
+            let go = GoOptions {
+                wtime_ms: Some(clocks[0]),
+                btime_ms: Some(clocks[1]),
+                winc_ms: Some(increment_ms),
+                binc_ms: Some(increment_ms),
+                movestogo: moves_to_go[side],
+                ..GoOptions::default()
+            };
+            let plan = plan_search(&go, board.turn(), MOVE_OVERHEAD_MS);
+            let options = SearchOptions {
+                hash_mb: 16,
+                threads: 1,
+                model_mode: if plan.emergency {
+                    ModelMode::Small
+                } else {
+                    ModelMode::HybridRoot
+                },
+                ..SearchOptions::default()
+            };
+
+            // Charge the longest the plan allows, so the check does not depend on how fast
+            // this machine happens to be.
+            let allowed_ms = plan.request.hard_time_ms.unwrap() + SIMULATED_LAG_MS;
+            let t0 = Instant::now();
+            let result = searcher.search_quiet(&board, plan.request, &options, &history);
+            let elapsed_ms = t0.elapsed().as_millis() as u64;
+            assert!(
+                elapsed_ms <= allowed_ms + 500,
+                "search ran {elapsed_ms}ms past a {allowed_ms}ms deadline"
+            );
+            let spent_ms = allowed_ms;
+
+            assert!(
+                spent_ms < clocks[side],
+                "flagged at {base_ms}+{increment_ms}: allowed {spent_ms}ms with {}ms left",
+                clocks[side]
+            );
+            clocks[side] = clocks[side] - spent_ms + increment_ms;
+            if let Some(remaining) = moves_to_go[side] {
+                moves_to_go[side] = Some(if remaining <= 1 {
+                    clocks[side] += base_ms;
+                    movestogo.unwrap_or(1)
+                } else {
+                    remaining - 1
+                });
+            }
+
+            assert!(!result.best_move.is_null());
+            board.apply_move(result.best_move);
+            history.push(board.zobrist());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use pleco::Board;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+#[test]
+fn replies_sent_right_after_bestmove_see_the_new_position() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_brainstorm"))
+        .args(["-o", "Model=classical"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (tx, lines) = channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let bestmove = || loop {
+        let line = lines
+            .recv_timeout(Duration::from_secs(30))
+            .expect("the engine stopped answering");
+        if let Some(rest) = line.strip_prefix("bestmove ") {
+            return rest.split_whitespace().next().unwrap().to_string();
+        }
+    };
+
+    // The engine plays both sides, and each `position` goes out as soon as the previous
+    // `bestmove` arrives, so it races the engine's own bookkeeping of that move.
+    let mut board = Board::start_pos();
+    let mut moves: Vec<String> = Vec::new();
+    for _ in 0..40 {
+        if board.generate_moves().is_empty() {
+            break;
+        }
+        let position = if moves.is_empty() {
+            "position startpos".to_string()
+        } else {
+            format!("position startpos moves {}", moves.join(" "))
+        };
+        writeln!(stdin, "{position}\ngo depth 1").unwrap();
+        stdin.flush().unwrap();
+        let reply = bestmove();
+        let mv = board
+            .generate_moves()
+            .iter()
+            .copied()
+            .find(|mv| mv.to_string() == reply)
+            .unwrap_or_else(|| panic!("`{reply}` is not legal in {}", board.fen()));
+        board.apply_move(mv);
+        moves.push(reply);
+    }
+
+    writeln!(stdin, "quit").unwrap();
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn contempt_is_advertised_as_typed_options() {
+    let output = Command::new(env!("CARGO_BIN_EXE_brainstorm"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(b"uci\nquit\n")?;
+            child.wait_with_output()
+        })
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let options: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("option name Contempt"))
+        .collect();
+    assert_eq!(
+        options,
+        [
+            "option name Contempt type spin default 0 min -200 max 200",
+            "option name ContemptAuto type check default false",
+        ]
+    );
+}